@@ -0,0 +1,61 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compiles a brief dictionary, in the text or JSON format, to a binary trie.
+//!
+//! Usage:
+//!
+//! ```text
+//! cargo run --features std --example briefc -- INPUT.briefs OUTPUT.bin
+//! ```
+
+use std::process::ExitCode;
+
+use clawtype_chords::briefs::source;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, output] = &args[..] else {
+        eprintln!("usage: briefc INPUT.briefs OUTPUT.bin");
+        return ExitCode::FAILURE;
+    };
+    let src = match std::fs::read_to_string(input) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{input}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let entries = match source::parse(&src) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{input}:{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let bytes = match source::compile(&entries) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{input}: cannot encode dictionary: {err:?}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = std::fs::write(output, bytes) {
+        eprintln!("{output}: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,71 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compiles a layout file to Rust source or to a binary blob.
+//!
+//! Usage:
+//!
+//! ```text
+//! cargo run --features std --example layoutc -- INPUT.layout rust TypeName > layout.rs
+//! cargo run --features std --example layoutc -- INPUT.layout blob layout.bin
+//! ```
+
+use std::process::ExitCode;
+
+use clawtype_chords::dsl;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, format, arg] = &args[..] else {
+        eprintln!("usage: layoutc INPUT.layout (rust TYPE_NAME | blob OUTPUT.bin)");
+        return ExitCode::FAILURE;
+    };
+    let src = match std::fs::read_to_string(input) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{input}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let layout = match dsl::parse(&src) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{input}:{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match format.as_str() {
+        "rust" => print!("{}", layout.to_rust(arg)),
+        "blob" => {
+            let bytes = match layout.to_blob() {
+                Ok(v) => v,
+                Err(err) => {
+                    eprintln!("{input}: cannot encode blob: {err:?}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            if let Err(err) = std::fs::write(arg, bytes) {
+                eprintln!("{arg}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+        _ => {
+            eprintln!("unknown output format: {format:?}, expected: rust, blob");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,166 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! What chords do on the host, see [`Action`].
+
+use core::ops::BitOr;
+
+use crate::keycodes::{KeyWithFlags, KEY_MASK};
+
+/// An action carried by [`UsbOutcome`](crate::UsbOutcome), for layouts
+/// with [`Lookup::Action`](crate::Lookup::Action) of this type.
+///
+/// Modifier masks, like in [`LayerOutcome::TemporaryPlusMask`](crate::LayerOutcome::TemporaryPlusMask),
+/// are plain [`KeyWithFlags`]. Combined with `|`, they add their flags to
+/// keys and mouse actions; media keys and system controls don't take
+/// modifiers, and are left unchanged.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// A keyboard key with modifier flags, see [`keycodes`](crate::keycodes).
+    Key(KeyWithFlags),
+    /// A mouse action, done with the modifier flags held, e.g. for Ctrl+click.
+    /// Like in [`KeyWithFlags`], the flags are in the high byte.
+    Mouse(MouseAction, KeyWithFlags),
+    /// A media key, as a usage ID on the HID Consumer page, e.g. `0xE9`
+    /// for Volume Increment, see [`keycodes::media`](crate::keycodes::media).
+    Media(u16),
+    /// A system control, as a usage ID on the HID Generic Desktop page,
+    /// e.g. `0x82` for System Sleep, see [`keycodes::system`](crate::keycodes::system).
+    System(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MouseAction {
+    /// Clicked by [`UsbOutcome::KeyHit`](crate::UsbOutcome::KeyHit). Pressed
+    /// by [`UsbOutcome::KeyPress`](crate::UsbOutcome::KeyPress) until
+    /// [`UsbOutcome::KeyRelease`](crate::UsbOutcome::KeyRelease), e.g.
+    /// as an unchorded key.
+    Button(MouseButton),
+    /// Presses the button, and leaves it pressed, e.g. to start a drag.
+    Press(MouseButton),
+    /// Releases the button, e.g. after [`Self::Press`] or [`Self::DragToggle`].
+    Release(MouseButton),
+    /// Presses the button if it's released, or releases it otherwise, e.g.
+    /// to drag without holding a chord.
+    DragToggle(MouseButton),
+    /// Scrolls by the amount, positive being up.
+    Wheel(i8),
+    /// Starts or stops moving the pointer.
+    Pointer(PointerMode),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    /// The button's bit in a HID mouse report.
+    pub const fn mask(self) -> u8 {
+        match self {
+            Self::Left => 0x01,
+            Self::Right => 0x02,
+            Self::Middle => 0x04,
+        }
+    }
+}
+
+/// Whether the pointer is moved, e.g. by a gyroscope.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PointerMode {
+    Toggle,
+    Enable,
+    Disable,
+}
+
+pub const MOUSE_LEFT: Action = Action::Mouse(MouseAction::Button(MouseButton::Left), 0);
+pub const MOUSE_RIGHT: Action = Action::Mouse(MouseAction::Button(MouseButton::Right), 0);
+pub const MOUSE_MIDDLE: Action = Action::Mouse(MouseAction::Button(MouseButton::Middle), 0);
+pub const MOUSE_LEFT_DRAG_TOGGLE: Action = Action::Mouse(MouseAction::DragToggle(MouseButton::Left), 0);
+pub const MOUSE_WHEEL_UP: Action = Action::Mouse(MouseAction::Wheel(10), 0);
+pub const MOUSE_WHEEL_DOWN: Action = Action::Mouse(MouseAction::Wheel(-10), 0);
+pub const MOUSE_POINTER_TOGGLE: Action = Action::Mouse(MouseAction::Pointer(PointerMode::Toggle), 0);
+
+impl Action {
+    /// The keyboard key, if it is one.
+    pub const fn key(self) -> Option<KeyWithFlags> {
+        match self {
+            Self::Key(key) => Some(key),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Self::Key(0)
+    }
+}
+
+impl From<KeyWithFlags> for Action {
+    fn from(key: KeyWithFlags) -> Self {
+        Self::Key(key)
+    }
+}
+
+/// The keyboard key, if the action is one; otherwise, the action is
+/// returned back.
+impl TryFrom<Action> for KeyWithFlags {
+    type Error = Action;
+
+    fn try_from(action: Action) -> Result<Self, Action> {
+        action.key().ok_or(action)
+    }
+}
+
+impl BitOr<KeyWithFlags> for Action {
+    type Output = Self;
+
+    fn bitor(self, mask: KeyWithFlags) -> Self {
+        match self {
+            Self::Key(key) => Self::Key(key | mask),
+            Self::Mouse(m, flags) => Self::Mouse(m, flags | (mask & !KEY_MASK)),
+            Self::Media(_) | Self::System(_) => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keycodes::*;
+
+    #[test]
+    fn masks() {
+        assert_eq!(Action::Key(E) | CTRL_FLAG, Action::Key(E | CTRL_FLAG));
+        assert_eq!(MOUSE_LEFT | CTRL_FLAG | SHIFT_FLAG,
+            Action::Mouse(MouseAction::Button(MouseButton::Left), CTRL_FLAG | SHIFT_FLAG));
+        assert_eq!(MOUSE_WHEEL_UP | (E | CTRL_FLAG), MOUSE_WHEEL_UP | CTRL_FLAG);
+        assert_eq!(Action::Media(0xe9) | CTRL_FLAG, Action::Media(0xe9));
+        assert_eq!(Action::System(system::SLEEP) | SHIFT_FLAG, Action::System(system::SLEEP));
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(KeyWithFlags::try_from(Action::Key(E | SHIFT_FLAG)), Ok(E | SHIFT_FLAG));
+        assert_eq!(KeyWithFlags::try_from(MOUSE_RIGHT | ALT_FLAG), Err(MOUSE_RIGHT | ALT_FLAG));
+        assert_eq!(KeyWithFlags::try_from(Action::Media(0xe9)), Err(Action::Media(0xe9)));
+        assert_eq!(Action::from(E).key(), Some(E));
+        assert_eq!(MOUSE_MIDDLE.key(), None);
+    }
+}
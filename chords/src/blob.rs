@@ -0,0 +1,1104 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compact binary layout format, which can be loaded at runtime (e.g. from
+//! a flash region) instead of compiling a [`Lookup`] impl into the firmware.
+//!
+//! All numbers are little-endian. Version 14 of the format is:
+//!
+//! ```text
+//! blob:     magic "CLWT", version: u8, layer count: u8,
+//!           layer offsets: [u16; layer count], sequences offset: u16
+//!           (offsets from start of blob)
+//! layer:    unchorded_mask: u8, flags: u8 (bit 0: transparent),
+//!           fallback: kind u8, layer: u8, mask: u16,
+//!           fallback count: u8, [layer: u8; fallback count],
+//!           unchorded count: u8, [switch: u8, action; unchorded count],
+//!           chord count: u16, [entry; chord count]
+//! entry:    chord: u8, outcome
+//! outcome:  tag: u8, payload length: u8, payload
+//! action:   kind: u8, arg: u8, value: u16
+//! sequences: count: u16, [chord count: u8, [chord: u8; chord count], outcome; count]
+//! ```
+//!
+//! An [`Action`] is stored as: a key with `value` being the key with
+//! flags; a mouse action with `arg` being its kind, and `value` its
+//! button, wheel amount or pointer mode in the low byte and the modifier
+//! flags in the high byte; a media key with `value` being the usage ID;
+//! or a system control with `arg` being the usage ID.
+//!
+//! [`VERSION`] is bumped on every change of the format, including new
+//! outcome tags and action kinds, and blobs of any other version are
+//! rejected: a blob has to be compiled by the same version of this crate
+//! as the firmware reading it.
+//!
+//! Every read is bounds-checked; malformed data results in `None` (or an
+//! [`Error`] when opening), never in a panic. As outcomes like
+//! [`LayerOutcome::EmitText`] borrow from the blob, lookups are only
+//! available on blobs with a static lifetime.
+
+use core::marker::PhantomData;
+
+use crate::action::{Action, MouseAction, MouseButton, PointerMode};
+use crate::keycodes::KEY_MASK;
+use crate::host_layout::HostLayout;
+use crate::layer_stack::LayerMode;
+use crate::{
+    LayerFallback, LayerInfo, LayerOutcome, Lookup, LookupError, MAX_SEQUENCE_LEN, SequenceMatch, SwitchSet,
+    TapHoldAction, UsbOutcome, unicode,
+};
+
+pub const MAGIC: [u8; 4] = *b"CLWT";
+pub const VERSION: u8 = 14;
+
+const HEADER_LEN: usize = MAGIC.len() + 2;
+const MAX_PAYLOAD: usize = 2 * (1 + ACTION_LEN);
+const ACTION_LEN: usize = 4;
+
+const FLAG_TRANSPARENT: u8 = 0x01;
+const INFO_LEN: usize = 6;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    BadOffset,
+    /// Output buffer is too small, or a layer is too big to be addressed.
+    TooLarge,
+}
+
+/// A validated view over a binary layout.
+#[derive(Copy, Clone, Debug)]
+pub struct Blob<'a> {
+    bytes: &'a [u8],
+    layers: u8,
+}
+
+impl<'a> Blob<'a> {
+    /// Checks the header and the layer offsets table. Contents of the
+    /// layers are checked lazily, during lookups.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let Some(header) = bytes.get(..HEADER_LEN) else {
+            return Err(Error::Truncated);
+        };
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = header[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let blob = Self { bytes, layers: header[MAGIC.len() + 1] };
+        for layer in 0..blob.layers {
+            let offset = blob.layer_offset(layer).ok_or(Error::Truncated)?;
+            if offset >= bytes.len() {
+                return Err(Error::BadOffset);
+            }
+        }
+        let offset = blob.sequences_offset().ok_or(Error::Truncated)?;
+        if offset >= bytes.len() {
+            return Err(Error::BadOffset);
+        }
+        Ok(blob)
+    }
+
+    /// Like [`Self::new`], but without any checks, for a blob already
+    /// checked with it. Reads are still bounds-checked.
+    fn new_unchecked(bytes: &'a [u8]) -> Self {
+        Self { bytes, layers: bytes.get(HEADER_LEN - 1).copied().unwrap_or(0) }
+    }
+
+    pub fn layers(&self) -> u8 {
+        self.layers
+    }
+
+    pub fn info(&self, layer: u8) -> LayerInfo {
+        self.layer(layer)
+            .and_then(|mut r| Some(LayerInfo {
+                unchorded_mask: SwitchSet(r.u8()?),
+                transparent: r.u8()? & FLAG_TRANSPARENT != 0,
+                fallback: decode_fallback(&mut r)?,
+            }))
+            .unwrap_or_default()
+    }
+
+    pub fn fallbacks(&self, layer: u8) -> &'a [u8] {
+        self.layer(layer)
+            .and_then(|mut r| {
+                r.take(INFO_LEN)?;
+                let count = r.u8()?;
+                r.take(count.into())
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn unchorded_key(&self, layer: u8, switch: SwitchSet) -> Option<Action> {
+        let mut r = self.unchorded(layer)?;
+        let count = r.u8()?;
+        for _ in 0..count {
+            let entry_switch = r.u8()?;
+            let action = r.take(ACTION_LEN)?;
+            if entry_switch == switch.0 {
+                return decode_action(&mut Reader { bytes: action, pos: 0 });
+            }
+        }
+        None
+    }
+
+    fn layer_offset(&self, layer: u8) -> Option<usize> {
+        if layer >= self.layers {
+            return None;
+        }
+        let mut r = Reader { bytes: self.bytes, pos: HEADER_LEN + 2 * usize::from(layer) };
+        r.u16().map(usize::from)
+    }
+
+    fn sequences_offset(&self) -> Option<usize> {
+        let mut r = Reader { bytes: self.bytes, pos: HEADER_LEN + 2 * usize::from(self.layers) };
+        r.u16().map(usize::from)
+    }
+
+    fn layer(&self, layer: u8) -> Option<Reader<'a>> {
+        let pos = self.layer_offset(layer)?;
+        Some(Reader { bytes: self.bytes, pos })
+    }
+
+    /// Returns a reader positioned at the unchorded count of the layer.
+    fn unchorded(&self, layer: u8) -> Option<Reader<'a>> {
+        let mut r = self.layer(layer)?;
+        r.take(INFO_LEN)?;
+        let count = r.u8()?;
+        r.take(count.into())?; // fallbacks
+        Some(r)
+    }
+
+    /// Returns a reader positioned at the chord count of the layer.
+    fn chords(&self, layer: u8) -> Option<Reader<'a>> {
+        let mut r = self.unchorded(layer)?;
+        let count = r.u8()?;
+        r.take((1 + ACTION_LEN) * usize::from(count))?;
+        Some(r)
+    }
+}
+
+impl Blob<'static> {
+    pub fn lookup(&self, layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+        let mut r = self.chords(layer)?;
+        let count = r.u16()?;
+        for _ in 0..count {
+            let entry_chord = r.u8()?;
+            let tag = r.u8()?;
+            let len = r.u8()?;
+            let payload = r.take(len.into())?;
+            if entry_chord == chord {
+                return decode_outcome(tag, payload);
+            }
+        }
+        None
+    }
+
+    /// Matches the chords typed after [`LayerOutcome::Leader`] against the
+    /// sequences in the blob, like [`lookup_sequence`](crate::lookup_sequence).
+    pub fn sequence(&self, chords: &[u8]) -> SequenceMatch<Action> {
+        self.match_sequence(chords).unwrap_or(SequenceMatch::None)
+    }
+
+    fn match_sequence(&self, chords: &[u8]) -> Option<SequenceMatch<Action>> {
+        let pos = self.sequences_offset()?;
+        let mut r = Reader { bytes: self.bytes, pos };
+        let count = r.u16()?;
+        let (mut found, mut longer) = (None, false);
+        for _ in 0..count {
+            let len = r.u8()?;
+            let sequence = r.take(len.into())?;
+            let tag = r.u8()?;
+            let len = r.u8()?;
+            let payload = r.take(len.into())?;
+            if sequence == chords && found.is_none() {
+                found = Some(decode_outcome(tag, payload)?);
+            }
+            longer |= sequence.len() > chords.len() && sequence.starts_with(chords);
+        }
+        Some(SequenceMatch::new(found, longer))
+    }
+}
+
+/// Provides a blob with a static lifetime (e.g. placed in a flash region)
+/// for use with [`BlobLookup`].
+pub trait Source {
+    fn bytes() -> &'static [u8];
+}
+
+/// A [`Lookup`] reading layers from a blob provided by `S`. The blob is
+/// not validated on every lookup, as chords are looked up often: call
+/// [`Self::check`] once at startup instead.
+pub struct BlobLookup<S> {
+    _source: PhantomData<S>,
+}
+
+impl<S: Source> BlobLookup<S> {
+    /// Validates the blob, like [`Blob::new`]. If it's not valid, lookups
+    /// find nothing or garbage (but never panic), so the firmware should
+    /// report the error and not use the lookup.
+    pub fn check() -> Result<(), Error> {
+        Blob::new(S::bytes()).map(|_| ())
+    }
+
+    fn blob() -> Blob<'static> {
+        Blob::new_unchecked(S::bytes())
+    }
+}
+
+impl<S: Source> Lookup for BlobLookup<S> {
+    type Action = Action;
+
+    fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Self::Action>> {
+        Self::blob().lookup(layer, chord)
+    }
+
+    fn info(layer: u8) -> LayerInfo {
+        Self::blob().info(layer)
+    }
+
+    fn unchorded_key(layer: u8, switch: SwitchSet) -> Option<Self::Action> {
+        Self::blob().unchorded_key(layer, switch)
+    }
+
+    fn fallbacks(layer: u8) -> &'static [u8] {
+        Self::blob().fallbacks(layer)
+    }
+
+    fn sequence(chords: &[u8]) -> SequenceMatch<Self::Action> {
+        Self::blob().sequence(chords)
+    }
+}
+
+/// Contents of a single layer, for [`write`].
+pub struct LayerSource<'a> {
+    pub info: LayerInfo,
+    pub fallbacks: &'a [u8],
+    pub unchorded: &'a [(u8, Action)],
+    pub chords: &'a [(u8, LayerOutcome<Action>)],
+}
+
+/// Serializes `layers`, and the `sequences` typed after
+/// [`LayerOutcome::Leader`], into `buf`, returning the length of the blob.
+pub fn write(
+    buf: &mut [u8],
+    layers: &[LayerSource],
+    sequences: &[(&[u8], LayerOutcome<Action>)],
+) -> Result<usize, Error> {
+    let count = u8::try_from(layers.len()).map_err(|_| Error::TooLarge)?;
+    let mut w = Writer { buf, pos: 0 };
+    w.bytes(&MAGIC)?;
+    w.u8(VERSION)?;
+    w.u8(count)?;
+    let dir = w.pos;
+    w.pos += 2 * layers.len() + 2;
+    for (i, layer) in layers.iter().enumerate() {
+        let offset = u16::try_from(w.pos).map_err(|_| Error::TooLarge)?;
+        w.put_u16(dir + 2 * i, offset)?;
+
+        w.info(layer.info)?;
+        w.u8(u8::try_from(layer.fallbacks.len()).map_err(|_| Error::TooLarge)?)?;
+        w.bytes(layer.fallbacks)?;
+        w.u8(u8::try_from(layer.unchorded.len()).map_err(|_| Error::TooLarge)?)?;
+        for &(switch, action) in layer.unchorded {
+            w.u8(switch)?;
+            w.action(action)?;
+        }
+        w.u16(u16::try_from(layer.chords.len()).map_err(|_| Error::TooLarge)?)?;
+        for &(chord, outcome) in layer.chords {
+            w.entry(chord, outcome)?;
+        }
+    }
+    let offset = u16::try_from(w.pos).map_err(|_| Error::TooLarge)?;
+    w.put_u16(dir + 2 * layers.len(), offset)?;
+    w.u16(u16::try_from(sequences.len()).map_err(|_| Error::TooLarge)?)?;
+    for &(chords, outcome) in sequences {
+        w.sequence(chords, outcome)?;
+    }
+    Ok(w.pos)
+}
+
+/// Serializes `layers` first layers of `L` into `buf`, by querying it for
+/// every possible chord and unchorded switch, and for sequences of chords
+/// extending any [`SequenceMatch::Prefix`]. Returns the length of the blob.
+pub fn write_lookup<L>(buf: &mut [u8], layers: u8) -> Result<usize, Error>
+where
+    L: Lookup<Action = Action>,
+{
+    let mut w = Writer { buf, pos: 0 };
+    w.bytes(&MAGIC)?;
+    w.u8(VERSION)?;
+    w.u8(layers)?;
+    let dir = w.pos;
+    w.pos += 2 * usize::from(layers) + 2;
+    for layer in 0..layers {
+        let offset = u16::try_from(w.pos).map_err(|_| Error::TooLarge)?;
+        w.put_u16(dir + 2 * usize::from(layer), offset)?;
+
+        w.info(L::info(layer))?;
+        let fallbacks = L::fallbacks(layer);
+        w.u8(u8::try_from(fallbacks.len()).map_err(|_| Error::TooLarge)?)?;
+        w.bytes(fallbacks)?;
+        let count_pos = w.pos;
+        w.u8(0)?;
+        let mut count = 0u8;
+        for bit in 0..8 {
+            let switch = SwitchSet(1 << bit);
+            if let Some(action) = L::unchorded_key(layer, switch) {
+                w.u8(switch.0)?;
+                w.action(action)?;
+                count += 1;
+            }
+        }
+        w.put_u8(count_pos, count)?;
+
+        let count_pos = w.pos;
+        w.u16(0)?;
+        let mut count = 0u16;
+        for chord in 0..=u8::MAX {
+            let Some(outcome) = L::lookup(layer, chord) else {
+                continue;
+            };
+            w.entry(chord, outcome)?;
+            count += 1;
+        }
+        w.put_u16(count_pos, count)?;
+    }
+    let offset = u16::try_from(w.pos).map_err(|_| Error::TooLarge)?;
+    w.put_u16(dir + 2 * usize::from(layers), offset)?;
+    let count_pos = w.pos;
+    w.u16(0)?;
+    let mut count = 0u16;
+    write_sequences::<L>(&mut w, &mut [0; MAX_SEQUENCE_LEN], 0, &mut count)?;
+    w.put_u16(count_pos, count)?;
+    Ok(w.pos)
+}
+
+/// Writes the sequences of `L` extending the first `len` chords of `chords`.
+fn write_sequences<L>(w: &mut Writer, chords: &mut [u8; MAX_SEQUENCE_LEN], len: usize, count: &mut u16) -> Result<(), Error>
+where
+    L: Lookup<Action = Action>,
+{
+    // a chord with no switches is never typed
+    for chord in 1..=u8::MAX {
+        chords[len] = chord;
+        let (found, longer) = match L::sequence(&chords[..=len]) {
+            SequenceMatch::None => continue,
+            SequenceMatch::Prefix => (None, true),
+            SequenceMatch::Complete(outcome) => (Some(outcome), false),
+            SequenceMatch::Ambiguous(outcome) => (Some(outcome), true),
+        };
+        if let Some(outcome) = found {
+            w.sequence(&chords[..=len], outcome)?;
+            *count = count.checked_add(1).ok_or(Error::TooLarge)?;
+        }
+        if longer && len + 1 < MAX_SEQUENCE_LEN {
+            write_sequences::<L>(w, chords, len + 1, count)?;
+        }
+    }
+    Ok(())
+}
+
+mod tag {
+    pub const CLEAR_STATE: u8 = 0;
+    pub const EMIT: u8 = 1;
+    pub const LAYER_SWITCH_AND_EMIT: u8 = 2;
+    pub const TEMPORARY_LAYER_SWITCH: u8 = 3;
+    pub const TOGGLE_PLUS_MASK: u8 = 4;
+    pub const TEMPORARY_PLUS_MASK: u8 = 5;
+    pub const FROM_OTHER_PLUS_MASK: u8 = 6;
+    pub const EMIT_TEXT: u8 = 7;
+    pub const EMIT_UNICODE: u8 = 8;
+    pub const SET_UNICODE_METHOD: u8 = 9;
+    pub const EMIT_CHAR: u8 = 10;
+    pub const SET_HOST_LAYOUT: u8 = 11;
+    pub const TAP_HOLD: u8 = 12;
+    pub const PUSH_LAYER: u8 = 13;
+    pub const CAPS_WORD: u8 = 14;
+    pub const LEADER: u8 = 15;
+    pub const BRIEFS: u8 = 16;
+    pub const UNDO: u8 = 17;
+
+    pub const USB_NOTHING: u8 = 0;
+    pub const USB_KEY_HIT: u8 = 1;
+    pub const USB_KEY_PRESS: u8 = 2;
+    pub const USB_KEY_RELEASE: u8 = 3;
+    pub const USB_ERROR: u8 = 4;
+
+    pub const ERROR_CYCLE: u8 = 0;
+    pub const ERROR_TOO_DEEP: u8 = 1;
+
+    pub const ACTION_KEY: u8 = 0;
+    pub const ACTION_MOUSE: u8 = 1;
+    pub const ACTION_MEDIA: u8 = 2;
+    pub const ACTION_SYSTEM: u8 = 3;
+
+    pub const MOUSE_BUTTON: u8 = 0;
+    pub const MOUSE_PRESS: u8 = 1;
+    pub const MOUSE_RELEASE: u8 = 2;
+    pub const MOUSE_DRAG_TOGGLE: u8 = 3;
+    pub const MOUSE_WHEEL: u8 = 4;
+    pub const MOUSE_POINTER: u8 = 5;
+
+    pub const TAP_HOLD_KEY: u8 = 0;
+    pub const TAP_HOLD_LAYER: u8 = 1;
+
+    pub const LAYER_ONE_SHOT: u8 = 0;
+    pub const LAYER_MOMENTARY: u8 = 1;
+    pub const LAYER_TOGGLE: u8 = 2;
+    pub const LAYER_LOCKED: u8 = 3;
+
+    pub const FALLBACK_NONE: u8 = 0;
+    pub const FALLBACK_TRANSPARENT: u8 = 1;
+    pub const FALLBACK_EMIT_WITH_MASK: u8 = 2;
+    pub const FALLBACK_SWALLOW: u8 = 3;
+}
+
+/// Returns the tag and the payload, which is either written into `buf`,
+/// or borrowed from `outcome`.
+fn encode_outcome<'a>(outcome: &LayerOutcome<Action>, buf: &'a mut [u8; MAX_PAYLOAD]) -> (u8, &'a [u8]) {
+    use LayerOutcome::*;
+    let (tag, len) = match *outcome {
+        ClearState => (tag::CLEAR_STATE, 0),
+        CapsWord => (tag::CAPS_WORD, 0),
+        Leader => (tag::LEADER, 0),
+        Briefs => (tag::BRIEFS, 0),
+        Undo => (tag::UNDO, 0),
+        Emit(usb) => {
+            encode_usb(usb, &mut buf[..1 + ACTION_LEN]);
+            (tag::EMIT, 1 + ACTION_LEN)
+        }
+        LayerSwitchAndEmit { layer, emit } => {
+            buf[0] = layer;
+            encode_usb(emit, &mut buf[1..2 + ACTION_LEN]);
+            (tag::LAYER_SWITCH_AND_EMIT, 2 + ACTION_LEN)
+        }
+        TemporaryLayerSwitch { layer } => {
+            buf[0] = layer;
+            (tag::TEMPORARY_LAYER_SWITCH, 1)
+        }
+        TogglePlusMask { mask } => {
+            buf[..2].copy_from_slice(&mask.to_le_bytes());
+            (tag::TOGGLE_PLUS_MASK, 2)
+        }
+        TemporaryPlusMask { mask } => {
+            buf[..2].copy_from_slice(&mask.to_le_bytes());
+            (tag::TEMPORARY_PLUS_MASK, 2)
+        }
+        FromOtherPlusMask { layer, mask } => {
+            buf[0] = layer;
+            buf[1..3].copy_from_slice(&mask.to_le_bytes());
+            (tag::FROM_OTHER_PLUS_MASK, 3)
+        }
+        EmitText(text) => return (tag::EMIT_TEXT, text.as_bytes()),
+        EmitUnicode(c) => {
+            buf[..4].copy_from_slice(&u32::from(c).to_le_bytes());
+            (tag::EMIT_UNICODE, 4)
+        }
+        SetUnicodeMethod(method) => {
+            buf[0] = match method {
+                unicode::Method::Linux => 0,
+                unicode::Method::WindowsAltNumpad => 1,
+                unicode::Method::MacHexInput => 2,
+            };
+            (tag::SET_UNICODE_METHOD, 1)
+        }
+        EmitChar(c) => {
+            buf[..4].copy_from_slice(&u32::from(c).to_le_bytes());
+            (tag::EMIT_CHAR, 4)
+        }
+        SetHostLayout(layout) => {
+            buf[0] = match layout {
+                HostLayout::Us => 0,
+                HostLayout::Uk => 1,
+                HostLayout::German => 2,
+                HostLayout::French => 3,
+                HostLayout::PolishProgrammer => 4,
+            };
+            (tag::SET_HOST_LAYOUT, 1)
+        }
+        PushLayer { layer, mode } => {
+            buf[0] = layer;
+            buf[1] = match mode {
+                LayerMode::OneShot => tag::LAYER_ONE_SHOT,
+                LayerMode::Momentary => tag::LAYER_MOMENTARY,
+                LayerMode::Toggle => tag::LAYER_TOGGLE,
+                LayerMode::Locked => tag::LAYER_LOCKED,
+            };
+            (tag::PUSH_LAYER, 2)
+        }
+        TapHold { tap, hold } => {
+            let (tap_buf, hold_buf) = buf.split_at_mut(1 + ACTION_LEN);
+            encode_tap_hold(tap, tap_buf);
+            encode_tap_hold(hold, hold_buf);
+            (tag::TAP_HOLD, MAX_PAYLOAD)
+        }
+    };
+    (tag, &buf[..len])
+}
+
+/// Writes the kind and the action (or error) into the `1 + ACTION_LEN`
+/// bytes of `buf`.
+fn encode_usb(usb: UsbOutcome<Action>, buf: &mut [u8]) {
+    use UsbOutcome::*;
+    let (kind, action) = match usb {
+        Nothing => (tag::USB_NOTHING, None),
+        KeyHit(a) => (tag::USB_KEY_HIT, Some(a)),
+        KeyPress(a) => (tag::USB_KEY_PRESS, Some(a)),
+        KeyRelease(a) => (tag::USB_KEY_RELEASE, Some(a)),
+        UsbOutcome::Error(LookupError::Cycle { layer }) => {
+            buf[1..3].copy_from_slice(&[tag::ERROR_CYCLE, layer]);
+            (tag::USB_ERROR, None)
+        }
+        UsbOutcome::Error(LookupError::TooDeep) => {
+            buf[1] = tag::ERROR_TOO_DEEP;
+            (tag::USB_ERROR, None)
+        }
+    };
+    buf[0] = kind;
+    if let Some(action) = action {
+        encode_action(action, &mut buf[1..]);
+    }
+}
+
+fn encode_tap_hold(action: TapHoldAction<Action>, buf: &mut [u8]) {
+    match action {
+        TapHoldAction::Key(a) => {
+            buf[0] = tag::TAP_HOLD_KEY;
+            encode_action(a, &mut buf[1..]);
+        }
+        TapHoldAction::Layer(layer) => {
+            buf[0] = tag::TAP_HOLD_LAYER;
+            buf[1] = layer;
+        }
+    }
+}
+
+/// Writes the action into the `ACTION_LEN` bytes of `buf`.
+fn encode_action(action: Action, buf: &mut [u8]) {
+    let (kind, arg, value) = match action {
+        Action::Key(key) => (tag::ACTION_KEY, 0, key),
+        Action::Mouse(mouse, flags) => {
+            let (arg, param) = match mouse {
+                MouseAction::Button(b) => (tag::MOUSE_BUTTON, encode_button(b)),
+                MouseAction::Press(b) => (tag::MOUSE_PRESS, encode_button(b)),
+                MouseAction::Release(b) => (tag::MOUSE_RELEASE, encode_button(b)),
+                MouseAction::DragToggle(b) => (tag::MOUSE_DRAG_TOGGLE, encode_button(b)),
+                MouseAction::Wheel(amount) => (tag::MOUSE_WHEEL, amount as u8),
+                MouseAction::Pointer(mode) => (tag::MOUSE_POINTER, match mode {
+                    PointerMode::Toggle => 0,
+                    PointerMode::Enable => 1,
+                    PointerMode::Disable => 2,
+                }),
+            };
+            (tag::ACTION_MOUSE, arg, (flags & !KEY_MASK) | u16::from(param))
+        }
+        Action::Media(usage) => (tag::ACTION_MEDIA, 0, usage),
+        Action::System(usage) => (tag::ACTION_SYSTEM, usage, 0),
+    };
+    buf[0] = kind;
+    buf[1] = arg;
+    buf[2..4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn encode_button(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+    }
+}
+
+fn decode_outcome(tag: u8, payload: &'static [u8]) -> Option<LayerOutcome<Action>> {
+    use LayerOutcome::*;
+    let mut r = Reader { bytes: payload, pos: 0 };
+    Some(match tag {
+        tag::CLEAR_STATE => ClearState,
+        tag::CAPS_WORD => CapsWord,
+        tag::LEADER => Leader,
+        tag::BRIEFS => Briefs,
+        tag::UNDO => Undo,
+        tag::EMIT => Emit(decode_usb(&mut r)?),
+        tag::LAYER_SWITCH_AND_EMIT => LayerSwitchAndEmit {
+            layer: r.u8()?,
+            emit: decode_usb(&mut r)?,
+        },
+        tag::TEMPORARY_LAYER_SWITCH => TemporaryLayerSwitch { layer: r.u8()? },
+        tag::TOGGLE_PLUS_MASK => TogglePlusMask { mask: r.u16()? },
+        tag::TEMPORARY_PLUS_MASK => TemporaryPlusMask { mask: r.u16()? },
+        tag::FROM_OTHER_PLUS_MASK => FromOtherPlusMask {
+            layer: r.u8()?,
+            mask: r.u16()?,
+        },
+        tag::EMIT_TEXT => EmitText(core::str::from_utf8(payload).ok()?),
+        tag::EMIT_UNICODE => EmitUnicode(char::from_u32(r.u32()?)?),
+        tag::SET_UNICODE_METHOD => SetUnicodeMethod(match r.u8()? {
+            0 => unicode::Method::Linux,
+            1 => unicode::Method::WindowsAltNumpad,
+            2 => unicode::Method::MacHexInput,
+            _ => return None,
+        }),
+        tag::EMIT_CHAR => EmitChar(char::from_u32(r.u32()?)?),
+        tag::SET_HOST_LAYOUT => SetHostLayout(match r.u8()? {
+            0 => HostLayout::Us,
+            1 => HostLayout::Uk,
+            2 => HostLayout::German,
+            3 => HostLayout::French,
+            4 => HostLayout::PolishProgrammer,
+            _ => return None,
+        }),
+        tag::PUSH_LAYER => PushLayer {
+            layer: r.u8()?,
+            mode: match r.u8()? {
+                tag::LAYER_ONE_SHOT => LayerMode::OneShot,
+                tag::LAYER_MOMENTARY => LayerMode::Momentary,
+                tag::LAYER_TOGGLE => LayerMode::Toggle,
+                tag::LAYER_LOCKED => LayerMode::Locked,
+                _ => return None,
+            },
+        },
+        tag::TAP_HOLD => TapHold {
+            tap: decode_tap_hold(&mut r)?,
+            hold: decode_tap_hold(&mut r)?,
+        },
+        _ => return None,
+    })
+}
+
+fn decode_fallback(r: &mut Reader) -> Option<LayerFallback> {
+    let kind = r.u8()?;
+    let layer = r.u8()?;
+    let mask = r.u16()?;
+    Some(match kind {
+        tag::FALLBACK_NONE => LayerFallback::None,
+        tag::FALLBACK_TRANSPARENT => LayerFallback::Transparent { layer },
+        tag::FALLBACK_EMIT_WITH_MASK => LayerFallback::EmitWithMask { layer, mask },
+        tag::FALLBACK_SWALLOW => LayerFallback::Swallow,
+        _ => return None,
+    })
+}
+
+fn decode_usb(r: &mut Reader) -> Option<UsbOutcome<Action>> {
+    use UsbOutcome::*;
+    let kind = r.u8()?;
+    let action = r.take(ACTION_LEN)?;
+    let decoded = || decode_action(&mut Reader { bytes: action, pos: 0 });
+    Some(match kind {
+        tag::USB_NOTHING => Nothing,
+        tag::USB_KEY_HIT => KeyHit(decoded()?),
+        tag::USB_KEY_PRESS => KeyPress(decoded()?),
+        tag::USB_KEY_RELEASE => KeyRelease(decoded()?),
+        tag::USB_ERROR => UsbOutcome::Error(match action[..2] {
+            [tag::ERROR_CYCLE, layer] => LookupError::Cycle { layer },
+            [tag::ERROR_TOO_DEEP, _] => LookupError::TooDeep,
+            _ => return None,
+        }),
+        _ => return None,
+    })
+}
+
+fn decode_tap_hold(r: &mut Reader) -> Option<TapHoldAction<Action>> {
+    let kind = r.u8()?;
+    let action = r.take(ACTION_LEN)?;
+    Some(match kind {
+        tag::TAP_HOLD_KEY => TapHoldAction::Key(decode_action(&mut Reader { bytes: action, pos: 0 })?),
+        tag::TAP_HOLD_LAYER => TapHoldAction::Layer(action[0]),
+        _ => return None,
+    })
+}
+
+fn decode_action(r: &mut Reader) -> Option<Action> {
+    let kind = r.u8()?;
+    let arg = r.u8()?;
+    let value = r.u16()?;
+    Some(match kind {
+        tag::ACTION_KEY => Action::Key(value),
+        tag::ACTION_MOUSE => {
+            let [param, _] = value.to_le_bytes();
+            let mouse = match arg {
+                tag::MOUSE_BUTTON => MouseAction::Button(decode_button(param)?),
+                tag::MOUSE_PRESS => MouseAction::Press(decode_button(param)?),
+                tag::MOUSE_RELEASE => MouseAction::Release(decode_button(param)?),
+                tag::MOUSE_DRAG_TOGGLE => MouseAction::DragToggle(decode_button(param)?),
+                tag::MOUSE_WHEEL => MouseAction::Wheel(param as i8),
+                tag::MOUSE_POINTER => MouseAction::Pointer(match param {
+                    0 => PointerMode::Toggle,
+                    1 => PointerMode::Enable,
+                    2 => PointerMode::Disable,
+                    _ => return None,
+                }),
+                _ => return None,
+            };
+            Action::Mouse(mouse, value & !KEY_MASK)
+        }
+        tag::ACTION_MEDIA => Action::Media(value),
+        tag::ACTION_SYSTEM => Action::System(arg),
+        _ => return None,
+    })
+}
+
+fn decode_button(param: u8) -> Option<MouseButton> {
+    Some(match param {
+        0 => MouseButton::Left,
+        1 => MouseButton::Right,
+        2 => MouseButton::Middle,
+        _ => return None,
+    })
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let v = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(v)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|v| v[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|v| u16::from_le_bytes([v[0], v[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|v| u32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+    }
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn put_bytes(&mut self, pos: usize, v: &[u8]) -> Result<(), Error> {
+        let end = pos.checked_add(v.len()).ok_or(Error::TooLarge)?;
+        let dst = self.buf.get_mut(pos..end).ok_or(Error::TooLarge)?;
+        dst.copy_from_slice(v);
+        Ok(())
+    }
+
+    fn put_u8(&mut self, pos: usize, v: u8) -> Result<(), Error> {
+        self.put_bytes(pos, &[v])
+    }
+
+    fn put_u16(&mut self, pos: usize, v: u16) -> Result<(), Error> {
+        self.put_bytes(pos, &v.to_le_bytes())
+    }
+
+    fn bytes(&mut self, v: &[u8]) -> Result<(), Error> {
+        self.put_bytes(self.pos, v)?;
+        self.pos += v.len();
+        Ok(())
+    }
+
+    fn u8(&mut self, v: u8) -> Result<(), Error> {
+        self.bytes(&[v])
+    }
+
+    fn info(&mut self, info: LayerInfo) -> Result<(), Error> {
+        self.u8(info.unchorded_mask.0)?;
+        self.u8(if info.transparent { FLAG_TRANSPARENT } else { 0 })?;
+        let (kind, layer, mask) = match info.fallback {
+            LayerFallback::None => (tag::FALLBACK_NONE, 0, 0),
+            LayerFallback::Transparent { layer } => (tag::FALLBACK_TRANSPARENT, layer, 0),
+            LayerFallback::EmitWithMask { layer, mask } => (tag::FALLBACK_EMIT_WITH_MASK, layer, mask),
+            LayerFallback::Swallow => (tag::FALLBACK_SWALLOW, 0, 0),
+        };
+        self.u8(kind)?;
+        self.u8(layer)?;
+        self.u16(mask)
+    }
+
+    fn u16(&mut self, v: u16) -> Result<(), Error> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn action(&mut self, action: Action) -> Result<(), Error> {
+        let mut buf = [0u8; ACTION_LEN];
+        encode_action(action, &mut buf);
+        self.bytes(&buf)
+    }
+
+    fn entry(&mut self, chord: u8, outcome: LayerOutcome<Action>) -> Result<(), Error> {
+        self.u8(chord)?;
+        self.outcome(outcome)
+    }
+
+    fn sequence(&mut self, chords: &[u8], outcome: LayerOutcome<Action>) -> Result<(), Error> {
+        self.u8(u8::try_from(chords.len()).map_err(|_| Error::TooLarge)?)?;
+        self.bytes(chords)?;
+        self.outcome(outcome)
+    }
+
+    fn outcome(&mut self, outcome: LayerOutcome<Action>) -> Result<(), Error> {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let (tag, payload) = encode_outcome(&outcome, &mut buf);
+        self.u8(tag)?;
+        self.u8(u8::try_from(payload.len()).map_err(|_| Error::TooLarge)?)?;
+        self.bytes(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clawtype_macros::chord;
+    use crate::action::{Action::Key, MOUSE_LEFT, MOUSE_WHEEL_DOWN};
+    use crate::keycodes::*;
+    use crate::sample_layers::SampleLayers;
+    use crate::{Engine, UsbOutcome::KeyHit as Hit};
+
+    const SAMPLE_LAYERS: u8 = 4;
+
+    fn sample_blob() -> &'static [u8] {
+        SampleSource::bytes()
+    }
+
+    #[test]
+    fn round_trip_sample_layers() {
+        let blob = Blob::new(sample_blob()).unwrap();
+        assert_eq!(blob.layers(), SAMPLE_LAYERS);
+        for layer in 0..SAMPLE_LAYERS {
+            assert_eq!(blob.info(layer), SampleLayers::info(layer));
+            assert_eq!(blob.fallbacks(layer), SampleLayers::fallbacks(layer));
+            for chord in 0..=u8::MAX {
+                assert_eq!(blob.lookup(layer, chord), SampleLayers::lookup(layer, chord),
+                    "layer={layer} chord={chord:#010b}");
+            }
+            for bit in 0..8 {
+                let switch = SwitchSet(1 << bit);
+                assert_eq!(blob.unchorded_key(layer, switch), SampleLayers::unchorded_key(layer, switch));
+            }
+        }
+        assert_eq!(blob.lookup(SAMPLE_LAYERS, chord!("___^")), None);
+        for first in 0..=u8::MAX {
+            for second in 0..=u8::MAX {
+                let chords = [first, second, chord!("__^^")];
+                for len in 1..=chords.len() {
+                    assert_eq!(blob.sequence(&chords[..len]), SampleLayers::sequence(&chords[..len]),
+                        "chords={:?}", &chords[..len]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_explicit_layers() {
+        let layer0 = [
+            (chord!("___^"), LayerOutcome::Emit(Hit(Key(E)))),
+            (chord!("%%%%"), LayerOutcome::ClearState),
+            (chord!("%%%^"), LayerOutcome::CapsWord),
+            (chord!("%%%v"), LayerOutcome::Leader),
+            (chord!("%%_%"), LayerOutcome::Briefs),
+            (chord!("%%^%"), LayerOutcome::Undo),
+            (chord!("v^_v"), LayerOutcome::LayerSwitchAndEmit { layer: 1, emit: UsbOutcome::Nothing }),
+            (chord!("vvvv"), LayerOutcome::EmitText("zażółć gęślą jaźń")),
+            (chord!("vvv_"), LayerOutcome::EmitUnicode('🦀')),
+            (chord!("vv__"), LayerOutcome::SetUnicodeMethod(unicode::Method::WindowsAltNumpad)),
+            (chord!("v___"), LayerOutcome::EmitChar('€')),
+            (chord!("_v__"), LayerOutcome::SetHostLayout(HostLayout::PolishProgrammer)),
+            (chord!("__v_"), LayerOutcome::TapHold {
+                tap: TapHoldAction::Key(Key(ESC)),
+                hold: TapHoldAction::Layer(3),
+            }),
+            (chord!("__vv"), LayerOutcome::PushLayer { layer: 1, mode: LayerMode::Toggle }),
+            (chord!("__v^"), LayerOutcome::PushLayer { layer: 2, mode: LayerMode::Momentary }),
+            (chord!("__^v"), LayerOutcome::Emit(UsbOutcome::Error(LookupError::Cycle { layer: 7 }))),
+            (chord!("__^^"), LayerOutcome::Emit(UsbOutcome::Error(LookupError::TooDeep))),
+        ];
+        let layer1 = [
+            (chord!("^^__"), LayerOutcome::TogglePlusMask { mask: CTRL_FLAG }),
+            (chord!("^^_^"), LayerOutcome::FromOtherPlusMask { layer: 0, mask: SHIFT_FLAG }),
+            (chord!("^^_v"), LayerOutcome::Emit(Hit(MOUSE_WHEEL_DOWN | CTRL_FLAG))),
+            (chord!("^^v_"), LayerOutcome::Emit(UsbOutcome::KeyPress(
+                Action::Mouse(MouseAction::Press(MouseButton::Middle), GUI_FLAG)))),
+            (chord!("^^vv"), LayerOutcome::Emit(Hit(Action::Mouse(MouseAction::Pointer(PointerMode::Disable), 0)))),
+            (chord!("^^v^"), LayerOutcome::Emit(Hit(Action::Media(0xe9)))),
+            (chord!("^^^^"), LayerOutcome::Emit(UsbOutcome::KeyPress(Action::System(0x82)))),
+            (chord!("^^^_"), LayerOutcome::TapHold {
+                tap: TapHoldAction::Key(Action::Media(0xe2)),
+                hold: TapHoldAction::Key(Key(SHIFT_FLAG)),
+            }),
+        ];
+        let layers = [
+            LayerSource {
+                info: LayerInfo { fallback: LayerFallback::Swallow, ..Default::default() },
+                fallbacks: &[],
+                unchorded: &[],
+                chords: &layer0,
+            },
+            LayerSource {
+                info: LayerInfo {
+                    unchorded_mask: SwitchSet(chord!("___^")),
+                    transparent: true,
+                    fallback: LayerFallback::EmitWithMask { layer: 0, mask: SHIFT_FLAG },
+                },
+                fallbacks: &[2, 0],
+                unchorded: &[(chord!("___^"), MOUSE_LEFT)],
+                chords: &layer1,
+            },
+        ];
+        let sequences: [(&[u8], _); 3] = [
+            (&[chord!("___^")], LayerOutcome::EmitText("e")),
+            (&[chord!("___^"), chord!("___^")], LayerOutcome::Emit(Hit(Key(E | SHIFT_FLAG)))),
+            (&[chord!("___v"), chord!("___v")], LayerOutcome::ClearState),
+        ];
+        let mut buf = vec![0u8; 512];
+        let n = write(&mut buf, &layers, &sequences).unwrap();
+        buf.truncate(n);
+        let blob = Blob::new(buf.leak()).unwrap();
+        for (i, layer) in layers.iter().enumerate() {
+            let i = i as u8;
+            assert_eq!(blob.info(i), layer.info);
+            assert_eq!(blob.fallbacks(i), layer.fallbacks);
+            for &(chord, outcome) in layer.chords {
+                assert_eq!(blob.lookup(i, chord), Some(outcome));
+            }
+            for &(switch, key) in layer.unchorded {
+                assert_eq!(blob.unchorded_key(i, SwitchSet(switch)), Some(key));
+            }
+        }
+        assert_eq!(blob.lookup(0, chord!("___v")), None);
+        let (e, t) = (chord!("___^"), chord!("___v"));
+        assert_eq!(blob.sequence(&[e]), SequenceMatch::Ambiguous(LayerOutcome::EmitText("e")));
+        assert_eq!(blob.sequence(&[e, e]), SequenceMatch::Complete(LayerOutcome::Emit(Hit(Key(E | SHIFT_FLAG)))));
+        assert_eq!(blob.sequence(&[t]), SequenceMatch::Prefix);
+        assert_eq!(blob.sequence(&[t, e]), SequenceMatch::None);
+        assert_eq!(blob.sequence(&[t, t, t]), SequenceMatch::None);
+    }
+
+    fn header(magic: &[u8], version: u8, rest: &[u8]) -> Vec<u8> {
+        [magic, &[version], rest].concat()
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        assert_eq!(Blob::new(b"CLW").unwrap_err(), Error::Truncated);
+        assert_eq!(Blob::new(&header(b"ABCD", VERSION, b"\x00")).unwrap_err(), Error::BadMagic);
+        assert_eq!(Blob::new(&header(&MAGIC, VERSION, b"\x01")).unwrap_err(), Error::Truncated);
+        assert_eq!(Blob::new(&header(&MAGIC, VERSION, b"\x01\xff\x00")).unwrap_err(), Error::BadOffset);
+        assert_eq!(Blob::new(&header(&MAGIC, VERSION, b"\x00")).unwrap_err(), Error::Truncated);
+        assert_eq!(Blob::new(&header(&MAGIC, VERSION, b"\x00\x08\x00")).unwrap_err(), Error::BadOffset);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = sample_blob().to_vec();
+        assert!(Blob::new(&bytes).is_ok());
+        for version in [0, VERSION - 1, VERSION + 1, u8::MAX] {
+            bytes[MAGIC.len()] = version;
+            assert_eq!(Blob::new(&bytes).unwrap_err(), Error::UnsupportedVersion(version));
+        }
+    }
+
+    #[test]
+    fn truncated_blob_does_not_panic() {
+        let full = sample_blob();
+        for n in 0..full.len() {
+            let Ok(blob) = Blob::new(&full[..n]) else {
+                continue;
+            };
+            for layer in 0..=SAMPLE_LAYERS {
+                blob.info(layer);
+                blob.fallbacks(layer);
+                for chord in 0..=u8::MAX {
+                    blob.lookup(layer, chord);
+                }
+                blob.unchorded_key(layer, SwitchSet(chord!("___^")));
+            }
+            blob.sequence(&[chord!("_^_v"), chord!("^___")]);
+        }
+    }
+
+    #[test]
+    fn too_small_buffer() {
+        let mut buf = [0u8; 64];
+        assert_eq!(write_lookup::<SampleLayers>(&mut buf, SAMPLE_LAYERS), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn too_long_text() {
+        const LONG: &str = match core::str::from_utf8(&[b'x'; 256]) {
+            Ok(v) => v,
+            Err(_) => panic!(),
+        };
+        let chords = [(chord!("___^"), LayerOutcome::EmitText(LONG))];
+        let layers = [LayerSource { info: LayerInfo::default(), fallbacks: &[], unchorded: &[], chords: &chords }];
+        let mut buf = [0u8; 1024];
+        assert_eq!(write(&mut buf, &layers, &[]), Err(Error::TooLarge));
+        let chords = [(chord!("___^"), LayerOutcome::EmitText(&LONG[1..]))];
+        let layers = [LayerSource { info: LayerInfo::default(), fallbacks: &[], unchorded: &[], chords: &chords }];
+        assert!(write(&mut buf, &layers, &[]).is_ok());
+    }
+
+    struct SampleSource;
+
+    impl Source for SampleSource {
+        fn bytes() -> &'static [u8] {
+            static BLOB: std::sync::OnceLock<Vec<u8>> = std::sync::OnceLock::new();
+            BLOB.get_or_init(|| {
+                let mut buf = vec![0u8; 2048];
+                let n = write_lookup::<SampleLayers>(&mut buf, SAMPLE_LAYERS).unwrap();
+                buf.truncate(n);
+                buf
+            })
+        }
+    }
+
+    struct BrokenSource;
+
+    impl Source for BrokenSource {
+        fn bytes() -> &'static [u8] {
+            // 3 layers, but no offsets
+            static BYTES: [u8; HEADER_LEN + 1] = [MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], VERSION, 3, 0];
+            &BYTES
+        }
+    }
+
+    #[test]
+    fn engine_with_blob_lookup() {
+        assert_eq!(BlobLookup::<SampleSource>::check(), Ok(()));
+        assert_eq!(BlobLookup::<BrokenSource>::check(), Err(Error::Truncated));
+        // lookups don't panic on the broken blob
+        assert_eq!(BlobLookup::<BrokenSource>::lookup(1, chord!("___^")), None);
+        assert_eq!(BlobLookup::<BrokenSource>::info(2), LayerInfo::default());
+
+        let mut eng = Engine::<BlobLookup<SampleSource>>::default();
+        assert_eq!(eng.handle(SwitchSet(chord!("_vv_"))), UsbOutcome::Nothing); // "shift"
+        assert_eq!(eng.handle(SwitchSet(0)), UsbOutcome::Nothing);
+        assert_eq!(eng.handle(SwitchSet(chord!("___^"))), UsbOutcome::Nothing);
+        assert_eq!(eng.handle(SwitchSet(0)), Hit(Key(E | SHIFT_FLAG)));
+        // leader, G, S
+        for chord in [chord!("%%%v"), chord!("_^_v")] {
+            assert_eq!(eng.handle(SwitchSet(chord)), UsbOutcome::Nothing);
+            assert_eq!(eng.handle(SwitchSet(0)), UsbOutcome::Nothing);
+        }
+        assert_eq!(eng.handle(SwitchSet(chord!("^___"))), UsbOutcome::Nothing);
+        assert_eq!(eng.handle(SwitchSet(0)), Hit(Key(G)));
+    }
+}
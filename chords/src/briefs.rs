@@ -0,0 +1,1021 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Brief dictionaries: whole words typed with one or a few consecutive
+//! chords, with automatic spacing, suffixes and capitalization, similar to
+//! Plover's dictionaries for stenography.
+//!
+//! Words are separated by spaces automatically. Translations can contain
+//! commands in braces:
+//!
+//! ```text
+//! {^}           the next word is attached, without a space
+//! {^ing}        a suffix, attached to the previous word, with simple English
+//!               orthography rules: make {^ing} = making, carry {^s} = carries
+//! {re^}         a prefix, attached to the next word
+//! {^-^}         an infix, attached on both sides
+//! {&a}          attached to adjacent glued translations, e.g. for fingerspelling
+//! {.} {?} {!}   attached punctuation, capitalizing the next word
+//! {,} {:} {;}   attached punctuation
+//! {-|}          capitalize the next word
+//! {<}           uppercase the next word
+//! {>}           lowercase the first letter of the next word
+//! ```
+//!
+//! When a chord completes an entry together with the chords before it, the
+//! translations of those chords are undone with backspaces, and replaced.
+//!
+//! Dictionaries are compiled on the host by [`source::compile`], to a trie
+//! in a compact binary format. All numbers are little-endian:
+//!
+//! ```text
+//! dictionary: magic "CLWB", version: u8, root node
+//! node:       translation length: u8, translation (UTF-8, empty if none),
+//!             child count: u8, [chord: u8, offset: u16; child count]
+//!             (offsets from start of the dictionary)
+//! ```
+
+use crate::blob::{Error, Reader};
+
+pub const MAGIC: [u8; 4] = *b"CLWB";
+pub const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// Most chords in a single dictionary entry.
+pub const MAX_BRIEF_LEN: usize = 4;
+
+/// A validated view over a compiled dictionary.
+#[derive(Copy, Clone, Debug)]
+pub struct Dictionary<'a> {
+    bytes: &'a [u8],
+}
+
+impl Dictionary<'static> {
+    /// A dictionary without any entries.
+    pub const EMPTY: Self = Self { bytes: &[MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], VERSION, 0, 0] };
+}
+
+impl<'a> Dictionary<'a> {
+    /// Checks the header. Nodes are checked lazily, during lookups.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let Some(header) = bytes.get(..HEADER_LEN) else {
+            return Err(Error::Truncated);
+        };
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = header[MAGIC.len()];
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        Ok(Self { bytes })
+    }
+
+    pub fn root(&self) -> Node<'a> {
+        Node { bytes: self.bytes, pos: HEADER_LEN }
+    }
+
+    /// The node reached by the chords, if any entry starts with them.
+    pub fn get(&self, chords: &[u8]) -> Option<Node<'a>> {
+        chords.iter().try_fold(self.root(), |node, &chord| node.child(chord))
+    }
+}
+
+/// A node of the trie, reached by a sequence of chords.
+#[derive(Copy, Clone, Debug)]
+pub struct Node<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Node<'a> {
+    /// The translation, if the chords leading to the node are a whole entry.
+    pub fn translation(&self) -> Option<&'a str> {
+        let mut r = Reader { bytes: self.bytes, pos: self.pos };
+        let len = r.u8()?;
+        if len == 0 {
+            return None;
+        }
+        core::str::from_utf8(r.take(len.into())?).ok()
+    }
+
+    pub fn child(&self, chord: u8) -> Option<Node<'a>> {
+        let mut r = self.children()?;
+        let count = r.u8()?;
+        for _ in 0..count {
+            let (entry_chord, offset) = (r.u8()?, r.u16()?);
+            if entry_chord == chord {
+                return Some(Node { bytes: self.bytes, pos: offset.into() });
+            }
+        }
+        None
+    }
+
+    /// Whether longer entries start with the chords leading to the node.
+    pub fn has_children(&self) -> bool {
+        self.children().and_then(|mut r| r.u8()).is_some_and(|count| count > 0)
+    }
+
+    /// Returns a reader positioned at the child count.
+    fn children(&self) -> Option<Reader<'a>> {
+        let mut r = Reader { bytes: self.bytes, pos: self.pos };
+        let len = r.u8()?;
+        r.take(len.into())?;
+        Some(r)
+    }
+}
+
+/// A single change of the typed text.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edit {
+    Backspace,
+    Char(char),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum Case {
+    #[default]
+    Normal,
+    Capitalize,
+    Upper,
+    Lower,
+}
+
+/// How many last characters of a word are remembered, for orthography.
+const WORD_LEN: usize = 16;
+
+/// Formatting state between translations.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Format {
+    /// The next word is attached to the previous text, without a space.
+    attach: bool,
+    /// The previous translation was glued, with `{&...}`.
+    glue: bool,
+    case: Case,
+    /// Last characters of the current word, with 0 for non-ASCII ones.
+    word: [u8; WORD_LEN],
+    word_len: u8,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        // at the start, there's nothing to separate the first word from
+        Self { attach: true, glue: false, case: Case::Normal, word: [0; WORD_LEN], word_len: 0 }
+    }
+}
+
+impl Format {
+    fn word(&self) -> &[u8] {
+        &self.word[..usize::from(self.word_len)]
+    }
+
+    fn track(&mut self, edit: Edit) {
+        match edit {
+            Edit::Backspace => self.word_len = self.word_len.saturating_sub(1),
+            Edit::Char(c) if c.is_whitespace() => self.word_len = 0,
+            Edit::Char(c) => {
+                if usize::from(self.word_len) == WORD_LEN {
+                    self.word.copy_within(1.., 0);
+                    self.word_len -= 1;
+                }
+                self.word[usize::from(self.word_len)] = if c.is_ascii() { c as u8 } else { 0 };
+                self.word_len += 1;
+            }
+        }
+    }
+}
+
+const MAX_QUEUE: usize = 3;
+
+/// Edits typing a translation, after undoing any translations it replaces.
+#[derive(Copy, Clone, Debug)]
+pub struct Output<'a> {
+    backspaces: u16,
+    /// A character erased by orthography of an undone translation.
+    restore: Option<char>,
+    format: Format,
+    /// Rest of the translation, not formatted yet.
+    rest: &'a str,
+    /// Text being typed, and its case.
+    text: &'a str,
+    case: Case,
+    /// Edits to be typed before the text.
+    queue: [Edit; MAX_QUEUE],
+    len: u8,
+    pos: u8,
+    /// How many recent translations are replaced.
+    replaced: u8,
+}
+
+impl Default for Output<'_> {
+    fn default() -> Self {
+        Self::new("", Format::default())
+    }
+}
+
+impl<'a> Output<'a> {
+    fn new(translation: &'a str, format: Format) -> Self {
+        Self {
+            backspaces: 0,
+            restore: None,
+            format,
+            rest: translation,
+            text: "",
+            case: Case::Normal,
+            queue: [Edit::Backspace; MAX_QUEUE],
+            len: 0,
+            pos: 0,
+            replaced: 0,
+        }
+    }
+
+    /// How many recent translations, typed by earlier outputs, are replaced
+    /// by this one.
+    pub fn replaced(&self) -> usize {
+        usize::from(self.replaced)
+    }
+
+    /// Erases more characters, before any other edits.
+    pub(crate) fn erase_more(&mut self, chars: u16) {
+        self.backspaces = self.backspaces.saturating_add(chars);
+    }
+
+    fn push(&mut self, edit: Edit) {
+        self.queue[usize::from(self.len)] = edit;
+        self.len += 1;
+    }
+
+    /// Formats the next part of the translation: a piece of text, or a
+    /// command in braces.
+    fn next_part(&mut self) {
+        (self.len, self.pos) = (0, 0);
+        let (part, rest) = match self.rest.strip_prefix('{').and_then(|s| s.split_once('}')) {
+            Some((command, rest)) => (Some(command), rest),
+            None => {
+                let end = self.rest[1..].find('{').map_or(self.rest.len(), |i| i + 1);
+                self.text_part(&self.rest[..end]);
+                (None, &self.rest[end..])
+            }
+        };
+        self.rest = rest;
+        let Some(command) = part else {
+            return;
+        };
+        match command {
+            "^" => self.format.attach = true,
+            "-|" => self.format.case = Case::Capitalize,
+            "<" => self.format.case = Case::Upper,
+            ">" => self.format.case = Case::Lower,
+            "." | "?" | "!" => {
+                self.attached(command);
+                self.format.case = Case::Capitalize;
+            }
+            "," | ":" | ";" => self.attached(command),
+            _ => {
+                if let Some(text) = command.strip_prefix('&') {
+                    let glue = self.format.glue;
+                    self.format.attach |= glue;
+                    self.text_part(text);
+                    self.format.glue = true;
+                } else if let Some(text) = command.strip_prefix('^') {
+                    match text.strip_suffix('^') {
+                        Some(infix) => {
+                            self.attached(infix);
+                            self.format.attach = true;
+                        }
+                        None => {
+                            self.orthography(text);
+                            self.attached(text);
+                        }
+                    }
+                } else if let Some(prefix) = command.strip_suffix('^') {
+                    self.text_part(prefix);
+                    self.format.attach = true;
+                } else {
+                    self.text_part(command);
+                }
+            }
+        }
+    }
+
+    /// A word, or words, separated from the previous text.
+    fn text_part(&mut self, text: &'a str) {
+        if !self.format.attach {
+            self.push(Edit::Char(' '));
+        }
+        self.text = text;
+        self.case = core::mem::take(&mut self.format.case);
+        self.format.attach = false;
+        self.format.glue = false;
+    }
+
+    fn attached(&mut self, text: &'a str) {
+        self.text = text;
+        self.case = Case::Normal;
+        self.format.attach = false;
+        self.format.glue = false;
+    }
+
+    /// Queues edits joining the suffix to the previous word.
+    fn orthography(&mut self, suffix: &str) {
+        let word = self.format.word();
+        let [.., prev, last] = *word else {
+            return;
+        };
+        let (prev, last) = (prev.to_ascii_lowercase(), last.to_ascii_lowercase());
+        let first = suffix.bytes().next().unwrap_or_default().to_ascii_lowercase();
+        if last == b'y' && is_consonant(prev) && first.is_ascii_alphabetic() && first != b'i' {
+            // carry: carried, carries
+            self.push(Edit::Backspace);
+            self.push(Edit::Char('i'));
+            if suffix == "s" {
+                self.push(Edit::Char('e'));
+            }
+        } else if suffix == "s" && (matches!(last, b's' | b'x' | b'z') || (last == b'h' && matches!(prev, b'c' | b's'))) {
+            // box: boxes
+            self.push(Edit::Char('e'));
+        } else if is_vowel(first) && last == b'e' && is_consonant(prev) {
+            // make: making
+            self.push(Edit::Backspace);
+        } else if is_vowel(first) && doubles_last(word) {
+            // stop: stopped
+            self.push(Edit::Char(char::from(last)));
+        }
+    }
+}
+
+impl Output<'static> {
+    /// Erases the characters, then types `restore`, if any.
+    pub fn erase(chars: u16, restore: Option<char>) -> Self {
+        Self { backspaces: chars, restore, ..Self::default() }
+    }
+}
+
+impl Iterator for Output<'_> {
+    type Item = Edit;
+
+    fn next(&mut self) -> Option<Edit> {
+        if self.backspaces > 0 {
+            self.backspaces -= 1;
+            return Some(Edit::Backspace);
+        }
+        if let Some(c) = self.restore.take() {
+            return Some(Edit::Char(c));
+        }
+        loop {
+            if self.pos < self.len {
+                let edit = self.queue[usize::from(self.pos)];
+                self.pos += 1;
+                self.format.track(edit);
+                return Some(edit);
+            }
+            let mut chars = self.text.chars();
+            if let Some(c) = chars.next() {
+                self.text = chars.as_str();
+                let c = match self.case {
+                    Case::Normal => c,
+                    Case::Capitalize => {
+                        self.case = Case::Normal;
+                        c.to_uppercase().next().unwrap_or(c)
+                    }
+                    Case::Upper => c.to_uppercase().next().unwrap_or(c),
+                    Case::Lower => {
+                        self.case = Case::Normal;
+                        c.to_lowercase().next().unwrap_or(c)
+                    }
+                };
+                self.format.track(Edit::Char(c));
+                return Some(Edit::Char(c));
+            }
+            if self.rest.is_empty() {
+                return None;
+            }
+            self.next_part();
+        }
+    }
+}
+
+fn is_vowel(b: u8) -> bool {
+    matches!(b.to_ascii_lowercase(), b'a' | b'e' | b'i' | b'o' | b'u')
+}
+
+fn is_consonant(b: u8) -> bool {
+    b.is_ascii_alphabetic() && !is_vowel(b)
+}
+
+/// Whether a short word ends with a single vowel and a consonant, which is
+/// then doubled before a suffix, like in: run, running.
+fn doubles_last(word: &[u8]) -> bool {
+    let [.., prev, last] = *word else {
+        return false;
+    };
+    let before = word.len().checked_sub(3).map(|i| word[i]);
+    is_consonant(last) && !matches!(last.to_ascii_lowercase(), b'w' | b'x' | b'y')
+        && is_vowel(prev)
+        && before.is_none_or(is_consonant)
+        && word.iter().filter(|&&b| is_vowel(b)).count() == 1
+}
+
+/// A translation already typed.
+#[derive(Copy, Clone, Debug, Default)]
+struct Translated {
+    chords: [u8; MAX_BRIEF_LEN],
+    len: u8,
+    /// Characters typed, and erased from the previous word.
+    typed: u16,
+    erased: u16,
+    before: Format,
+}
+
+impl Translated {
+    fn chords(&self) -> &[u8] {
+        &self.chords[..usize::from(self.len)]
+    }
+
+    /// The character erased by orthography from the previous word, if any.
+    fn erased_char(&self) -> Option<char> {
+        if self.erased == 0 {
+            return None;
+        }
+        self.before.word().last().filter(|&&b| b != 0).map(|&b| char::from(b))
+    }
+}
+
+/// Translates consecutive chords with a [`Dictionary`], keeping track of the
+/// recent translations and the formatting state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Translator {
+    history: [Translated; MAX_BRIEF_LEN],
+    len: u8,
+    format: Format,
+}
+
+impl Translator {
+    /// Translates the chord, preferring the longest entry ending with it that
+    /// replaces whole recent translations. A chord only starting longer
+    /// entries results in an empty output. Returns `None`, and resets the
+    /// state, if the chord is not in the dictionary.
+    pub fn translate<'a>(&mut self, dict: &Dictionary<'a>, chord: u8) -> Option<Output<'a>> {
+        let len = usize::from(self.len);
+        let mut chords = [0u8; MAX_BRIEF_LEN];
+        let mut prefix = false;
+        for undone in (0..=len).rev() {
+            let recent = &self.history[len - undone..len];
+            let n = recent.iter().map(|t| usize::from(t.len)).sum::<usize>() + 1;
+            if n > MAX_BRIEF_LEN {
+                continue;
+            }
+            let mut i = 0;
+            for t in recent {
+                chords[i..i + t.chords().len()].copy_from_slice(t.chords());
+                i += t.chords().len();
+            }
+            chords[i] = chord;
+            let Some(node) = dict.get(&chords[..n]) else {
+                continue;
+            };
+            match node.translation() {
+                Some(text) => return Some(self.replace(undone, &chords[..n], text)),
+                None => prefix |= node.has_children(),
+            }
+        }
+        if prefix {
+            return Some(self.replace(0, &[chord], ""));
+        }
+        self.reset();
+        None
+    }
+
+    /// Forgets the recent translations, e.g. when other keys were typed. The
+    /// next word is then attached to them.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Forgets the last translation, returning edits erasing it. The
+    /// formatting state is then as before the translation.
+    pub fn undo(&mut self) -> Option<Output<'static>> {
+        self.len = self.len.checked_sub(1)?;
+        let t = self.history[usize::from(self.len)];
+        self.format = t.before;
+        Some(Output::erase(t.typed, t.erased_char()))
+    }
+
+    /// Undoes the last `undone` translations, and types the new one.
+    fn replace<'a>(&mut self, undone: usize, chords: &[u8], text: &'a str) -> Output<'a> {
+        let keep = usize::from(self.len) - undone;
+        let before = match undone {
+            0 => self.format,
+            _ => self.history[keep].before,
+        };
+        let (mut backspaces, mut restore) = (0u16, None);
+        for (i, t) in self.history[keep..usize::from(self.len)].iter().enumerate() {
+            backspaces = backspaces.saturating_add(t.typed);
+            if i == 0 {
+                restore = t.erased_char();
+            } else {
+                backspaces = backspaces.saturating_sub(t.erased);
+            }
+        }
+
+        let output = Output::new(text, before);
+        let mut end = output;
+        let (mut typed, mut erased) = (0u16, 0u16);
+        for edit in &mut end {
+            match edit {
+                Edit::Char(_) => typed = typed.saturating_add(1),
+                Edit::Backspace => erased = erased.saturating_add(1),
+            }
+        }
+        self.format = end.format;
+
+        self.len = keep as u8;
+        if keep == MAX_BRIEF_LEN {
+            self.history.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        let mut t = Translated { len: chords.len() as u8, typed, erased, before, ..Default::default() };
+        t.chords[..chords.len()].copy_from_slice(chords);
+        self.history[usize::from(self.len)] = t;
+        self.len += 1;
+
+        Output { backspaces, restore, replaced: undone as u8, ..output }
+    }
+}
+
+/// Host-side compiler of dictionaries, from a text or JSON source.
+///
+/// The text format has an entry per line: chords separated by `/`, then
+/// whitespace, then the translation until the end of the line. Lines
+/// starting with `#` are comments:
+///
+/// ```text
+/// # the
+/// ___v the
+/// _^_v/^___ {^ing}
+/// ```
+///
+/// The JSON format is an object, like in Plover: `{"___v": "the"}`. Chords
+/// are written in the [`notation`](crate::notation) of the `chord!` macro,
+/// with 4 positions, e.g. `_^_%` or `I:^` (in the text format, without
+/// spaces), and at least one switch pressed.
+#[cfg(any(test, feature = "std"))]
+pub mod source {
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    use super::{MAGIC, MAX_BRIEF_LEN, VERSION};
+    use crate::blob;
+    use crate::notation;
+
+    /// Chords, and their translation.
+    pub type Entry = (Vec<u8>, String);
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Error {
+        /// 1-based line number.
+        pub line: usize,
+        /// 1-based column number, in characters.
+        pub column: usize,
+        pub kind: ErrorKind,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum ErrorKind {
+        MalformedChord(String, notation::ErrorKind),
+        EmptyChord(String),
+        TooManyChords(String),
+        MissingTranslation,
+        DuplicateEntry(String),
+        MalformedJson(&'static str),
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            use ErrorKind::*;
+            write!(f, "{}:{}: ", self.line, self.column)?;
+            match &self.kind {
+                MalformedChord(s, kind) => write!(f, "malformed chord: {s:?}, {kind}"),
+                EmptyChord(s) => write!(f, "chord with no switches pressed: {s:?}"),
+                TooManyChords(s) => write!(f, "more than {MAX_BRIEF_LEN} chords: {s:?}"),
+                MissingTranslation => write!(f, "missing translation"),
+                DuplicateEntry(s) => write!(f, "entry {s:?} already defined"),
+                MalformedJson(what) => write!(f, "malformed JSON: {what}"),
+            }
+        }
+    }
+
+    impl std::error::Error for Error {}
+
+    /// Parses a dictionary in the text format, or in JSON if it starts
+    /// with `{`.
+    pub fn parse(src: &str) -> Result<Vec<Entry>, Error> {
+        let mut entries = Vec::new();
+        if src.trim_start().starts_with('{') {
+            let mut json = Json { src, pos: 0 };
+            json.object(|pos, key, value| {
+                let entry = parse_entry(key, value).map_err(|kind| json_error(src, pos, kind))?;
+                add(&mut entries, entry).map_err(|kind| json_error(src, pos, kind))
+            })?;
+            return Ok(entries);
+        }
+        for (i, line) in src.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let column = line[..line.len() - trimmed.len()].chars().count() + 1;
+            let error = |kind| Error { line: i + 1, column, kind };
+            let (key, value) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+            let entry = parse_entry(key, value.trim()).map_err(error)?;
+            add(&mut entries, entry).map_err(error)?;
+        }
+        Ok(entries)
+    }
+
+    fn parse_entry(key: &str, value: &str) -> Result<Entry, ErrorKind> {
+        let chords = key.split('/')
+            .map(|s| match notation::parse_chord_u8(s) {
+                Ok(0) => Err(ErrorKind::EmptyChord(key.to_string())),
+                Ok(chord) => Ok(chord),
+                Err(err) => Err(ErrorKind::MalformedChord(key.to_string(), err.kind)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if chords.len() > MAX_BRIEF_LEN {
+            return Err(ErrorKind::TooManyChords(key.to_string()));
+        }
+        if value.is_empty() {
+            return Err(ErrorKind::MissingTranslation);
+        }
+        Ok((chords, value.to_string()))
+    }
+
+    fn add(entries: &mut Vec<Entry>, entry: Entry) -> Result<(), ErrorKind> {
+        if let Some((chords, _)) = entries.iter().find(|(chords, _)| *chords == entry.0) {
+            let key: Vec<_> = chords.iter().map(|&c| crate::dsl::chord_to_string(c)).collect();
+            return Err(ErrorKind::DuplicateEntry(key.join("/")));
+        }
+        entries.push(entry);
+        Ok(())
+    }
+
+    /// Compiles the entries to the binary trie format.
+    pub fn compile(entries: &[Entry]) -> Result<Vec<u8>, blob::Error> {
+        let mut root = Trie::default();
+        for (chords, translation) in entries {
+            let node = chords.iter().fold(&mut root, |node, chord| node.children.entry(*chord).or_default());
+            node.translation = translation;
+        }
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        root.write(&mut out)?;
+        Ok(out)
+    }
+
+    #[derive(Default)]
+    struct Trie<'a> {
+        translation: &'a str,
+        children: BTreeMap<u8, Trie<'a>>,
+    }
+
+    impl Trie<'_> {
+        fn write(&self, out: &mut Vec<u8>) -> Result<(), blob::Error> {
+            out.push(u8::try_from(self.translation.len()).map_err(|_| blob::Error::TooLarge)?);
+            out.extend_from_slice(self.translation.as_bytes());
+            out.push(u8::try_from(self.children.len()).map_err(|_| blob::Error::TooLarge)?);
+            let mut table = out.len();
+            out.resize(table + 3 * self.children.len(), 0);
+            for (&chord, child) in &self.children {
+                let offset = u16::try_from(out.len()).map_err(|_| blob::Error::TooLarge)?;
+                out[table] = chord;
+                out[table + 1..table + 3].copy_from_slice(&offset.to_le_bytes());
+                table += 3;
+                child.write(out)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn json_error(src: &str, pos: usize, kind: ErrorKind) -> Error {
+        let before = &src[..pos];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Error {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            kind,
+        }
+    }
+
+    /// A minimal parser of a JSON object with string values.
+    struct Json<'a> {
+        src: &'a str,
+        pos: usize,
+    }
+
+    impl Json<'_> {
+        fn error(&self, what: &'static str) -> Error {
+            json_error(self.src, self.pos, ErrorKind::MalformedJson(what))
+        }
+
+        fn skip_whitespace(&mut self) {
+            let rest = &self.src[self.pos..];
+            self.pos += rest.len() - rest.trim_start().len();
+        }
+
+        fn eat(&mut self, c: char) -> bool {
+            self.skip_whitespace();
+            let found = self.src[self.pos..].starts_with(c);
+            if found {
+                self.pos += c.len_utf8();
+            }
+            found
+        }
+
+        /// Parses the object, calling `f` with the position, key and value
+        /// of every member.
+        fn object<F>(&mut self, mut f: F) -> Result<(), Error>
+        where F: FnMut(usize, &str, &str) -> Result<(), Error>,
+        {
+            if !self.eat('{') {
+                return Err(self.error("expected '{'"));
+            }
+            if !self.eat('}') {
+                loop {
+                    self.skip_whitespace();
+                    let pos = self.pos;
+                    let key = self.string()?;
+                    if !self.eat(':') {
+                        return Err(self.error("expected ':'"));
+                    }
+                    let value = self.string()?;
+                    f(pos, &key, &value)?;
+                    if self.eat('}') {
+                        break;
+                    }
+                    if !self.eat(',') {
+                        return Err(self.error("expected ',' or '}'"));
+                    }
+                }
+            }
+            self.skip_whitespace();
+            if self.pos != self.src.len() {
+                return Err(self.error("unexpected characters after the object"));
+            }
+            Ok(())
+        }
+
+        fn string(&mut self) -> Result<String, Error> {
+            if !self.eat('"') {
+                return Err(self.error("expected a string"));
+            }
+            let mut out = String::new();
+            let (src, start) = (self.src, self.pos);
+            let mut chars = src[start..].char_indices();
+            loop {
+                let Some((i, c)) = chars.next() else {
+                    return Err(self.error("unterminated string"));
+                };
+                self.pos = start + i;
+                match c {
+                    '"' => break,
+                    '\\' => {
+                        let c = match chars.next().map(|(_, c)| c) {
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            Some('/') => '/',
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some('u') => {
+                                let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                                u32::from_str_radix(&hex, 16).ok()
+                                    .and_then(char::from_u32)
+                                    .ok_or_else(|| self.error("bad \\u escape"))?
+                            }
+                            _ => return Err(self.error("bad escape")),
+                        };
+                        out.push(c);
+                    }
+                    c => out.push(c),
+                }
+            }
+            self.pos += 1;
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::source::{Error as SourceError, ErrorKind};
+
+    use clawtype_macros::chord;
+
+    const SAMPLE: &str = include_str!("../examples/sample.briefs");
+
+    fn dictionary(src: &str) -> Dictionary<'static> {
+        let bytes = source::compile(&source::parse(src).unwrap()).unwrap();
+        Dictionary::new(bytes.leak()).unwrap()
+    }
+
+    /// Translates the chords, and applies the edits to a text.
+    fn type_chords(dict: &Dictionary, chords: &[&str]) -> String {
+        let mut translator = Translator::default();
+        let mut text = String::new();
+        for s in chords {
+            let chord = crate::notation::parse_chord_u8(s).unwrap();
+            let output = translator.translate(dict, chord).unwrap_or_else(|| panic!("{s} not translated"));
+            for edit in output {
+                match edit {
+                    Edit::Backspace => _ = text.pop(),
+                    Edit::Char(c) => text.push(c),
+                }
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn trie() {
+        let dict = dictionary(SAMPLE);
+        let the = chord!("___v");
+        assert_eq!(dict.get(&[the]).unwrap().translation(), Some("the"));
+        assert!(dict.get(&[chord!("_^_v")]).unwrap().has_children());
+        assert_eq!(dict.get(&[chord!("_^_v"), chord!("^___")]).unwrap().translation(), Some("{^ing}"));
+        assert!(dict.get(&[the, the]).is_none());
+        assert!(Dictionary::EMPTY.get(&[the]).is_none());
+        assert!(!Dictionary::EMPTY.root().has_children());
+    }
+
+    #[test]
+    fn text_and_json_sources() {
+        let text = source::parse("# comment\n  ___^/___v  the thing\n__^_ {^ing}\n").unwrap();
+        let json = source::parse(r#" { "___^/___v": "the thing", "__^_" : "{^ing}" } "#).unwrap();
+        assert_eq!(text, json);
+        assert_eq!(text, [
+            (vec![chord!("___^"), chord!("___v")], "the thing".to_string()),
+            (vec![chord!("__^_")], "{^ing}".to_string()),
+        ]);
+        assert_eq!(source::parse("{}").unwrap(), []);
+        assert_eq!(source::parse(r#"{"I:^ M:v/P:v": "x"}"#).unwrap(),
+            [(vec![chord!("I:^ M:v"), chord!("P:v")], "x".to_string())]);
+    }
+
+    #[test]
+    fn source_errors() {
+        let err_at = |src| {
+            let SourceError { line, column, kind } = source::parse(src).unwrap_err();
+            (line, column, kind)
+        };
+        assert_eq!(err_at("___^ a\n  __x^ b"),
+            (2, 3, ErrorKind::MalformedChord("__x^".into(), crate::notation::ErrorKind::UnknownCrumb)));
+        assert_eq!(err_at("___^/__^__ a"),
+            (1, 1, ErrorKind::MalformedChord("___^/__^__".into(), crate::notation::ErrorKind::NotFourPositions)));
+        assert_eq!(err_at("____ a"), (1, 1, ErrorKind::EmptyChord("____".into())));
+        assert_eq!(err_at("___^/___^/___^/___^/___^ a"),
+            (1, 1, ErrorKind::TooManyChords("___^/___^/___^/___^/___^".into())));
+        assert_eq!(err_at("___^"), (1, 1, ErrorKind::MissingTranslation));
+        assert_eq!(err_at("___^ a\n___^ b"), (2, 1, ErrorKind::DuplicateEntry("___^".into())));
+        assert_eq!(err_at("{\n  \"___^\": \"a\",\n  \"___^\": \"b\"\n}"),
+            (3, 3, ErrorKind::DuplicateEntry("___^".into())));
+        assert_eq!(err_at("{\"___^\" \"a\"}"), (1, 9, ErrorKind::MalformedJson("expected ':'")));
+        assert_eq!(err_at("{\"___^\": \"a\"} x"),
+            (1, 15, ErrorKind::MalformedJson("unexpected characters after the object")));
+        assert_eq!(err_at("{\"___^\": \"a\\q\"}"), (1, 12, ErrorKind::MalformedJson("bad escape")));
+        assert_eq!(err_at("{\"___^\": \"a"), (1, 11, ErrorKind::MalformedJson("unterminated string")));
+        assert_eq!(source::parse("  ___^").unwrap_err().to_string(), "1:3: missing translation");
+    }
+
+    #[test]
+    fn spacing_and_capitalization() {
+        let dict = dictionary(SAMPLE);
+        // the cat. it is
+        let text = type_chords(&dict, &["___v", "__v_", "_^^^", "___%", "__%_"]);
+        assert_eq!(text, "the cat. It is");
+        assert_eq!(type_chords(&dict, &["_vv_", "___v", "__v_", "^__^", "___v"]), "The cat, the");
+        assert_eq!(type_chords(&dict, &["__v_", "^^__", "___v"]), "catthe");
+        assert_eq!(type_chords(&dict, &["_%%_", "___v", "___v"]), "THE the");
+        assert_eq!(type_chords(&dict, &["___v", "v___", "vv__", "___v"]), "the ab the");
+    }
+
+    #[test]
+    fn suffixes() {
+        let dict = dictionary(SAMPLE);
+        let ing = ["_^_v", "^___"];
+        let suffixed = |word: &str, suffix: &[&str]| {
+            let mut chords = vec![word];
+            chords.extend_from_slice(suffix);
+            type_chords(&dict, &chords)
+        };
+        assert_eq!(suffixed("%___", &ing), "making");
+        assert_eq!(suffixed("_%__", &ing), "stopping");
+        assert_eq!(suffixed("_%__", &["_v_v"]), "stopped");
+        assert_eq!(suffixed("%%__", &["_v_v"]), "carried");
+        assert_eq!(suffixed("%%__", &ing), "carrying");
+        assert_eq!(suffixed("%%__", &["__vv"]), "carries");
+        assert_eq!(suffixed("^^^^", &["__vv"]), "boxes");
+        assert_eq!(suffixed("^^^^", &ing), "boxing");
+        assert_eq!(suffixed("_%%%", &ing), "opening");
+        assert_eq!(suffixed("_%%%", &["__vv"]), "opens");
+    }
+
+    #[test]
+    fn multi_chord_entries_replace_translations() {
+        let dict = dictionary(SAMPLE);
+        let mut translator = Translator::default();
+        let mut edits = |s: &str| -> Vec<Edit> {
+            translator.translate(&dict, crate::notation::parse_chord_u8(s).unwrap()).unwrap().collect()
+        };
+        use Edit::*;
+        // "the" + "cat" = "the category"
+        assert_eq!(edits("___v"), [Char('t'), Char('h'), Char('e')]);
+        assert_eq!(edits("__v_"), [Char(' '), Char('c'), Char('a'), Char('t')]);
+        assert_eq!(edits("vvvv"), [
+            Backspace, Backspace, Backspace, Backspace, Backspace, Backspace, Backspace,
+            Char('t'), Char('h'), Char('e'), Char(' '),
+            Char('c'), Char('a'), Char('t'), Char('e'), Char('g'), Char('o'), Char('r'), Char('y'),
+        ]);
+        // a chord only starting an entry
+        assert_eq!(edits("_^_v"), []);
+        assert_eq!(edits("^___"), [Char('i'), Char('n'), Char('g')]);
+    }
+
+    #[test]
+    fn replaced_suffix_restores_erased_letter() {
+        let dict = dictionary("%___ make\n^^_^ {^ing}\n^^_^/^^_^ {^ings}\n");
+        assert_eq!(type_chords(&dict, &["%___", "^^_^"]), "making");
+        assert_eq!(type_chords(&dict, &["%___", "^^_^", "^^_^"]), "makings");
+    }
+
+    #[test]
+    fn undo_restores_formatting() {
+        let dict = dictionary(SAMPLE);
+        let mut translator = Translator::default();
+        let mut edits = |chord| translator.translate(&dict, chord).unwrap().collect::<Vec<_>>();
+        edits(chord!("___v")); // the
+        edits(chord!("_^^^")); // .
+        edits(chord!("%___")); // make
+        assert_eq!(edits(chord!("_v_v")), [Edit::Backspace, Edit::Char('e'), Edit::Char('d')]);
+        let undo: Vec<_> = translator.undo().unwrap().collect();
+        assert_eq!(undo, [Edit::Backspace, Edit::Backspace, Edit::Char('e')]);
+        let undo: Vec<_> = translator.undo().unwrap().collect();
+        assert_eq!(undo, [Edit::Backspace; 5]);
+        // capitalized again
+        let edits: Vec<_> = translator.translate(&dict, chord!("___v")).unwrap().collect();
+        assert_eq!(edits, [Edit::Char(' '), Edit::Char('T'), Edit::Char('h'), Edit::Char('e')]);
+
+        // a translation replacing others is undone as a whole
+        let mut translator = Translator::default();
+        translator.translate(&dict, chord!("___v"));
+        translator.translate(&dict, chord!("__v_"));
+        let output = translator.translate(&dict, chord!("vvvv")).unwrap();
+        assert_eq!(output.replaced(), 2);
+        assert_eq!(translator.undo().unwrap().count(), "the category".len());
+        assert!(translator.undo().is_none());
+    }
+
+    #[test]
+    fn untranslated_chord_resets() {
+        let dict = dictionary(SAMPLE);
+        let mut translator = Translator::default();
+        assert!(translator.translate(&dict, chord!("___v")).is_some());
+        assert!(translator.translate(&dict, chord!("^___")).is_none());
+        // attached to whatever was typed in between
+        let edits: Vec<_> = translator.translate(&dict, chord!("___v")).unwrap().collect();
+        assert_eq!(edits, [Edit::Char('t'), Edit::Char('h'), Edit::Char('e')]);
+    }
+
+    #[test]
+    fn truncated_dictionary_does_not_panic() {
+        let full = source::compile(&source::parse(SAMPLE).unwrap()).unwrap();
+        for n in 0..full.len() {
+            let Ok(dict) = Dictionary::new(&full[..n]) else {
+                continue;
+            };
+            let mut translator = Translator::default();
+            for chord in 0..=u8::MAX {
+                if let Some(output) = translator.translate(&dict, chord) {
+                    output.count();
+                }
+            }
+        }
+        assert_eq!(Dictionary::new(b"CLWB").unwrap_err(), Error::Truncated);
+        assert_eq!(Dictionary::new(b"CLWT\x01").unwrap_err(), Error::BadMagic);
+        assert_eq!(Dictionary::new(b"CLWB\x02").unwrap_err(), Error::UnsupportedVersion(2));
+    }
+}
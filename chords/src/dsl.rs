@@ -0,0 +1,1158 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Host-side compiler for a human-readable layout language. A layout can be
+//! compiled either to Rust source of a [`Lookup`](crate::Lookup) impl, or to
+//! the [`blob`] format. Example:
+//!
+//! ```text
+//! # comments start with '#'
+//! layer 0 base                  # layer number, and an optional name
+//!   _^_% UP                     # Emit(KeyHit(UP))
+//!   ^^_% shift+KEY_8            # Emit(KeyHit(KEY_8 | SHIFT_FLAG))
+//!   ___^ press E                # also: hit, release, nothing
+//!   %%%% clear                  # ClearState
+//!   %%%^ caps-word              # CapsWord
+//!   %%%v leader                 # Leader
+//!   %%_% briefs                 # Briefs
+//!   %%^% undo                   # Undo
+//!   v^_v switch 2 pointer:toggle  # LayerSwitchAndEmit
+//!   _vv_ once 1                 # TemporaryLayerSwitch
+//!   _^^_ temp ctrl              # TemporaryPlusMask
+//!   %_^^ toggle alt             # TogglePlusMask
+//!   %_%% text "Hi, #1!\n"       # EmitText; escapes: \" \\ \n \t
+//!   v_%% unicode ż              # EmitUnicode; also: unicode U+017C
+//!   vv%% unicode-method mac     # SetUnicodeMethod; also: linux, windows
+//!   v_^% char @                 # EmitChar; also: char U+0040
+//!   vv^% host-layout de         # SetHostLayout; also: us, uk, fr, pl
+//!   __^_ tap SPACE hold ctrl    # TapHold; either can also be: layer N
+//!   v_^_ push 3 toggle          # PushLayer; also: one-shot, momentary, locked
+//! layer 1 shift
+//!   default from 0 shift        # LayerInfo::fallback; also: layer N, swallow
+//! layer 2
+//!   mask __^^                   # LayerInfo::unchorded_mask
+//!   unchorded ___^ mouse:left
+//! layer 3 num
+//!   transparent                 # LayerInfo::transparent
+//!   fallback 2 0                # Lookup::fallbacks, in order
+//! sequence _^_v ^___ text "git status\n"  # Lookup::sequence, after Leader
+//! ```
+//!
+//! Sequences can be declared anywhere, as they don't belong to any layer.
+//! Chords are written in the [`notation`](crate::notation) of the `chord!`
+//! macro, with 4 positions and without spaces, e.g. `_^_%` or `I:^`. Keys
+//! are names from
+//! [`keycodes`](crate::keycodes) (case-insensitive), optionally joined by `+`
+//! with modifiers: `ctrl`, `shift`, `alt`, `gui`, `rctrl`, `rshift`, `ralt`,
+//! `rgui`, or full flag names like `SHIFT_FLAG`.
+//!
+//! Instead of a key, an action can also be one of:
+//! - `mouse:left`, `mouse:right`, `mouse:middle` for a button, which is
+//!   clicked when hit, and held while pressed; `mouse:press:left`,
+//!   `mouse:release:left` and `mouse:drag:left` to press, release, or
+//!   toggle it,
+//! - `wheel:N` to scroll by `N`, positive being up,
+//! - `pointer:toggle`, `pointer:on`, `pointer:off` to move the pointer or not,
+//! - `media:volume_up` for a media key, by its name in
+//!   [`keycodes::media`](crate::keycodes::media) or its HID Consumer usage
+//!   ID like `media:0xE9`,
+//! - `system:sleep` for a system control, by its name in
+//!   [`keycodes::system`](crate::keycodes::system) or its usage ID like
+//!   `system:0x82`.
+//!
+//! Modifiers can be joined with mouse actions too, e.g. `ctrl+mouse:left`.
+
+use std::fmt::{self, Write as _};
+
+use crate::action::{Action, MouseAction, MouseButton, PointerMode};
+use crate::blob;
+use crate::keycodes::{self, KeyWithFlags, FLAG_NAMES, KEY_MASK, KEY_NAMES};
+use crate::host_layout::HostLayout;
+use crate::layer_stack::LayerMode;
+use crate::notation;
+use crate::{LayerFallback, LayerInfo, LayerOutcome, MAX_SEQUENCE_LEN, SwitchSet, TapHoldAction, UsbOutcome, unicode};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    /// Layers, indexed by their number. Layers not declared in the
+    /// source are left empty.
+    pub layers: Vec<Layer>,
+    /// Sequences of chords typed after [`LayerOutcome::Leader`].
+    pub sequences: Vec<(Vec<u8>, LayerOutcome<Action>)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layer {
+    pub name: Option<String>,
+    pub info: LayerInfo,
+    pub fallbacks: Vec<u8>,
+    pub unchorded: Vec<(u8, Action)>,
+    pub chords: Vec<(u8, LayerOutcome<Action>)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number, in characters.
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnknownKey(String),
+    MalformedChord(String, notation::ErrorKind),
+    ExpectedNumber(String),
+    MissingArgument(&'static str),
+    UnexpectedToken(String),
+    DuplicateChord(String),
+    DuplicateLayer(u8),
+    DuplicateUnchorded(String),
+    NotSingleSwitch(String),
+    MalformedString(String),
+    MalformedCharacter(String),
+    UnknownUnicodeMethod(String),
+    UnknownHostLayout(String),
+    UnknownLayerMode(String),
+    SequenceTooLong,
+    DuplicateSequence,
+    OutsideLayer,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ErrorKind::*;
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            UnknownKey(s) => write!(f, "unknown key: {s:?}"),
+            MalformedChord(s, kind) => write!(f, "malformed chord: {s:?}, {kind}"),
+            ExpectedNumber(s) => write!(f, "expected a number 0-255, got: {s:?}"),
+            MissingArgument(what) => write!(f, "missing argument: {what}"),
+            UnexpectedToken(s) => write!(f, "unexpected: {s:?}"),
+            DuplicateChord(s) => write!(f, "chord {s:?} already defined in this layer"),
+            DuplicateLayer(n) => write!(f, "layer {n} already defined"),
+            DuplicateUnchorded(s) => write!(f, "unchorded key {s:?} already defined in this layer"),
+            NotSingleSwitch(s) => write!(f, "unchorded key must be a single switch, got: {s:?}"),
+            MalformedString(s) => write!(f, "malformed string: {s}"),
+            MalformedCharacter(s) => write!(f, "expected a single character or U+hex, got: {s:?}"),
+            UnknownUnicodeMethod(s) => write!(f, "unknown unicode method: {s:?}, expected: linux, windows, mac"),
+            UnknownHostLayout(s) => write!(f, "unknown host layout: {s:?}, expected: us, uk, de, fr, pl"),
+            UnknownLayerMode(s) => write!(f, "unknown layer mode: {s:?}, expected: one-shot, momentary, toggle, locked"),
+            SequenceTooLong => write!(f, "sequence longer than {MAX_SEQUENCE_LEN} chords"),
+            DuplicateSequence => write!(f, "sequence already defined"),
+            OutsideLayer => write!(f, "expected 'layer' before any definitions"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn parse(src: &str) -> Result<Layout, Error> {
+    let mut layout = Layout::default();
+    let mut declared = Vec::<u8>::new();
+    let mut current: Option<usize> = None;
+    for (i, line) in src.lines().enumerate() {
+        let mut tokens = Tokens::new(i + 1, line);
+        let Some(first) = tokens.next() else {
+            continue;
+        };
+        if first.text == "layer" {
+            let n = tokens.number("layer number")?;
+            if declared.contains(&n) {
+                return Err(first.error(ErrorKind::DuplicateLayer(n)));
+            }
+            declared.push(n);
+            let n = usize::from(n);
+            if layout.layers.len() <= n {
+                layout.layers.resize_with(n + 1, Layer::default);
+            }
+            layout.layers[n].name = tokens.next().map(|t| t.text.to_string());
+            tokens.end()?;
+            current = Some(n);
+            continue;
+        }
+        if first.text == "sequence" {
+            let mut chords = vec![tokens.chord("chord")?];
+            let outcome = loop {
+                let t = tokens.expect("action")?;
+                match t.chord() {
+                    Ok(_) if chords.len() == MAX_SEQUENCE_LEN => {
+                        return Err(t.error(ErrorKind::SequenceTooLong));
+                    }
+                    Ok(chord) => chords.push(chord),
+                    Err(err @ Error { kind: ErrorKind::MalformedChord(_, notation::ErrorKind::NotFourPositions), .. }) => {
+                        return Err(err);
+                    }
+                    Err(_) => break tokens.outcome_from(t)?,
+                }
+            };
+            if layout.sequences.iter().any(|(c, _)| *c == chords) {
+                return Err(first.error(ErrorKind::DuplicateSequence));
+            }
+            layout.sequences.push((chords, outcome));
+            tokens.end()?;
+            continue;
+        }
+        let Some(n) = current else {
+            return Err(first.error(ErrorKind::OutsideLayer));
+        };
+        let layer = &mut layout.layers[n];
+        match first.text {
+            "mask" => {
+                let chord = tokens.chord("mask")?;
+                layer.info.unchorded_mask = SwitchSet(chord);
+            }
+            "transparent" => layer.info.transparent = true,
+            "default" => {
+                let t = tokens.expect("default action")?;
+                layer.info.fallback = match t.text {
+                    "layer" => LayerFallback::Transparent { layer: tokens.number("layer number")? },
+                    "from" => LayerFallback::EmitWithMask {
+                        layer: tokens.number("layer number")?,
+                        mask: match tokens.next() {
+                            Some(t) => t.key()?,
+                            None => 0,
+                        },
+                    },
+                    "swallow" => LayerFallback::Swallow,
+                    _ => return Err(t.error(ErrorKind::UnexpectedToken(t.text.to_string()))),
+                };
+            }
+            "fallback" => {
+                layer.fallbacks.push(tokens.number("layer number")?);
+                while let Some(t) = tokens.next() {
+                    layer.fallbacks.push(t.number()?);
+                }
+            }
+            "unchorded" => {
+                let t = tokens.expect("switch")?;
+                let switch = t.chord()?;
+                if switch.count_ones() != 1 {
+                    return Err(t.error(ErrorKind::NotSingleSwitch(t.text.to_string())));
+                }
+                if layer.unchorded.iter().any(|&(s, _)| s == switch) {
+                    return Err(t.error(ErrorKind::DuplicateUnchorded(t.text.to_string())));
+                }
+                let action = tokens.action("key")?;
+                layer.unchorded.push((switch, action));
+            }
+            _ => {
+                let chord = first.chord()?;
+                if layer.chords.iter().any(|&(c, _)| c == chord) {
+                    return Err(first.error(ErrorKind::DuplicateChord(first.text.to_string())));
+                }
+                let outcome = tokens.outcome()?;
+                layer.chords.push((chord, outcome));
+            }
+        }
+        tokens.end()?;
+    }
+    Ok(layout)
+}
+
+impl Layout {
+    pub fn to_blob(&self) -> Result<Vec<u8>, blob::Error> {
+        let sources: Vec<_> = self.layers.iter()
+            .map(|l| blob::LayerSource {
+                info: l.info,
+                fallbacks: &l.fallbacks,
+                unchorded: &l.unchorded,
+                chords: &l.chords,
+            })
+            .collect();
+        let sequences: Vec<_> = self.sequences.iter()
+            .map(|(chords, outcome)| (chords.as_slice(), *outcome))
+            .collect();
+        let mut buf = vec![0u8; 1024];
+        loop {
+            match blob::write(&mut buf, &sources, &sequences) {
+                Ok(n) => {
+                    buf.truncate(n);
+                    return Ok(buf);
+                }
+                Err(blob::Error::TooLarge) if buf.len() < MAX_BLOB_LEN => {
+                    buf.resize(buf.len() * 2, 0);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Generates Rust source of a `Lookup` impl named `type_name`, in the
+    /// same style as handwritten layouts using `layout!`.
+    pub fn to_rust(&self, type_name: &str) -> String {
+        let mut s = String::new();
+        let _ = self.write_rust(&mut s, type_name);
+        s
+    }
+
+    fn write_rust(&self, w: &mut String, type_name: &str) -> fmt::Result {
+        writeln!(w, "// Generated by the clawtype-chords layout compiler. Do not edit.")?;
+        writeln!(w)?;
+        writeln!(w, "#[allow(unused_imports)]")?;
+        writeln!(w, "use clawtype_macros::{{chord, layout}};")?;
+        writeln!(w, "#[allow(unused_imports)]")?;
+        writeln!(w, "use clawtype_chords::{{")?;
+        writeln!(w, "    LayerOutcome::*,")?;
+        writeln!(w, "    UsbOutcome::{{KeyHit as Hit, KeyPress as Press, KeyRelease as Release, Nothing}},")?;
+        writeln!(w, "    action::{{Action::{{self, Key}}, MouseAction, MouseButton, PointerMode}},")?;
+        writeln!(w, "    keycodes::*,")?;
+        writeln!(w, "    LayerFallback, TapHoldAction, host_layout, layer_stack, unicode,")?;
+        writeln!(w, "}};")?;
+        writeln!(w)?;
+        writeln!(w, "layout! {{")?;
+        writeln!(w, "    pub struct {type_name};")?;
+        writeln!(w, "    type Action = Action;")?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            writeln!(w)?;
+            write!(w, "    layer {i} {{")?;
+            match &layer.name {
+                Some(name) => writeln!(w, " // {name:?}")?,
+                None => writeln!(w)?,
+            }
+            let mask = layer.info.unchorded_mask.0;
+            if mask != 0 {
+                writeln!(w, "        unchorded_mask: {:?},", chord_to_string(mask))?;
+            }
+            if layer.info.transparent {
+                writeln!(w, "        transparent: true,")?;
+            }
+            if layer.info.fallback != LayerFallback::None {
+                writeln!(w, "        fallback: {},", fallback_to_rust(layer.info.fallback))?;
+            }
+            if !layer.fallbacks.is_empty() {
+                writeln!(w, "        fallbacks: {:?},", layer.fallbacks)?;
+            }
+            for &(switch, action) in &layer.unchorded {
+                writeln!(w, "        unchorded {:?} => {},", chord_to_string(switch), action_to_rust(action))?;
+            }
+            for &(chord, outcome) in &layer.chords {
+                writeln!(w, "        {:?} => {},", chord_to_string(chord), outcome_to_rust(outcome))?;
+            }
+            writeln!(w, "    }}")?;
+        }
+        if !self.sequences.is_empty() {
+            writeln!(w)?;
+            writeln!(w, "    fn sequence(chords: &[u8]) -> clawtype_chords::SequenceMatch<Action> {{")?;
+            writeln!(w, "        clawtype_chords::lookup_sequence(chords, &[")?;
+            for (chords, outcome) in &self.sequences {
+                let chords: Vec<_> = chords.iter()
+                    .map(|&chord| format!("chord!({:?})", chord_to_string(chord)))
+                    .collect();
+                writeln!(w, "            (&[{}], {}),", chords.join(", "), outcome_to_rust(*outcome))?;
+            }
+            writeln!(w, "        ])")?;
+            writeln!(w, "    }}")?;
+        }
+        writeln!(w, "}}")
+    }
+}
+
+const MAX_BLOB_LEN: usize = 1 << 20;
+
+const MODIFIERS: &[(&str, KeyWithFlags)] = &[
+    ("ctrl", keycodes::CTRL_FLAG),
+    ("shift", keycodes::SHIFT_FLAG),
+    ("alt", keycodes::ALT_FLAG),
+    ("gui", keycodes::GUI_FLAG),
+    ("rctrl", keycodes::RIGHT_CTRL_FLAG),
+    ("rshift", keycodes::RIGHT_SHIFT_FLAG),
+    ("ralt", keycodes::RIGHT_ALT_FLAG),
+    ("rgui", keycodes::RIGHT_GUI_FLAG),
+];
+
+pub(crate) fn chord_to_string(chord: u8) -> String {
+    (0..4).rev()
+        .map(|i| match (chord >> (2 * i)) & 0b11 {
+            0b10 => '^',
+            0b01 => 'v',
+            0b11 => '%',
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Parses a `+`-separated list of modifiers and at most one key.
+fn parse_key(s: &str) -> Option<KeyWithFlags> {
+    let mut key = None;
+    let mut flags = 0;
+    for part in s.split('+') {
+        let upper = part.to_ascii_uppercase();
+        let flag = MODIFIERS.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(part))
+            .or_else(|| FLAG_NAMES.iter().find(|(name, _)| *name == upper));
+        if let Some((_, flag)) = flag {
+            flags |= flag;
+            continue;
+        }
+        let (_, k) = KEY_NAMES.iter().find(|(name, _)| *name == upper)?;
+        if key.replace(*k).is_some() {
+            return None;
+        }
+    }
+    Some(key.unwrap_or(0) | flags)
+}
+
+fn key_to_rust(key: KeyWithFlags) -> String {
+    if key == 0 {
+        return "0".to_string();
+    }
+    let mut parts = Vec::new();
+    let code = key & KEY_MASK;
+    if code != 0 {
+        match KEY_NAMES.iter().find(|(_, k)| *k == code) {
+            Some((name, _)) => parts.push(name.to_string()),
+            None => parts.push(format!("{code:#04x}")),
+        }
+    }
+    for (name, flag) in FLAG_NAMES {
+        if key & flag != 0 {
+            parts.push(name.to_string());
+        }
+    }
+    parts.join(" | ")
+}
+
+/// Parses an action, see the [module docs](self), joined by `+` with
+/// modifiers.
+fn parse_action(s: &str) -> Option<Action> {
+    let mut action = None;
+    let mut flags = 0;
+    for part in s.split('+') {
+        let lower = part.to_ascii_lowercase();
+        let parsed = match lower.split_once(':') {
+            Some(("mouse", arg)) => {
+                let button = |name: &str| match name {
+                    "left" => Some(MouseButton::Left),
+                    "right" => Some(MouseButton::Right),
+                    "middle" => Some(MouseButton::Middle),
+                    _ => None,
+                };
+                let mouse = match arg.split_once(':') {
+                    Some(("press", name)) => MouseAction::Press(button(name)?),
+                    Some(("release", name)) => MouseAction::Release(button(name)?),
+                    Some(("drag", name)) => MouseAction::DragToggle(button(name)?),
+                    Some(_) => return None,
+                    None => MouseAction::Button(button(arg)?),
+                };
+                Action::Mouse(mouse, 0)
+            }
+            Some(("wheel", n)) => Action::Mouse(MouseAction::Wheel(n.parse().ok()?), 0),
+            Some(("pointer", mode)) => Action::Mouse(MouseAction::Pointer(match mode {
+                "toggle" => PointerMode::Toggle,
+                "on" => PointerMode::Enable,
+                "off" => PointerMode::Disable,
+                _ => return None,
+            }), 0),
+            Some(("media", usage)) => Action::Media(parse_usage(usage, keycodes::media::NAMES)?),
+            Some(("system", usage)) => Action::System(parse_usage(usage, keycodes::system::NAMES)?),
+            Some(_) => return None,
+            None => {
+                let key = parse_key(part)?;
+                if key & KEY_MASK == 0 {
+                    flags |= key;
+                    continue;
+                }
+                Action::Key(key)
+            }
+        };
+        if action.replace(parsed).is_some() {
+            return None;
+        }
+    }
+    match action.unwrap_or_default() {
+        Action::Media(_) | Action::System(_) if flags != 0 => None,
+        action => Some(action | flags),
+    }
+}
+
+/// Parses a usage ID, by its name (case-insensitive) or number.
+fn parse_usage<T: Copy + TryFrom<u32>>(s: &str, names: &[(&str, T)]) -> Option<T> {
+    if let Some((_, usage)) = names.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+        return Some(*usage);
+    }
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => s.parse().ok()?,
+    };
+    T::try_from(n).ok()
+}
+
+fn action_to_rust(action: Action) -> String {
+    fn button(b: MouseButton) -> String {
+        format!("MouseButton::{b:?}")
+    }
+    match action {
+        Action::Key(key) => format!("Key({})", key_to_rust(key)),
+        Action::Mouse(mouse, flags) => {
+            let mouse = match mouse {
+                MouseAction::Button(b) => format!("Button({})", button(b)),
+                MouseAction::Press(b) => format!("Press({})", button(b)),
+                MouseAction::Release(b) => format!("Release({})", button(b)),
+                MouseAction::DragToggle(b) => format!("DragToggle({})", button(b)),
+                MouseAction::Wheel(n) => format!("Wheel({n})"),
+                MouseAction::Pointer(mode) => format!("Pointer(PointerMode::{mode:?})"),
+            };
+            format!("Action::Mouse(MouseAction::{mouse}, {})", key_to_rust(flags))
+        }
+        Action::Media(usage) => format!("Action::Media({usage:#04x})"),
+        Action::System(usage) => format!("Action::System({usage:#04x})"),
+    }
+}
+
+fn tap_hold_to_rust(action: TapHoldAction<Action>) -> String {
+    match action {
+        TapHoldAction::Key(action) => format!("TapHoldAction::Key({})", action_to_rust(action)),
+        TapHoldAction::Layer(layer) => format!("TapHoldAction::Layer({layer})"),
+    }
+}
+
+fn fallback_to_rust(fallback: LayerFallback) -> String {
+    match fallback {
+        LayerFallback::None => "LayerFallback::None".to_string(),
+        LayerFallback::Transparent { layer } => format!("LayerFallback::Transparent {{ layer: {layer} }}"),
+        LayerFallback::EmitWithMask { layer, mask } =>
+            format!("LayerFallback::EmitWithMask {{ layer: {layer}, mask: {} }}", key_to_rust(mask)),
+        LayerFallback::Swallow => "LayerFallback::Swallow".to_string(),
+    }
+}
+
+fn usb_to_rust(usb: UsbOutcome<Action>) -> String {
+    use UsbOutcome::*;
+    match usb {
+        Nothing => "Nothing".to_string(),
+        KeyHit(a) => format!("Hit({})", action_to_rust(a)),
+        KeyPress(a) => format!("Press({})", action_to_rust(a)),
+        KeyRelease(a) => format!("Release({})", action_to_rust(a)),
+        UsbOutcome::Error(e) => format!("clawtype_chords::UsbOutcome::Error(clawtype_chords::LookupError::{e:?})"),
+    }
+}
+
+fn outcome_to_rust(outcome: LayerOutcome<Action>) -> String {
+    use LayerOutcome::*;
+    match outcome {
+        ClearState => "ClearState".to_string(),
+        CapsWord => "CapsWord".to_string(),
+        Leader => "Leader".to_string(),
+        Briefs => "Briefs".to_string(),
+        Undo => "Undo".to_string(),
+        Emit(usb) => format!("Emit({})", usb_to_rust(usb)),
+        LayerSwitchAndEmit { layer, emit } =>
+            format!("LayerSwitchAndEmit {{ layer: {layer}, emit: {} }}", usb_to_rust(emit)),
+        TemporaryLayerSwitch { layer } => format!("TemporaryLayerSwitch {{ layer: {layer} }}"),
+        TogglePlusMask { mask } => format!("TogglePlusMask {{ mask: {} }}", key_to_rust(mask)),
+        TemporaryPlusMask { mask } => format!("TemporaryPlusMask {{ mask: {} }}", key_to_rust(mask)),
+        FromOtherPlusMask { layer, mask } =>
+            format!("FromOtherPlusMask {{ layer: {layer}, mask: {} }}", key_to_rust(mask)),
+        EmitText(text) => format!("EmitText({text:?})"),
+        EmitUnicode(c) => format!("EmitUnicode({c:?})"),
+        SetUnicodeMethod(method) => format!("SetUnicodeMethod(unicode::Method::{method:?})"),
+        EmitChar(c) => format!("EmitChar({c:?})"),
+        SetHostLayout(layout) => format!("SetHostLayout(host_layout::HostLayout::{layout:?})"),
+        TapHold { tap, hold } => format!(
+            "TapHold {{ tap: {}, hold: {} }}",
+            tap_hold_to_rust(tap), tap_hold_to_rust(hold),
+        ),
+        PushLayer { layer, mode } =>
+            format!("PushLayer {{ layer: {layer}, mode: layer_stack::LayerMode::{mode:?} }}"),
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Token<'a> {
+    line: usize,
+    column: usize,
+    text: &'a str,
+}
+
+impl Token<'_> {
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error { line: self.line, column: self.column, kind }
+    }
+
+    fn chord(&self) -> Result<u8, Error> {
+        notation::parse_chord_u8(self.text)
+            .map_err(|err| self.error(ErrorKind::MalformedChord(self.text.to_string(), err.kind)))
+    }
+
+    fn key(&self) -> Result<KeyWithFlags, Error> {
+        parse_key(self.text)
+            .ok_or_else(|| self.error(ErrorKind::UnknownKey(self.text.to_string())))
+    }
+
+    fn action(&self) -> Result<Action, Error> {
+        parse_action(self.text)
+            .ok_or_else(|| self.error(ErrorKind::UnknownKey(self.text.to_string())))
+    }
+
+    fn number(&self) -> Result<u8, Error> {
+        self.text.parse()
+            .map_err(|_| self.error(ErrorKind::ExpectedNumber(self.text.to_string())))
+    }
+
+    fn character(&self) -> Result<char, Error> {
+        let mut chars = self.text.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(c);
+        }
+        self.text.strip_prefix("U+")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(ErrorKind::MalformedCharacter(self.text.to_string())))
+    }
+
+    fn string(&self) -> Result<String, Error> {
+        let malformed = || self.error(ErrorKind::MalformedString(self.text.to_string()));
+        let body = self.text.strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .filter(|s| string_len(s).is_none())
+            .ok_or_else(malformed)?;
+        let mut out = String::new();
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            out.push(match c {
+                '\\' => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(c @ ('"' | '\\')) => c,
+                    _ => return Err(malformed()),
+                },
+                c => c,
+            });
+        }
+        Ok(out)
+    }
+}
+
+/// Returns the length of a string literal's body up to the closing quote,
+/// or `None` if it's not terminated.
+fn string_len(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    s.find(|c| {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return true,
+            _ => (),
+        }
+        false
+    })
+}
+
+struct Tokens<'a> {
+    line: usize,
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: usize, src: &'a str) -> Self {
+        // strip comment, if any (but not inside a string)
+        let (mut quoted, mut escaped) = (false, false);
+        let comment = src.find(|c| {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                '#' if !quoted => return true,
+                _ => (),
+            }
+            false
+        });
+        let src = match comment {
+            Some(i) => &src[..i],
+            None => src,
+        };
+        Self { line, src, pos: 0 }
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let rest = &self.src[self.pos..];
+        let start = self.pos + (rest.len() - rest.trim_start().len());
+        let rest = &self.src[start..];
+        let len = match rest.strip_prefix('"') {
+            Some(quoted) => string_len(quoted).map_or(rest.len(), |n| n + 2),
+            None => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+        self.pos = start + len;
+        if len == 0 {
+            return None;
+        }
+        Some(Token {
+            line: self.line,
+            column: self.src[..start].chars().count() + 1,
+            text: &rest[..len],
+        })
+    }
+
+    fn expect(&mut self, what: &'static str) -> Result<Token<'a>, Error> {
+        self.next().ok_or(Error {
+            line: self.line,
+            column: self.src.chars().count() + 1,
+            kind: ErrorKind::MissingArgument(what),
+        })
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        match self.next() {
+            Some(t) => Err(t.error(ErrorKind::UnexpectedToken(t.text.to_string()))),
+            None => Ok(()),
+        }
+    }
+
+    fn chord(&mut self, what: &'static str) -> Result<u8, Error> {
+        self.expect(what)?.chord()
+    }
+
+    fn key(&mut self, what: &'static str) -> Result<KeyWithFlags, Error> {
+        self.expect(what)?.key()
+    }
+
+    fn action(&mut self, what: &'static str) -> Result<Action, Error> {
+        self.expect(what)?.action()
+    }
+
+    fn number(&mut self, what: &'static str) -> Result<u8, Error> {
+        self.expect(what)?.number()
+    }
+
+    fn usb(&mut self, first: Token) -> Result<UsbOutcome<Action>, Error> {
+        use UsbOutcome::*;
+        Ok(match first.text {
+            "nothing" => Nothing,
+            "hit" => KeyHit(self.action("key")?),
+            "press" => KeyPress(self.action("key")?),
+            "release" => KeyRelease(self.action("key")?),
+            _ => KeyHit(first.action()?),
+        })
+    }
+
+    fn tap_hold(&mut self, what: &'static str) -> Result<TapHoldAction<Action>, Error> {
+        let t = self.expect(what)?;
+        Ok(match t.text {
+            "layer" => TapHoldAction::Layer(self.number("layer number")?),
+            _ => TapHoldAction::Key(t.action()?),
+        })
+    }
+
+    fn outcome(&mut self) -> Result<LayerOutcome<Action>, Error> {
+        let t = self.expect("action")?;
+        self.outcome_from(t)
+    }
+
+    fn outcome_from(&mut self, t: Token) -> Result<LayerOutcome<Action>, Error> {
+        use LayerOutcome::*;
+        Ok(match t.text {
+            "clear" => ClearState,
+            "caps-word" => CapsWord,
+            "leader" => Leader,
+            "briefs" => Briefs,
+            "undo" => Undo,
+            "switch" => LayerSwitchAndEmit {
+                layer: self.number("layer number")?,
+                emit: match self.next() {
+                    Some(t) => self.usb(t)?,
+                    None => UsbOutcome::Nothing,
+                },
+            },
+            // The parser is meant for short-lived host tools, so it's ok
+            // to leak the text to satisfy the 'static lifetime.
+            "text" => EmitText(self.expect("text")?.string()?.leak()),
+            "unicode" => EmitUnicode(self.expect("character")?.character()?),
+            "unicode-method" => {
+                let t = self.expect("method")?;
+                SetUnicodeMethod(match t.text {
+                    "linux" => unicode::Method::Linux,
+                    "windows" => unicode::Method::WindowsAltNumpad,
+                    "mac" => unicode::Method::MacHexInput,
+                    _ => return Err(t.error(ErrorKind::UnknownUnicodeMethod(t.text.to_string()))),
+                })
+            }
+            "char" => EmitChar(self.expect("character")?.character()?),
+            "host-layout" => {
+                let t = self.expect("host layout")?;
+                SetHostLayout(match t.text {
+                    "us" => HostLayout::Us,
+                    "uk" => HostLayout::Uk,
+                    "de" => HostLayout::German,
+                    "fr" => HostLayout::French,
+                    "pl" => HostLayout::PolishProgrammer,
+                    _ => return Err(t.error(ErrorKind::UnknownHostLayout(t.text.to_string()))),
+                })
+            }
+            "tap" => {
+                let tap = self.tap_hold("tap action")?;
+                let t = self.expect("hold")?;
+                if t.text != "hold" {
+                    return Err(t.error(ErrorKind::UnexpectedToken(t.text.to_string())));
+                }
+                TapHold { tap, hold: self.tap_hold("hold action")? }
+            }
+            "push" => PushLayer {
+                layer: self.number("layer number")?,
+                mode: {
+                    let t = self.expect("layer mode")?;
+                    match t.text {
+                        "one-shot" => LayerMode::OneShot,
+                        "momentary" => LayerMode::Momentary,
+                        "toggle" => LayerMode::Toggle,
+                        "locked" => LayerMode::Locked,
+                        _ => return Err(t.error(ErrorKind::UnknownLayerMode(t.text.to_string()))),
+                    }
+                },
+            },
+            "once" => TemporaryLayerSwitch { layer: self.number("layer number")? },
+            "toggle" => TogglePlusMask { mask: self.key("modifiers")? },
+            "temp" => TemporaryPlusMask { mask: self.key("modifiers")? },
+            "from" => FromOtherPlusMask {
+                layer: self.number("layer number")?,
+                mask: match self.next() {
+                    Some(t) => t.key()?,
+                    None => 0,
+                },
+            },
+            _ => Emit(self.usb(t)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clawtype_macros::chord;
+    use crate::{Lookup, SequenceMatch};
+    use crate::action::{Action::Key, MOUSE_LEFT, MOUSE_POINTER_TOGGLE};
+    use crate::keycodes::*;
+    use crate::sample_layers::SampleLayers;
+    use crate::UsbOutcome::KeyHit as Hit;
+
+    const SAMPLE: &str = include_str!("../examples/sample.layout");
+
+    /// [`SAMPLE`] compiled by `layoutc`, to check that the generated
+    /// Rust builds.
+    mod generated {
+        include!("dsl/sample_layout.rs");
+    }
+
+    fn err_at(src: &str) -> (usize, usize, ErrorKind) {
+        let err = parse(src).unwrap_err();
+        (err.line, err.column, err.kind)
+    }
+
+    #[test]
+    fn sample_matches_sample_layers() {
+        let layout = parse(SAMPLE).unwrap();
+        assert_eq!(layout.layers.len(), 4);
+        let bytes = layout.to_blob().unwrap();
+        let blob = blob::Blob::new(bytes.leak()).unwrap();
+        for layer in 0..4 {
+            assert_eq!(blob.info(layer), SampleLayers::info(layer));
+            assert_eq!(blob.fallbacks(layer), SampleLayers::fallbacks(layer));
+            for chord in 0..=u8::MAX {
+                assert_eq!(blob.lookup(layer, chord), SampleLayers::lookup(layer, chord),
+                    "layer={layer} chord={:?}", chord_to_string(chord));
+            }
+            for bit in 0..8 {
+                let switch = SwitchSet(1 << bit);
+                assert_eq!(blob.unchorded_key(layer, switch), SampleLayers::unchorded_key(layer, switch));
+            }
+        }
+        for (chords, outcome) in &layout.sequences {
+            assert_eq!(blob.sequence(chords), SampleLayers::sequence(chords));
+            assert!(matches!(SampleLayers::sequence(chords),
+                SequenceMatch::Complete(o) | SequenceMatch::Ambiguous(o) if o == *outcome));
+        }
+        assert_eq!(layout.sequences.len(), 4);
+    }
+
+    #[test]
+    fn generated_sample_matches_sample_layers() {
+        use generated::SampleLayout;
+        let layout = parse(SAMPLE).unwrap();
+        assert_eq!(layout.to_rust("SampleLayout"), include_str!("dsl/sample_layout.rs"),
+            "regenerate with: layoutc examples/sample.layout rust SampleLayout");
+        for layer in 0..4 {
+            assert_eq!(SampleLayout::info(layer), SampleLayers::info(layer));
+            assert_eq!(SampleLayout::fallbacks(layer), SampleLayers::fallbacks(layer));
+            for chord in 0..=u8::MAX {
+                assert_eq!(SampleLayout::lookup(layer, chord), SampleLayers::lookup(layer, chord),
+                    "layer={layer} chord={:?}", chord_to_string(chord));
+            }
+            for bit in 0..8 {
+                let switch = SwitchSet(1 << bit);
+                assert_eq!(SampleLayout::unchorded_key(layer, switch), SampleLayers::unchorded_key(layer, switch));
+            }
+        }
+        for (chords, _) in &layout.sequences {
+            assert_eq!(SampleLayout::sequence(chords), SampleLayers::sequence(chords));
+        }
+    }
+
+    #[test]
+    fn actions() {
+        use LayerOutcome::*;
+        let layout = parse(r#"
+            layer 1 fancy
+              mask __^^
+              unchorded ___^ mouse:left   # comment
+              ^^^^ q
+              ^^^_ shift+ralt+e
+              ^^_^ release Ctrl+shift_flag
+              ^^_v nothing
+              %%%% clear
+              %%%^ caps-word
+              %%%v leader
+              %%_% briefs
+              %%^% undo
+              v^_v switch 2 pointer:toggle
+              v^_% switch 0
+              v^__ ctrl+mouse:press:middle
+              v^^_ mouse:drag:left
+              v^^^ wheel:-3
+              v^^v pointer:off
+              v^vv media:0xE9
+              v^v_ press media:mute
+              v_vv media:Volume_Down
+              v_v_ press system:sleep
+              v_v^ system:0x83
+              _vv_ once 1
+              _^^_ temp ctrl
+              %_^^ toggle alt+gui
+              default from 0 shift
+              ^^^v from 0 shift
+              %_%% text "say \"hi\" # not a comment\n"  # a comment
+              v_%% unicode ż
+              vv%% unicode U+1F980
+              %%%_ unicode-method windows
+              v_^% char @
+              v_^^ char U+0023
+              vv^% host-layout fr
+              __^_ tap SPACE hold ctrl
+              __^^ tap layer 2 hold ralt+shift
+              v_^_ push 2 toggle
+              vv^_ push 0 one-shot
+            layer 2
+              transparent
+              fallback 1 0
+              default swallow
+            sequence ___^ clear
+            sequence ___^ ___v ^^^^ ^^^^ shift+E  # comment
+        "#).unwrap();
+        assert_eq!(layout.layers.len(), 3);
+        assert!(!layout.layers[1].info.transparent);
+        assert!(layout.layers[2].info.transparent);
+        assert_eq!(layout.layers[2].fallbacks, [1, 0]);
+        assert_eq!(layout.layers[1].info.fallback, LayerFallback::EmitWithMask { layer: 0, mask: SHIFT_FLAG });
+        assert_eq!(layout.layers[2].info.fallback, LayerFallback::Swallow);
+        assert_eq!(layout.layers[0], Layer::default());
+        let layer = &layout.layers[1];
+        assert_eq!(layer.name.as_deref(), Some("fancy"));
+        assert_eq!(layer.info.unchorded_mask, SwitchSet(chord!("__^^")));
+        assert_eq!(layer.unchorded, [(chord!("___^"), MOUSE_LEFT)]);
+        assert_eq!(layer.chords, [
+            (chord!("^^^^"), Emit(Hit(Key(Q)))),
+            (chord!("^^^_"), Emit(Hit(Key(E | SHIFT_FLAG | RIGHT_ALT_FLAG)))),
+            (chord!("^^_^"), Emit(UsbOutcome::KeyRelease(Key(CTRL_FLAG | SHIFT_FLAG)))),
+            (chord!("^^_v"), Emit(UsbOutcome::Nothing)),
+            (chord!("%%%%"), ClearState),
+            (chord!("%%%^"), CapsWord),
+            (chord!("%%%v"), Leader),
+            (chord!("%%_%"), Briefs),
+            (chord!("%%^%"), Undo),
+            (chord!("v^_v"), LayerSwitchAndEmit { layer: 2, emit: Hit(MOUSE_POINTER_TOGGLE) }),
+            (chord!("v^_%"), LayerSwitchAndEmit { layer: 0, emit: UsbOutcome::Nothing }),
+            (chord!("v^__"), Emit(Hit(Action::Mouse(MouseAction::Press(MouseButton::Middle), CTRL_FLAG)))),
+            (chord!("v^^_"), Emit(Hit(Action::Mouse(MouseAction::DragToggle(MouseButton::Left), 0)))),
+            (chord!("v^^^"), Emit(Hit(Action::Mouse(MouseAction::Wheel(-3), 0)))),
+            (chord!("v^^v"), Emit(Hit(Action::Mouse(MouseAction::Pointer(PointerMode::Disable), 0)))),
+            (chord!("v^vv"), Emit(Hit(Action::Media(0xe9)))),
+            (chord!("v^v_"), Emit(UsbOutcome::KeyPress(Action::Media(keycodes::media::MUTE)))),
+            (chord!("v_vv"), Emit(Hit(Action::Media(keycodes::media::VOLUME_DOWN)))),
+            (chord!("v_v_"), Emit(UsbOutcome::KeyPress(Action::System(keycodes::system::SLEEP)))),
+            (chord!("v_v^"), Emit(Hit(Action::System(0x83)))),
+            (chord!("_vv_"), TemporaryLayerSwitch { layer: 1 }),
+            (chord!("_^^_"), TemporaryPlusMask { mask: CTRL_FLAG }),
+            (chord!("%_^^"), TogglePlusMask { mask: ALT_FLAG | GUI_FLAG }),
+            (chord!("^^^v"), FromOtherPlusMask { layer: 0, mask: SHIFT_FLAG }),
+            (chord!("%_%%"), EmitText("say \"hi\" # not a comment\n")),
+            (chord!("v_%%"), EmitUnicode('ż')),
+            (chord!("vv%%"), EmitUnicode('🦀')),
+            (chord!("%%%_"), SetUnicodeMethod(unicode::Method::WindowsAltNumpad)),
+            (chord!("v_^%"), EmitChar('@')),
+            (chord!("v_^^"), EmitChar('#')),
+            (chord!("vv^%"), SetHostLayout(HostLayout::French)),
+            (chord!("__^_"), TapHold { tap: TapHoldAction::Key(Key(SPACE)), hold: TapHoldAction::Key(Key(CTRL_FLAG)) }),
+            (chord!("__^^"), TapHold {
+                tap: TapHoldAction::Layer(2),
+                hold: TapHoldAction::Key(Key(RIGHT_ALT_FLAG | SHIFT_FLAG)),
+            }),
+            (chord!("v_^_"), PushLayer { layer: 2, mode: LayerMode::Toggle }),
+            (chord!("vv^_"), PushLayer { layer: 0, mode: LayerMode::OneShot }),
+        ]);
+        assert_eq!(layout.sequences, [
+            (vec![chord!("___^")], ClearState),
+            (vec![chord!("___^"), chord!("___v"), chord!("^^^^"), chord!("^^^^")], Emit(Hit(Key(E | SHIFT_FLAG)))),
+        ]);
+    }
+
+    #[test]
+    fn finger_labels() {
+        let layout = parse("layer 0\n  I:^ E\n  mask I:v\nsequence P:% I:^ ___v A").unwrap();
+        assert_eq!(layout.layers[0].chords, [(chord!("I:^"), LayerOutcome::Emit(Hit(Key(E))))]);
+        assert_eq!(layout.layers[0].info.unchorded_mask, SwitchSet(chord!("I:v")));
+        assert_eq!(layout.sequences, [
+            (vec![chord!("P:%"), chord!("I:^"), chord!("___v")], LayerOutcome::Emit(Hit(Key(A)))),
+        ]);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        use ErrorKind::*;
+        assert_eq!(err_at("layer 0\n  ___^ FOO"),
+            (2, 8, UnknownKey("FOO".into())));
+        assert_eq!(err_at("layer 0\n  __^ E"),
+            (2, 3, MalformedChord("__^".into(), notation::ErrorKind::WrongLength)));
+        assert_eq!(err_at("layer 0\n  __x^ E"),
+            (2, 3, MalformedChord("__x^".into(), notation::ErrorKind::UnknownCrumb)));
+        assert_eq!(err_at("layer 0\n  __^_v E"),
+            (2, 3, MalformedChord("__^_v".into(), notation::ErrorKind::NotFourPositions)));
+        assert_eq!(err_at("layer 0\n  ___^ shift+E+A"),
+            (2, 8, UnknownKey("shift+E+A".into())));
+        assert_eq!(err_at("layer 0\n  ___^ mouse:left+E"),
+            (2, 8, UnknownKey("mouse:left+E".into())));
+        assert_eq!(err_at("layer 0\n  ___^ mouse:drag:up"),
+            (2, 8, UnknownKey("mouse:drag:up".into())));
+        assert_eq!(err_at("layer 0\n  ___^ wheel:200"),
+            (2, 8, UnknownKey("wheel:200".into())));
+        assert_eq!(err_at("layer 0\n  ___^ shift+media:0xE9"),
+            (2, 8, UnknownKey("shift+media:0xE9".into())));
+        assert_eq!(err_at("layer 0\n  ___^ media:louder"),
+            (2, 8, UnknownKey("media:louder".into())));
+        assert_eq!(err_at("layer 0\n  ___^ system:0x100"),
+            (2, 8, UnknownKey("system:0x100".into())));
+        assert_eq!(err_at("layer 0\n  ___^ once x"),
+            (2, 13, ExpectedNumber("x".into())));
+        assert_eq!(err_at("layer 0\n  ___^ once"),
+            (2, 12, MissingArgument("layer number")));
+        assert_eq!(err_at("layer 0\n  ___^ E E"),
+            (2, 10, UnexpectedToken("E".into())));
+        assert_eq!(err_at("layer 0\n  ___^ E\n  ___^ T"),
+            (3, 3, DuplicateChord("___^".into())));
+        assert_eq!(err_at("layer 0\nlayer 0"),
+            (2, 1, DuplicateLayer(0)));
+        assert_eq!(err_at("layer 0\n  unchorded __%_ E"),
+            (2, 13, NotSingleSwitch("__%_".into())));
+        assert_eq!(err_at("layer 0\n  ___^ text \"abc"),
+            (2, 13, MalformedString("\"abc".into())));
+        assert_eq!(err_at("layer 0\n  ___^ text \"a\\qc\""),
+            (2, 13, MalformedString("\"a\\qc\"".into())));
+        assert_eq!(err_at("layer 0\n  ___^ text abc"),
+            (2, 13, MalformedString("abc".into())));
+        assert_eq!(err_at("layer 0\n  ___^ unicode ab"),
+            (2, 16, MalformedCharacter("ab".into())));
+        assert_eq!(err_at("layer 0\n  ___^ unicode U+D800"),
+            (2, 16, MalformedCharacter("U+D800".into())));
+        assert_eq!(err_at("layer 0\n  ___^ unicode-method bsd"),
+            (2, 23, UnknownUnicodeMethod("bsd".into())));
+        assert_eq!(err_at("layer 0\n  ___^ char"),
+            (2, 12, MissingArgument("character")));
+        assert_eq!(err_at("layer 0\n  ___^ host-layout dvorak"),
+            (2, 20, UnknownHostLayout("dvorak".into())));
+        assert_eq!(err_at("layer 0\n  ___^ tap E"),
+            (2, 13, MissingArgument("hold")));
+        assert_eq!(err_at("layer 0\n  ___^ tap E tap T"),
+            (2, 14, UnexpectedToken("tap".into())));
+        assert_eq!(err_at("layer 0\n  ___^ tap layer x hold E"),
+            (2, 18, ExpectedNumber("x".into())));
+        assert_eq!(err_at("layer 0\n  ___^ push 1 sticky"),
+            (2, 15, UnknownLayerMode("sticky".into())));
+        assert_eq!(err_at("layer 0\n  ___^ push 1"),
+            (2, 14, MissingArgument("layer mode")));
+        assert_eq!(err_at("layer 0\n  fallback"),
+            (2, 11, MissingArgument("layer number")));
+        assert_eq!(err_at("layer 0\n  fallback 1 x"),
+            (2, 14, ExpectedNumber("x".into())));
+        assert_eq!(err_at("layer 0\n  default to 1"),
+            (2, 11, UnexpectedToken("to".into())));
+        assert_eq!(err_at("layer 0\n  default layer"),
+            (2, 16, MissingArgument("layer number")));
+        assert_eq!(err_at("sequence E"),
+            (1, 10, MalformedChord("E".into(), notation::ErrorKind::UnknownCrumb)));
+        assert_eq!(err_at("sequence ___^ ___^v E"),
+            (1, 15, MalformedChord("___^v".into(), notation::ErrorKind::NotFourPositions)));
+        assert_eq!(err_at("sequence ___^"),
+            (1, 14, MissingArgument("action")));
+        assert_eq!(err_at("sequence ___^ ___^ ___^ ___^ ___^ E"),
+            (1, 30, SequenceTooLong));
+        assert_eq!(err_at("sequence ___^ E\nsequence ___^ T"),
+            (2, 1, DuplicateSequence));
+        assert_eq!(err_at("layer 0\n  unchorded ___^ E\n  unchorded ___^ T"),
+            (3, 13, DuplicateUnchorded("___^".into())));
+        assert_eq!(err_at("  ___^ E"),
+            (1, 3, OutsideLayer));
+        assert_eq!(parse("layer 0\n  ___^ FOO").unwrap_err().to_string(),
+            "2:8: unknown key: \"FOO\"");
+    }
+
+    #[test]
+    fn rust_output() {
+        let layout = parse(r#"
+            layer 0
+              ___^ shift+E
+              _^^_ text "-> \"x\""
+              _vv_ unicode-method mac
+              _vv^ host-layout pl
+              _vvv char ż
+              __^_ tap SPACE hold ctrl
+              v^_v switch 1 pointer:toggle
+              v^^_ ctrl+wheel:5
+              v^^^ media:0xCD
+              v_^_ push 1 momentary
+            layer 1 mouse
+              transparent
+              fallback 2 0
+              mask __^^
+              unchorded ___^ mouse:left
+              default from 0
+            sequence _^_v ^___ text "git status\n"
+        "#).unwrap();
+        let rust = layout.to_rust("Layout");
+        for expected in [
+            "layout! {\n    pub struct Layout;\n    type Action = Action;\n",
+            "    layer 1 { // \"mouse\"\n",
+            "        unchorded_mask: \"__^^\",\n",
+            "        transparent: true,\n",
+            "        fallback: LayerFallback::EmitWithMask { layer: 0, mask: 0 },\n",
+            "        fallbacks: [2, 0],\n",
+            "        unchorded \"___^\" => Action::Mouse(MouseAction::Button(MouseButton::Left), 0),\n",
+            "        \"___^\" => Emit(Hit(Key(E | SHIFT_FLAG))),\n",
+            "        \"v^_v\" => LayerSwitchAndEmit { layer: 1, emit: Hit(Action::Mouse(MouseAction::Pointer(PointerMode::Toggle), 0)) },\n",
+            "        \"v^^_\" => Emit(Hit(Action::Mouse(MouseAction::Wheel(5), CTRL_FLAG))),\n",
+            "        \"v^^^\" => Emit(Hit(Action::Media(0xcd))),\n",
+            "        \"_^^_\" => EmitText(\"-> \\\"x\\\"\"),\n",
+            "        \"_vv_\" => SetUnicodeMethod(unicode::Method::MacHexInput),\n",
+            "        \"_vv^\" => SetHostLayout(host_layout::HostLayout::PolishProgrammer),\n",
+            "        \"_vvv\" => EmitChar('ż'),\n",
+            "        \"__^_\" => TapHold { tap: TapHoldAction::Key(Key(SPACE)), hold: TapHoldAction::Key(Key(CTRL_FLAG)) },\n",
+            "        \"v_^_\" => PushLayer { layer: 1, mode: layer_stack::LayerMode::Momentary },\n",
+            "            (&[chord!(\"_^_v\"), chord!(\"^___\")], EmitText(\"git status\\n\")),\n",
+        ] {
+            assert!(rust.contains(expected), "missing {expected:?} in:\n{rust}");
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// Generated by the clawtype-chords layout compiler. Do not edit.
+
+#[allow(unused_imports)]
+use clawtype_macros::{chord, layout};
+#[allow(unused_imports)]
+use clawtype_chords::{
+    LayerOutcome::*,
+    UsbOutcome::{KeyHit as Hit, KeyPress as Press, KeyRelease as Release, Nothing},
+    action::{Action::{self, Key}, MouseAction, MouseButton, PointerMode},
+    keycodes::*,
+    LayerFallback, TapHoldAction, host_layout, layer_stack, unicode,
+};
+
+layout! {
+    pub struct SampleLayout;
+    type Action = Action;
+
+    layer 0 {
+        "_^_%" => Emit(Hit(Key(UP))),
+        "_v_%" => Emit(Hit(Key(DOWN))),
+        "^__%" => Emit(Hit(Key(LEFT))),
+        "v__%" => Emit(Hit(Key(RIGHT))),
+        "^^_%" => Emit(Hit(Key(PAGE_UP))),
+        "vv_v" => Emit(Hit(Key(PAGE_DOWN))),
+        "__^_" => Emit(Hit(Key(SPACE))),
+        "_^__" => Emit(Hit(Key(BACKSPACE))),
+        "___^" => Emit(Hit(Key(E))),
+        "___v" => Emit(Hit(Key(T))),
+        "__v_" => Emit(Hit(Key(A))),
+        "___%" => Emit(Hit(Key(I))),
+        "__%_" => Emit(Hit(Key(O))),
+        "_v__" => Emit(Hit(Key(N))),
+        "^___" => Emit(Hit(Key(S))),
+        "_%__" => Emit(Hit(Key(H))),
+        "v___" => Emit(Hit(Key(R))),
+        "%___" => Emit(Hit(Key(L))),
+        "__^^" => Emit(Hit(Key(D))),
+        "__vv" => Emit(Hit(Key(C))),
+        "__^v" => Emit(Hit(Key(U))),
+        "^^__" => Emit(Hit(Key(M))),
+        "_vv_" => TemporaryLayerSwitch { layer: 1 },
+        "_^^_" => TemporaryPlusMask { mask: CTRL_FLAG },
+        "%%__" => TemporaryPlusMask { mask: ALT_FLAG },
+        "%%_^" => TemporaryPlusMask { mask: RIGHT_ALT_FLAG },
+        "_%%_" => TemporaryPlusMask { mask: GUI_FLAG },
+        "_^_^" => Emit(Hit(Key(TAB))),
+        "__^%" => Emit(Hit(Key(W))),
+        "_^_v" => Emit(Hit(Key(G))),
+        "__%v" => Emit(Hit(Key(F))),
+        "__%%" => Emit(Hit(Key(Y))),
+        "_v_v" => Emit(Hit(Key(P))),
+        "v__v" => Emit(Hit(Key(B))),
+        "^__^" => Emit(Hit(Key(COMMA))),
+        "_^^^" => Emit(Hit(Key(PERIOD))),
+        "_vvv" => Emit(Hit(Key(V))),
+        "_%_%" => Emit(Hit(Key(ENTER))),
+        "vvvv" => Emit(Hit(Key(ESC))),
+        "^__v" => Emit(Hit(Key(K))),
+        "%__%" => Emit(Hit(Key(QUOTE))),
+        "%__v" => Emit(Hit(Key(QUOTE | SHIFT_FLAG))),
+        "vvv_" => Emit(Hit(Key(MINUS))),
+        "__v%" => Emit(Hit(Key(X))),
+        "_%%%" => Emit(Hit(Key(J))),
+        "_%_v" => Emit(Hit(Key(SEMICOLON))),
+        "^^^_" => Emit(Hit(Key(KEY_9 | SHIFT_FLAG))),
+        "^_^_" => Emit(Hit(Key(KEY_0 | SHIFT_FLAG))),
+        "^^^^" => Emit(Hit(Key(Q))),
+        "_^^v" => Emit(Hit(Key(SLASH))),
+        "_^^%" => Emit(Hit(Key(Z))),
+        "^^_v" => Emit(Hit(Key(SEMICOLON | SHIFT_FLAG))),
+        "_^%_" => Emit(Hit(Key(KEY_0))),
+        "v_v_" => Emit(Hit(Key(KEY_1))),
+        "%_%_" => Emit(Hit(Key(KEY_2))),
+        "%%%_" => Emit(Hit(Key(KEY_3))),
+        "^^^%" => Emit(Hit(Key(KEY_4))),
+        "_vv%" => Emit(Hit(Key(EQUAL))),
+        "%^__" => Emit(Hit(Key(KEY_4 | SHIFT_FLAG))),
+        "^_%_" => Emit(Hit(Key(LEFT_BRACE | SHIFT_FLAG))),
+        "v_%_" => Emit(Hit(Key(RIGHT_BRACE | SHIFT_FLAG))),
+        "v_v%" => EmitText("->"),
+        "%_%%" => EmitText("Hi, World!\n"),
+        "^_^%" => EmitText("zażółć)"),
+        "v_%%" => EmitUnicode('ż'),
+        "vv%%" => SetUnicodeMethod(unicode::Method::MacHexInput),
+        "v_^%" => EmitChar('@'),
+        "vv^%" => SetHostLayout(host_layout::HostLayout::German),
+        "v_^_" => PushLayer { layer: 3, mode: layer_stack::LayerMode::Toggle },
+        "vv^_" => PushLayer { layer: 3, mode: layer_stack::LayerMode::Locked },
+        "vv_^" => PushLayer { layer: 3, mode: layer_stack::LayerMode::Momentary },
+        "%%%%" => ClearState,
+        "%%%^" => CapsWord,
+        "%%%v" => Leader,
+        "%%^%" => Undo,
+        "v^_v" => LayerSwitchAndEmit { layer: 2, emit: Hit(Action::Mouse(MouseAction::Pointer(PointerMode::Toggle), 0)) },
+    }
+
+    layer 1 { // "shift"
+        fallback: LayerFallback::EmitWithMask { layer: 0, mask: SHIFT_FLAG },
+        "_^%_" => Emit(Hit(Key(KEY_5))),
+        "v_v_" => Emit(Hit(Key(KEY_6))),
+        "%_%_" => Emit(Hit(Key(KEY_7))),
+        "%%%_" => Emit(Hit(Key(KEY_8))),
+        "^^^%" => Emit(Hit(Key(KEY_9))),
+        "^__^" => Emit(Hit(Key(SLASH | SHIFT_FLAG))),
+        "_^^^" => Emit(Hit(Key(KEY_1 | SHIFT_FLAG))),
+        "vvv_" => Emit(Hit(Key(MINUS | SHIFT_FLAG))),
+        "%__%" => Emit(Hit(Key(TILDE))),
+        "^^^_" => Emit(Hit(Key(LEFT_BRACE))),
+        "^_^_" => Emit(Hit(Key(RIGHT_BRACE))),
+        "_vv%" => Emit(Hit(Key(EQUAL | SHIFT_FLAG))),
+        "^_%_" => Emit(Hit(Key(COMMA | SHIFT_FLAG))),
+        "v_%_" => Emit(Hit(Key(PERIOD | SHIFT_FLAG))),
+        "%__v" => Emit(Hit(Key(KEY_7 | SHIFT_FLAG))),
+        "_%_v" => Emit(Hit(Key(KEY_2 | SHIFT_FLAG))),
+        "_^^v" => Emit(Hit(Key(BACKSLASH))),
+        "^^_v" => Emit(Hit(Key(BACKSLASH | SHIFT_FLAG))),
+        "%^__" => Emit(Hit(Key(TILDE | SHIFT_FLAG))),
+        "^^_%" => Emit(Hit(Key(KEY_6 | SHIFT_FLAG))),
+        "_^__" => Emit(Hit(Key(DELETE))),
+        "_^_%" => Emit(Hit(Key(HOME))),
+        "_v_%" => Emit(Hit(Key(END))),
+    }
+
+    layer 2 { // "test"
+        unchorded_mask: "__^^",
+        unchorded "___^" => Action::Mouse(MouseAction::Button(MouseButton::Left), 0),
+        unchorded "__^_" => Action::Mouse(MouseAction::Button(MouseButton::Right), 0),
+        "^^__" => TemporaryPlusMask { mask: CTRL_FLAG },
+        "v^_v" => LayerSwitchAndEmit { layer: 0, emit: Hit(Action::Mouse(MouseAction::Pointer(PointerMode::Toggle), 0)) },
+    }
+
+    layer 3 { // "num"
+        transparent: true,
+        "___^" => Emit(Hit(Key(KEY_1))),
+        "__^_" => Emit(Hit(Key(KEY_2))),
+        "_^__" => Emit(Hit(Key(KEY_3))),
+        "^___" => Emit(Hit(Key(KEY_4))),
+    }
+
+    fn sequence(chords: &[u8]) -> clawtype_chords::SequenceMatch<Action> {
+        clawtype_chords::lookup_sequence(chords, &[
+            (&[chord!("_^_v")], EmitText("git ")),
+            (&[chord!("_^_v"), chord!("^___")], EmitText("git status\n")),
+            (&[chord!("_^_v"), chord!("__^^")], EmitText("git diff\n")),
+            (&[chord!("___v"), chord!("__%_"), chord!("__^^")], EmitText("TODO: ")),
+        ])
+    }
+}
@@ -0,0 +1,374 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Translating characters to keys, according to the keyboard layout
+//! selected in the host OS.
+//!
+//! The tables follow the Windows variants of the layouts. Characters on
+//! dead keys are typed by following the dead key with a Space.
+
+use crate::keycodes::{self, *};
+
+const ALT_GR: KeyWithFlags = RIGHT_ALT_FLAG;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum HostLayout {
+    /// US QWERTY.
+    #[default]
+    Us,
+    /// UK QWERTY.
+    Uk,
+    /// German QWERTZ.
+    German,
+    /// French AZERTY.
+    French,
+    /// Polish (Programmers) - US QWERTY, with Polish letters on AltGr.
+    PolishProgrammer,
+}
+
+/// How to type a character on a host layout.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub key: KeyWithFlags,
+    /// The key is a dead key, and must be followed by a Space.
+    pub dead: bool,
+}
+
+impl HostLayout {
+    /// Returns `None` if the character can't be typed on the layout.
+    pub fn stroke(self, c: char) -> Option<Stroke> {
+        match self {
+            Self::Us => us(c),
+            Self::Uk => uk(c),
+            Self::German => german(c),
+            Self::French => french(c),
+            Self::PolishProgrammer => polish_programmer(c),
+        }
+    }
+}
+
+const fn key(key: KeyWithFlags) -> Option<Stroke> {
+    Some(Stroke { key, dead: false })
+}
+
+const fn dead(key: KeyWithFlags) -> Option<Stroke> {
+    Some(Stroke { key, dead: true })
+}
+
+fn us(c: char) -> Option<Stroke> {
+    u8::try_from(c).ok().and_then(keycodes::from_ascii).and_then(key)
+}
+
+fn uk(c: char) -> Option<Stroke> {
+    match c {
+        '"' => key(KEY_2 | SHIFT_FLAG),
+        '£' => key(KEY_3 | SHIFT_FLAG),
+        '@' => key(QUOTE | SHIFT_FLAG),
+        '#' => key(NON_US_NUM),
+        '~' => key(NON_US_NUM | SHIFT_FLAG),
+        '\\' => key(NON_US_BS),
+        '|' => key(NON_US_BS | SHIFT_FLAG),
+        '¬' => key(TILDE | SHIFT_FLAG),
+        '€' => key(KEY_4 | ALT_GR),
+        _ => us(c),
+    }
+}
+
+fn german(c: char) -> Option<Stroke> {
+    match c {
+        'y' => key(Z),
+        'Y' => key(Z | SHIFT_FLAG),
+        'z' => key(Y),
+        'Z' => key(Y | SHIFT_FLAG),
+        '"' => key(KEY_2 | SHIFT_FLAG),
+        '§' => key(KEY_3 | SHIFT_FLAG),
+        '&' => key(KEY_6 | SHIFT_FLAG),
+        '/' => key(KEY_7 | SHIFT_FLAG),
+        '(' => key(KEY_8 | SHIFT_FLAG),
+        ')' => key(KEY_9 | SHIFT_FLAG),
+        '=' => key(KEY_0 | SHIFT_FLAG),
+        '{' => key(KEY_7 | ALT_GR),
+        '[' => key(KEY_8 | ALT_GR),
+        ']' => key(KEY_9 | ALT_GR),
+        '}' => key(KEY_0 | ALT_GR),
+        'ß' => key(MINUS),
+        '?' => key(MINUS | SHIFT_FLAG),
+        '\\' => key(MINUS | ALT_GR),
+        '`' => dead(EQUAL | SHIFT_FLAG),
+        'ü' => key(LEFT_BRACE),
+        'Ü' => key(LEFT_BRACE | SHIFT_FLAG),
+        '+' => key(RIGHT_BRACE),
+        '*' => key(RIGHT_BRACE | SHIFT_FLAG),
+        '~' => key(RIGHT_BRACE | ALT_GR),
+        'ö' => key(SEMICOLON),
+        'Ö' => key(SEMICOLON | SHIFT_FLAG),
+        'ä' => key(QUOTE),
+        'Ä' => key(QUOTE | SHIFT_FLAG),
+        '#' => key(NON_US_NUM),
+        '\'' => key(NON_US_NUM | SHIFT_FLAG),
+        '^' => dead(TILDE),
+        '°' => key(TILDE | SHIFT_FLAG),
+        ';' => key(COMMA | SHIFT_FLAG),
+        ':' => key(PERIOD | SHIFT_FLAG),
+        '-' => key(SLASH),
+        '_' => key(SLASH | SHIFT_FLAG),
+        '<' => key(NON_US_BS),
+        '>' => key(NON_US_BS | SHIFT_FLAG),
+        '|' => key(NON_US_BS | ALT_GR),
+        '@' => key(Q | ALT_GR),
+        '€' => key(E | ALT_GR),
+        'µ' => key(M | ALT_GR),
+        _ => us(c),
+    }
+}
+
+fn french(c: char) -> Option<Stroke> {
+    match c {
+        'a' => key(Q),
+        'A' => key(Q | SHIFT_FLAG),
+        'q' => key(A),
+        'Q' => key(A | SHIFT_FLAG),
+        'z' => key(W),
+        'Z' => key(W | SHIFT_FLAG),
+        'w' => key(Z),
+        'W' => key(Z | SHIFT_FLAG),
+        'm' => key(SEMICOLON),
+        'M' => key(SEMICOLON | SHIFT_FLAG),
+        // digits are shifted
+        '1'..='9' => key((KEY_1 + (c as u8 - b'1') as KeyWithFlags) | SHIFT_FLAG),
+        '0' => key(KEY_0 | SHIFT_FLAG),
+        '&' => key(KEY_1),
+        'é' => key(KEY_2),
+        '"' => key(KEY_3),
+        '\'' => key(KEY_4),
+        '(' => key(KEY_5),
+        '-' => key(KEY_6),
+        'è' => key(KEY_7),
+        '_' => key(KEY_8),
+        'ç' => key(KEY_9),
+        'à' => key(KEY_0),
+        ')' => key(MINUS),
+        '°' => key(MINUS | SHIFT_FLAG),
+        '=' => key(EQUAL),
+        '+' => key(EQUAL | SHIFT_FLAG),
+        '~' => dead(KEY_2 | ALT_GR),
+        '#' => key(KEY_3 | ALT_GR),
+        '{' => key(KEY_4 | ALT_GR),
+        '[' => key(KEY_5 | ALT_GR),
+        '|' => key(KEY_6 | ALT_GR),
+        '`' => dead(KEY_7 | ALT_GR),
+        '\\' => key(KEY_8 | ALT_GR),
+        '^' => key(KEY_9 | ALT_GR),
+        '@' => key(KEY_0 | ALT_GR),
+        ']' => key(MINUS | ALT_GR),
+        '}' => key(EQUAL | ALT_GR),
+        '$' => key(RIGHT_BRACE),
+        '£' => key(RIGHT_BRACE | SHIFT_FLAG),
+        'ù' => key(QUOTE),
+        '%' => key(QUOTE | SHIFT_FLAG),
+        '*' => key(NON_US_NUM),
+        'µ' => key(NON_US_NUM | SHIFT_FLAG),
+        '²' => key(TILDE),
+        ',' => key(M),
+        '?' => key(M | SHIFT_FLAG),
+        ';' => key(COMMA),
+        '.' => key(COMMA | SHIFT_FLAG),
+        ':' => key(PERIOD),
+        '/' => key(PERIOD | SHIFT_FLAG),
+        '!' => key(SLASH),
+        '§' => key(SLASH | SHIFT_FLAG),
+        '<' => key(NON_US_BS),
+        '>' => key(NON_US_BS | SHIFT_FLAG),
+        '€' => key(E | ALT_GR),
+        _ => us(c),
+    }
+}
+
+fn polish_programmer(c: char) -> Option<Stroke> {
+    let letter = |k| key(k | ALT_GR);
+    match c {
+        // dead key, for Polish letters: ~a => ą, etc.
+        '~' => dead(TILDE | SHIFT_FLAG),
+        'ą' => letter(A),
+        'ć' => letter(C),
+        'ę' => letter(E),
+        'ł' => letter(L),
+        'ń' => letter(N),
+        'ó' => letter(O),
+        'ś' => letter(S),
+        'ź' => letter(X),
+        'ż' => letter(Z),
+        'Ą' => letter(A | SHIFT_FLAG),
+        'Ć' => letter(C | SHIFT_FLAG),
+        'Ę' => letter(E | SHIFT_FLAG),
+        'Ł' => letter(L | SHIFT_FLAG),
+        'Ń' => letter(N | SHIFT_FLAG),
+        'Ó' => letter(O | SHIFT_FLAG),
+        'Ś' => letter(S | SHIFT_FLAG),
+        'Ź' => letter(X | SHIFT_FLAG),
+        'Ż' => letter(Z | SHIFT_FLAG),
+        '€' => letter(U),
+        _ => us(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [HostLayout; 5] = [
+        HostLayout::Us,
+        HostLayout::Uk,
+        HostLayout::German,
+        HostLayout::French,
+        HostLayout::PolishProgrammer,
+    ];
+
+    fn printable_ascii() -> impl Iterator<Item = char> {
+        (b' '..=b'~').map(char::from)
+    }
+
+    #[test]
+    fn every_printable_ascii_is_typeable() {
+        for layout in ALL {
+            for c in printable_ascii().chain(['\n', '\t']) {
+                assert!(layout.stroke(c).is_some(), "{layout:?}: {c:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn no_two_characters_on_same_stroke() {
+        for layout in ALL {
+            let mut seen = std::collections::HashMap::new();
+            for c in printable_ascii() {
+                let Some(stroke) = layout.stroke(c) else { continue };
+                let key = (stroke.key, stroke.dead);
+                if let Some(other) = seen.insert(key, c) {
+                    panic!("{layout:?}: {c:?} and {other:?} on {:#06x}", stroke.key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn letters_are_shifted_for_uppercase() {
+        for layout in ALL {
+            for c in 'a'..='z' {
+                let lower = layout.stroke(c).unwrap();
+                let upper = layout.stroke(c.to_ascii_uppercase()).unwrap();
+                assert_eq!(upper.key, lower.key | SHIFT_FLAG, "{layout:?}: {c:?}");
+                assert!(!lower.dead);
+            }
+        }
+    }
+
+    #[test]
+    fn only_modifiers_are_shift_and_alt_gr() {
+        for layout in ALL {
+            for c in printable_ascii() {
+                let stroke = layout.stroke(c).unwrap();
+                assert_eq!(stroke.key & FLAG_MASK & !(SHIFT_FLAG | ALT_GR), 0, "{layout:?}: {c:?}");
+            }
+        }
+    }
+
+    fn keys(layout: HostLayout, text: &str) -> Vec<(KeyWithFlags, bool)> {
+        text.chars()
+            .map(|c| layout.stroke(c).unwrap())
+            .map(|s| (s.key, s.dead))
+            .collect()
+    }
+
+    #[test]
+    fn us() {
+        for c in printable_ascii() {
+            let stroke = HostLayout::Us.stroke(c).unwrap();
+            assert_eq!(Some(stroke.key), keycodes::from_ascii(c as u8));
+            assert!(!stroke.dead);
+        }
+        assert_eq!(HostLayout::Us.stroke('ż'), None);
+    }
+
+    #[test]
+    fn uk() {
+        assert_eq!(keys(HostLayout::Uk, "@\"#~\\|`"), [
+            (QUOTE | SHIFT_FLAG, false),
+            (KEY_2 | SHIFT_FLAG, false),
+            (NON_US_NUM, false),
+            (NON_US_NUM | SHIFT_FLAG, false),
+            (NON_US_BS, false),
+            (NON_US_BS | SHIFT_FLAG, false),
+            (TILDE, false),
+        ]);
+        assert_eq!(keys(HostLayout::Uk, "£"), [(KEY_3 | SHIFT_FLAG, false)]);
+    }
+
+    #[test]
+    fn german() {
+        assert_eq!(keys(HostLayout::German, "yZ@-/\\^`~{"), [
+            (Z, false),
+            (Y | SHIFT_FLAG, false),
+            (Q | ALT_GR, false),
+            (SLASH, false),
+            (KEY_7 | SHIFT_FLAG, false),
+            (MINUS | ALT_GR, false),
+            (TILDE, true),
+            (EQUAL | SHIFT_FLAG, true),
+            (RIGHT_BRACE | ALT_GR, false),
+            (KEY_7 | ALT_GR, false),
+        ]);
+        assert_eq!(keys(HostLayout::German, "äßÜ"), [
+            (QUOTE, false),
+            (MINUS, false),
+            (LEFT_BRACE | SHIFT_FLAG, false),
+        ]);
+    }
+
+    #[test]
+    fn french() {
+        assert_eq!(keys(HostLayout::French, "aqzwm1,.!~^@"), [
+            (Q, false),
+            (A, false),
+            (W, false),
+            (Z, false),
+            (SEMICOLON, false),
+            (KEY_1 | SHIFT_FLAG, false),
+            (M, false),
+            (COMMA | SHIFT_FLAG, false),
+            (SLASH, false),
+            (KEY_2 | ALT_GR, true),
+            (KEY_9 | ALT_GR, false),
+            (KEY_0 | ALT_GR, false),
+        ]);
+        assert_eq!(keys(HostLayout::French, "éàù"), [
+            (KEY_2, false),
+            (KEY_0, false),
+            (QUOTE, false),
+        ]);
+    }
+
+    #[test]
+    fn polish_programmer() {
+        assert_eq!(keys(HostLayout::PolishProgrammer, "@~`żŹ"), [
+            (KEY_2 | SHIFT_FLAG, false),
+            (TILDE | SHIFT_FLAG, true),
+            (TILDE, false),
+            (Z | ALT_GR, false),
+            (X | ALT_GR | SHIFT_FLAG, false),
+        ]);
+    }
+}
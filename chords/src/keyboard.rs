@@ -0,0 +1,507 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Keyboard keys held on the host, as sent in HID keyboard reports.
+
+use core::fmt;
+
+use crate::keycodes::{KeyWithFlags, FLAG_MASK};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    AlreadyPressed,
+    AlreadyReleased,
+    TooManyKeysPressed,
+    UnsupportedKey,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        f.write_str(match self {
+            AlreadyPressed => "key is already marked as pressed",
+            AlreadyReleased => "key is already marked as released",
+            TooManyKeysPressed => "too many keys are already marked as pressed",
+            UnsupportedKey => "key is outside of the report",
+        })
+    }
+}
+
+/// Length of a boot keyboard report, like `usbd_hid`'s `KeyboardReport`:
+/// the modifiers, a reserved byte, and 6 keys.
+pub const REPORT_LEN: usize = 8;
+
+/// Keys currently pressed on the keyboard, sent as a whole in each report.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateReport {
+    /// Modifiers pressed as keys, like `0xE0` for Left Control.
+    modifier: u8,
+    keycodes: [u8; 6],
+    /// Modifier flags pressed together with the key in the same slot.
+    key_flags: [u8; 6],
+    /// How many times each modifier flag is pressed without a key.
+    flag_counts: [u8; 8],
+}
+
+impl StateReport {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The report as sent to the host.
+    pub fn to_bytes(&self) -> [u8; REPORT_LEN] {
+        let mut bytes = [0; REPORT_LEN];
+        bytes[0] = self.modifier();
+        bytes[2..].copy_from_slice(&self.keycodes);
+        bytes
+    }
+
+    pub fn press(&mut self, key: impl Into<u8>) -> Result<(), Error> {
+        let key = key.into();
+        if let Some(mask) = as_modifier_mask(key) {
+            if self.modifier & mask != 0 {
+                return Err(Error::AlreadyPressed);
+            }
+            self.modifier |= mask;
+            return Ok(());
+        }
+        if self.slot_of(key).is_some() {
+            return Err(Error::AlreadyPressed);
+        }
+        // A released slot is reused, and the other keys stay in place.
+        let Some(slot) = self.keycodes.iter_mut().find(|k| **k == 0) else {
+            return Err(Error::TooManyKeysPressed);
+        };
+        *slot = key;
+        Ok(())
+    }
+
+    pub fn release(&mut self, key: impl Into<u8>) -> Result<(), Error> {
+        let key = key.into();
+        if let Some(mask) = as_modifier_mask(key) {
+            if self.modifier & mask == 0 {
+                return Err(Error::AlreadyReleased);
+            }
+            self.modifier &= !mask;
+            return Ok(());
+        }
+        let Some(i) = self.slot_of(key) else {
+            return Err(Error::AlreadyReleased);
+        };
+        self.keycodes[i] = 0;
+        self.key_flags[i] = 0;
+        Ok(())
+    }
+
+    /// Pressed modifiers, as in the high byte of a [`KeyWithFlags`].
+    pub fn modifier(&self) -> u8 {
+        let counted = (0..8)
+            .filter(|&bit| self.flag_counts[bit] > 0)
+            .fold(0, |acc, bit| acc | (1 << bit));
+        self.key_flags.iter().fold(self.modifier | counted, |acc, flags| acc | flags)
+    }
+
+    /// Presses the key, if any, and its modifier flags. Unlike with
+    /// [`Self::press`], flags already pressed are not an error, e.g. when
+    /// several held keys have Shift.
+    pub fn press_with_flags(&mut self, k: KeyWithFlags) -> Result<(), Error> {
+        let [flags, key] = k.to_be_bytes();
+        if key != 0 {
+            self.press(key)?;
+        }
+        match self.slot_of(key) {
+            Some(i) => self.key_flags[i] = flags,
+            // no key, or a modifier key
+            None => {
+                for (bit, count) in self.flag_counts.iter_mut().enumerate() {
+                    if flags & (1 << bit) != 0 {
+                        *count = count.saturating_add(1);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases the key, if any, together with the flags it was pressed
+    /// with. Flags pressed without a key stay pressed until released as
+    /// many times; releasing them more is not an error.
+    pub fn release_with_flags(&mut self, k: KeyWithFlags) -> Result<(), Error> {
+        let [flags, key] = k.to_be_bytes();
+        if self.slot_of(key).is_some() {
+            return self.release(key);
+        }
+        if key != 0 {
+            self.release(key)?;
+        }
+        for (bit, count) in self.flag_counts.iter_mut().enumerate() {
+            if flags & (1 << bit) != 0 {
+                *count = count.saturating_sub(1);
+            }
+        }
+        Ok(())
+    }
+
+    fn slot_of(&self, key: u8) -> Option<usize> {
+        if key == 0 {
+            return None;
+        }
+        self.keycodes.iter().position(|&k| k == key)
+    }
+}
+
+/// Number of keys in the bitmap of [`NkroReport`], i.e. usages `0x00` to
+/// `0xDF` of the Keyboard page. The modifiers come after them, and are only
+/// in the boot report.
+pub const NKRO_KEYS: usize = 0xE0;
+
+/// Length of the bitmap report of [`NkroReport`].
+pub const NKRO_REPORT_LEN: usize = NKRO_KEYS / 8;
+
+/// Report descriptor of the bitmap report of [`NkroReport`], for an
+/// interface of its own next to a boot keyboard.
+#[rustfmt::skip]
+pub const NKRO_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xa1, 0x01,       // Collection (Application)
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x29, 0xdf,       //   Usage Maximum (0xDF)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0xe0,       //   Report Count (224)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute): keys bitmap
+    0xc0,             // End Collection
+];
+
+/// Keys currently pressed on the keyboard, without the 6 keys limit of
+/// [`StateReport`].
+///
+/// The modifiers and the first 6 keys go to a boot keyboard report, and
+/// only the keys that don't fit there go to the bitmap report. Each of the
+/// two is meant for a separate interface, so a host that only understands
+/// the boot keyboard still gets the first 6 keys, and no key is reported
+/// twice.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NkroReport {
+    boot: StateReport,
+    overflow: [u8; NKRO_REPORT_LEN],
+    /// Modifier flags pressed together with each key in the bitmap.
+    overflow_flags: [u8; NKRO_KEYS],
+}
+
+impl Default for NkroReport {
+    fn default() -> Self {
+        Self {
+            boot: StateReport::default(),
+            overflow: [0; NKRO_REPORT_LEN],
+            overflow_flags: [0; NKRO_KEYS],
+        }
+    }
+}
+
+impl NkroReport {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The boot keyboard report, as sent to the host.
+    pub fn boot_bytes(&self) -> [u8; REPORT_LEN] {
+        self.boot.to_bytes()
+    }
+
+    /// The bitmap report, as sent to the host.
+    pub fn nkro_bytes(&self) -> [u8; NKRO_REPORT_LEN] {
+        self.overflow
+    }
+
+    pub fn press(&mut self, key: impl Into<u8>) -> Result<(), Error> {
+        self.press_with_flags(KeyWithFlags::from(key.into()))
+    }
+
+    pub fn release(&mut self, key: impl Into<u8>) -> Result<(), Error> {
+        self.release_with_flags(KeyWithFlags::from(key.into()))
+    }
+
+    /// Pressed modifiers, as in the high byte of a [`KeyWithFlags`].
+    pub fn modifier(&self) -> u8 {
+        self.boot.modifier()
+    }
+
+    /// Like [`StateReport::press_with_flags`].
+    pub fn press_with_flags(&mut self, k: KeyWithFlags) -> Result<(), Error> {
+        let [flags, key] = k.to_be_bytes();
+        if key == 0 || as_modifier_mask(key).is_some() {
+            return self.boot.press_with_flags(k);
+        }
+        let (byte, mask) = bitmap_pos(key)?;
+        if self.overflow[byte] & mask != 0 {
+            return Err(Error::AlreadyPressed);
+        }
+        match self.boot.press_with_flags(k) {
+            Err(Error::TooManyKeysPressed) => {
+                self.overflow[byte] |= mask;
+                self.overflow_flags[usize::from(key)] = flags;
+                self.boot.press_with_flags(k & FLAG_MASK)
+            }
+            result => result,
+        }
+    }
+
+    /// Like [`StateReport::release_with_flags`].
+    pub fn release_with_flags(&mut self, k: KeyWithFlags) -> Result<(), Error> {
+        let [_, key] = k.to_be_bytes();
+        if key == 0 || as_modifier_mask(key).is_some() {
+            return self.boot.release_with_flags(k);
+        }
+        let (byte, mask) = bitmap_pos(key)?;
+        if self.overflow[byte] & mask == 0 {
+            return self.boot.release_with_flags(k);
+        }
+        self.overflow[byte] &= !mask;
+        let flags = core::mem::take(&mut self.overflow_flags[usize::from(key)]);
+        self.boot.release_with_flags(KeyWithFlags::from(flags) << 8)
+    }
+}
+
+/// Byte index and bit mask of the key in the bitmap of [`NkroReport`].
+fn bitmap_pos(key: u8) -> Result<(usize, u8), Error> {
+    let key = usize::from(key);
+    if key >= NKRO_KEYS {
+        return Err(Error::UnsupportedKey);
+    }
+    Ok((key / 8, 1 << (key % 8)))
+}
+
+fn as_modifier_mask(key: u8) -> Option<u8> {
+    if !matches!(key, MODIFIERS_START..=MODIFIERS_END) {
+        return None;
+    }
+    Some(1 << (key - MODIFIERS_START))
+}
+
+const MODIFIERS_START: u8 = 224;
+const MODIFIERS_END: u8 = 231;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keycodes::*;
+
+    fn slots(state: &StateReport) -> [u8; 6] {
+        state.to_bytes()[2..].try_into().unwrap()
+    }
+
+    #[test]
+    fn six_slots() {
+        let mut state = StateReport::default();
+        for key in [A, B, C, D, E, F] {
+            state.press(key as u8).unwrap();
+        }
+        assert_eq!(slots(&state), [A as u8, B as u8, C as u8, D as u8, E as u8, F as u8]);
+        assert_eq!(state.press(G as u8), Err(Error::TooManyKeysPressed));
+
+        // a released slot is reused, and the other keys stay in place
+        state.release(C as u8).unwrap();
+        assert_eq!(slots(&state), [A as u8, B as u8, 0, D as u8, E as u8, F as u8]);
+        state.press(G as u8).unwrap();
+        assert_eq!(slots(&state), [A as u8, B as u8, G as u8, D as u8, E as u8, F as u8]);
+
+        // modifiers don't take slots
+        state.press(0xe1).unwrap();
+        assert_eq!(state.modifier(), (LEFT_SHIFT_FLAG >> 8) as u8);
+        state.press(0xe7).unwrap();
+        assert_eq!(state.modifier(), ((LEFT_SHIFT_FLAG | RIGHT_GUI_FLAG) >> 8) as u8);
+        assert_eq!(state.to_bytes()[..2], [((LEFT_SHIFT_FLAG | RIGHT_GUI_FLAG) >> 8) as u8, 0]);
+
+        state.clear();
+        assert_eq!(state.to_bytes(), [0; REPORT_LEN]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut state = StateReport::default();
+        assert_eq!(state.release(A as u8), Err(Error::AlreadyReleased));
+        assert_eq!(state.release(0xe0), Err(Error::AlreadyReleased));
+        state.press(A as u8).unwrap();
+        state.press(0xe0).unwrap();
+        assert_eq!(state.press(A as u8), Err(Error::AlreadyPressed));
+        assert_eq!(state.press(0xe0), Err(Error::AlreadyPressed));
+        state.release(A as u8).unwrap();
+        state.release(0xe0).unwrap();
+        assert_eq!(state.release(A as u8), Err(Error::AlreadyReleased));
+        assert_eq!(state.release(0xe0), Err(Error::AlreadyReleased));
+        assert_eq!(state.to_bytes(), [0; REPORT_LEN]);
+    }
+
+    #[test]
+    fn keys_with_flags() {
+        let mut state = StateReport::default();
+        state.press_with_flags(UP | SHIFT_FLAG).unwrap();
+        state.press_with_flags(HOME | SHIFT_FLAG | CTRL_FLAG).unwrap();
+        assert_eq!(slots(&state), [UP as u8, HOME as u8, 0, 0, 0, 0]);
+        assert_eq!(state.modifier(), ((SHIFT_FLAG | CTRL_FLAG) >> 8) as u8);
+        assert_eq!(state.press_with_flags(UP), Err(Error::AlreadyPressed));
+
+        // just the flags, e.g. Alt held by the chord engine
+        state.press_with_flags(ALT_FLAG).unwrap();
+        state.release_with_flags(ALT_FLAG).unwrap();
+        state.release_with_flags(ALT_FLAG).unwrap();
+        assert_eq!(state.modifier(), ((SHIFT_FLAG | CTRL_FLAG) >> 8) as u8);
+
+        // Shift is still held by UP
+        state.release_with_flags(HOME | SHIFT_FLAG | CTRL_FLAG).unwrap();
+        assert_eq!(slots(&state), [UP as u8, 0, 0, 0, 0, 0]);
+        assert_eq!(state.modifier(), (SHIFT_FLAG >> 8) as u8);
+        assert_eq!(state.release_with_flags(HOME), Err(Error::AlreadyReleased));
+
+        // the key's own flags are released with it
+        state.release_with_flags(UP).unwrap();
+        assert_eq!(state.to_bytes(), [0; REPORT_LEN]);
+    }
+
+    #[test]
+    fn flags_without_keys() {
+        let mut state = StateReport::default();
+        // e.g. Ctrl held by a tap-hold chord, and by an unchorded key
+        state.press_with_flags(CTRL_FLAG).unwrap();
+        state.press_with_flags(CTRL_FLAG | SHIFT_FLAG).unwrap();
+        state.press_with_flags(E | CTRL_FLAG).unwrap();
+        state.release_with_flags(E).unwrap();
+        state.release_with_flags(CTRL_FLAG).unwrap();
+        assert_eq!(state.modifier(), ((CTRL_FLAG | SHIFT_FLAG) >> 8) as u8);
+        state.release_with_flags(CTRL_FLAG | SHIFT_FLAG).unwrap();
+        assert_eq!(state.modifier(), 0);
+
+        // a modifier pressed as a key is separate from the flags
+        state.press(0xe0).unwrap();
+        state.press_with_flags(CTRL_FLAG).unwrap();
+        state.release_with_flags(CTRL_FLAG).unwrap();
+        assert_eq!(state.modifier(), (CTRL_FLAG >> 8) as u8);
+        state.release(0xe0).unwrap();
+        assert_eq!(state.modifier(), 0);
+
+        // a key released without flags doesn't keep its flags in the slot
+        state.press_with_flags(E | ALT_FLAG).unwrap();
+        state.release(E as u8).unwrap();
+        state.press(T as u8).unwrap();
+        assert_eq!(state.to_bytes(), [0, 0, T as u8, 0, 0, 0, 0, 0]);
+    }
+
+    /// Total bits of input and output items in a report descriptor,
+    /// checking that it's well-formed on the way.
+    fn report_bits(desc: &[u8]) -> (usize, usize) {
+        let (mut size, mut count) = (0, 0);
+        let (mut input, mut output) = (0, 0);
+        let mut depth = 0;
+        let mut i = 0;
+        while i < desc.len() {
+            let prefix = desc[i];
+            assert_ne!(prefix, 0xfe, "long items are not used");
+            let len = [0, 1, 2, 4][usize::from(prefix & 0b11)];
+            assert!(i + len < desc.len(), "item at {i} is truncated");
+            let mut data = 0usize;
+            for (n, b) in desc[i + 1..i + 1 + len].iter().enumerate() {
+                data |= usize::from(*b) << (8 * n);
+            }
+            match prefix & !0b11 {
+                0x74 => size = data,
+                0x94 => count = data,
+                0x80 => input += size * count,
+                0x90 => output += size * count,
+                0xa0 => depth += 1,
+                0xc0 => {
+                    assert!(depth > 0, "End Collection at {i} without a Collection");
+                    depth -= 1;
+                }
+                _ => (),
+            }
+            i += 1 + len;
+        }
+        assert_eq!(depth, 0, "unclosed Collection");
+        (input, output)
+    }
+
+    #[test]
+    fn nkro_descriptor() {
+        assert_eq!(report_bits(NKRO_DESCRIPTOR), (8 * NKRO_REPORT_LEN, 0));
+        assert_eq!(NKRO_DESCRIPTOR[..6], [0x05, 0x01, 0x09, 0x06, 0xa1, 0x01]);
+    }
+
+    fn bitmap(keys: &[KeyWithFlags]) -> [u8; NKRO_REPORT_LEN] {
+        let mut bitmap = [0; NKRO_REPORT_LEN];
+        for &key in keys {
+            bitmap[key as usize / 8] |= 1 << (key % 8);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn nkro_report() {
+        let mut state = NkroReport::default();
+        for key in [A, B, C, D, E, F, G, UP, HOME] {
+            state.press(key as u8).unwrap();
+        }
+        state.press_with_flags(SHIFT_FLAG).unwrap();
+        let shift = (SHIFT_FLAG >> 8) as u8;
+        // the first 6 keys in the boot report, the rest in the bitmap
+        assert_eq!(state.boot_bytes(), [shift, 0, A as u8, B as u8, C as u8, D as u8, E as u8, F as u8]);
+        assert_eq!(state.nkro_bytes(), bitmap(&[G, UP, HOME]));
+
+        assert_eq!(state.press(A as u8), Err(Error::AlreadyPressed));
+        assert_eq!(state.press(UP as u8), Err(Error::AlreadyPressed));
+        assert_eq!(state.press(0xe8), Err(Error::UnsupportedKey));
+        state.release(B as u8).unwrap();
+        state.release(UP as u8).unwrap();
+        assert_eq!(state.release(UP as u8), Err(Error::AlreadyReleased));
+        assert_eq!(state.boot_bytes()[2..], [A as u8, 0, C as u8, D as u8, E as u8, F as u8]);
+        assert_eq!(state.nkro_bytes(), bitmap(&[G, HOME]));
+
+        // a key in the bitmap stays there when a boot slot is freed
+        assert_eq!(state.press(G as u8), Err(Error::AlreadyPressed));
+        state.press(T as u8).unwrap();
+        assert_eq!(state.boot_bytes()[2..], [A as u8, T as u8, C as u8, D as u8, E as u8, F as u8]);
+        assert_eq!(state.nkro_bytes(), bitmap(&[G, HOME]));
+
+        // modifiers only go to the boot report
+        state.press(0xe4).unwrap();
+        assert_eq!(state.modifier(), ((SHIFT_FLAG | RIGHT_CTRL_FLAG) >> 8) as u8);
+        state.clear();
+        assert_eq!(state.boot_bytes(), [0; REPORT_LEN]);
+        assert_eq!(state.nkro_bytes(), [0; NKRO_REPORT_LEN]);
+    }
+
+    #[test]
+    fn nkro_keys_with_flags() {
+        let mut state = NkroReport::default();
+        for key in [A, B, C, D, E, F] {
+            state.press(key as u8).unwrap();
+        }
+        state.press_with_flags(UP | SHIFT_FLAG).unwrap();
+        state.press_with_flags(HOME | SHIFT_FLAG | CTRL_FLAG).unwrap();
+        assert_eq!(state.nkro_bytes(), bitmap(&[UP, HOME]));
+        assert_eq!(state.modifier(), ((SHIFT_FLAG | CTRL_FLAG) >> 8) as u8);
+
+        // Shift is still held by UP
+        state.release_with_flags(HOME).unwrap();
+        assert_eq!(state.modifier(), (SHIFT_FLAG >> 8) as u8);
+        state.release(UP as u8).unwrap();
+        assert_eq!(state.modifier(), 0);
+        assert_eq!(state.nkro_bytes(), [0; NKRO_REPORT_LEN]);
+    }
+}
@@ -35,25 +35,6 @@ pub const RIGHT_SHIFT_FLAG: KeyWithFlags = 0x2000;
 pub const RIGHT_ALT_FLAG: KeyWithFlags = 0x4000;
 pub const RIGHT_GUI_FLAG: KeyWithFlags = 0x8000;
 
-pub const HACK_MOUSE_MARKER: KeyWithFlags = 0xF0;
-pub const HACK_MOUSE_ENABLE_TOGGLE: KeyWithFlags = 0xF0;
-// pub const HACK_MOUSE_ENABLE: KeyWithFlags = 0xF0;
-// pub const HACK_MOUSE_DISABLE: KeyWithFlags = 0xF1;
-pub const HACK_MOUSE_LEFT_BTN: KeyWithFlags = 0xF1;
-pub const HACK_MOUSE_RIGHT_BTN: KeyWithFlags = 0xF2;
-// pub const HACK_MOUSE_LEFT_PRESS: KeyWithFlags = 0xF2;
-// pub const HACK_MOUSE_LEFT_RELEASE: KeyWithFlags = 0xF3;
-pub const HACK_MOUSE_LEFT_DRAG_TOGGLE: KeyWithFlags = 0xF3;
-pub const HACK_MOUSE_LEFT_CLICK: KeyWithFlags = 0xF4;
-pub const HACK_MOUSE_RIGHT_PRESS: KeyWithFlags = 0xF5;
-pub const HACK_MOUSE_RIGHT_RELEASE: KeyWithFlags = 0xF6;
-pub const HACK_MOUSE_RIGHT_CLICK: KeyWithFlags = 0xF7;
-pub const HACK_MOUSE_MIDDLE_PRESS: KeyWithFlags = 0xF8;
-pub const HACK_MOUSE_MIDDLE_RELEASE: KeyWithFlags = 0xF9;
-pub const HACK_MOUSE_MIDDLE_CLICK: KeyWithFlags = 0xFA;
-pub const HACK_MOUSE_WHEEL_DOWN: KeyWithFlags = 0xFB;
-pub const HACK_MOUSE_WHEEL_UP: KeyWithFlags = 0xFC;
-
 pub const A: KeyWithFlags = 4;
 pub const B: KeyWithFlags = 5;
 pub const C: KeyWithFlags = 6;
@@ -166,3 +147,225 @@ pub const F21: KeyWithFlags = 112;
 pub const F22: KeyWithFlags = 113;
 pub const F23: KeyWithFlags = 114;
 pub const F24: KeyWithFlags = 115;
+
+/// Translates a printable ASCII character, or `\n`, or `\t`, to a key
+/// (with Shift if needed) on a US host keyboard layout.
+pub const fn from_ascii(c: u8) -> Option<KeyWithFlags> {
+    Some(match c {
+        b'a'..=b'z' => A + (c - b'a') as KeyWithFlags,
+        b'A'..=b'Z' => (A + (c - b'A') as KeyWithFlags) | SHIFT_FLAG,
+        b'1'..=b'9' => KEY_1 + (c - b'1') as KeyWithFlags,
+        b'0' => KEY_0,
+        b'\n' => ENTER,
+        b'\t' => TAB,
+        b' ' => SPACE,
+        b'!' => KEY_1 | SHIFT_FLAG,
+        b'@' => KEY_2 | SHIFT_FLAG,
+        b'#' => KEY_3 | SHIFT_FLAG,
+        b'$' => KEY_4 | SHIFT_FLAG,
+        b'%' => KEY_5 | SHIFT_FLAG,
+        b'^' => KEY_6 | SHIFT_FLAG,
+        b'&' => KEY_7 | SHIFT_FLAG,
+        b'*' => KEY_8 | SHIFT_FLAG,
+        b'(' => KEY_9 | SHIFT_FLAG,
+        b')' => KEY_0 | SHIFT_FLAG,
+        b'-' => MINUS,
+        b'_' => MINUS | SHIFT_FLAG,
+        b'=' => EQUAL,
+        b'+' => EQUAL | SHIFT_FLAG,
+        b'[' => LEFT_BRACE,
+        b'{' => LEFT_BRACE | SHIFT_FLAG,
+        b']' => RIGHT_BRACE,
+        b'}' => RIGHT_BRACE | SHIFT_FLAG,
+        b'\\' => BACKSLASH,
+        b'|' => BACKSLASH | SHIFT_FLAG,
+        b';' => SEMICOLON,
+        b':' => SEMICOLON | SHIFT_FLAG,
+        b'\'' => QUOTE,
+        b'"' => QUOTE | SHIFT_FLAG,
+        b'`' => TILDE,
+        b'~' => TILDE | SHIFT_FLAG,
+        b',' => COMMA,
+        b'<' => COMMA | SHIFT_FLAG,
+        b'.' => PERIOD,
+        b'>' => PERIOD | SHIFT_FLAG,
+        b'/' => SLASH,
+        b'?' => SLASH | SHIFT_FLAG,
+        _ => return None,
+    })
+}
+
+/// Whether the key types a character, like a letter, a digit, space, Enter
+/// or Tab - i.e. it's not a function or navigation key, nor combined with
+/// Ctrl, Alt (but AltGr is fine) or GUI.
+pub const fn types_character(key: KeyWithFlags) -> bool {
+    const COMMANDS: KeyWithFlags = CTRL_FLAG | ALT_FLAG | GUI_FLAG | RIGHT_CTRL_FLAG | RIGHT_GUI_FLAG;
+    key & COMMANDS == 0 && matches!(key & KEY_MASK,
+        A..=ENTER | TAB..=SLASH | KEYPAD_SLASH..=KEYPAD_PERIOD | NON_US_BS)
+}
+
+/// Names of the keys above, e.g. for parsing or printing layouts.
+pub const KEY_NAMES: &[(&str, KeyWithFlags)] = &[
+    ("A", A),
+    ("B", B),
+    ("C", C),
+    ("D", D),
+    ("E", E),
+    ("F", F),
+    ("G", G),
+    ("H", H),
+    ("I", I),
+    ("J", J),
+    ("K", K),
+    ("L", L),
+    ("M", M),
+    ("N", N),
+    ("O", O),
+    ("P", P),
+    ("Q", Q),
+    ("R", R),
+    ("S", S),
+    ("T", T),
+    ("U", U),
+    ("V", V),
+    ("W", W),
+    ("X", X),
+    ("Y", Y),
+    ("Z", Z),
+    ("KEY_1", KEY_1),
+    ("KEY_2", KEY_2),
+    ("KEY_3", KEY_3),
+    ("KEY_4", KEY_4),
+    ("KEY_5", KEY_5),
+    ("KEY_6", KEY_6),
+    ("KEY_7", KEY_7),
+    ("KEY_8", KEY_8),
+    ("KEY_9", KEY_9),
+    ("KEY_0", KEY_0),
+    ("ENTER", ENTER),
+    ("ESC", ESC),
+    ("BACKSPACE", BACKSPACE),
+    ("TAB", TAB),
+    ("SPACE", SPACE),
+    ("MINUS", MINUS),
+    ("EQUAL", EQUAL),
+    ("LEFT_BRACE", LEFT_BRACE),
+    ("RIGHT_BRACE", RIGHT_BRACE),
+    ("BACKSLASH", BACKSLASH),
+    ("NON_US_NUM", NON_US_NUM),
+    ("SEMICOLON", SEMICOLON),
+    ("QUOTE", QUOTE),
+    ("TILDE", TILDE),
+    ("COMMA", COMMA),
+    ("PERIOD", PERIOD),
+    ("SLASH", SLASH),
+    ("CAPS_LOCK", CAPS_LOCK),
+    ("F1", F1),
+    ("F2", F2),
+    ("F3", F3),
+    ("F4", F4),
+    ("F5", F5),
+    ("F6", F6),
+    ("F7", F7),
+    ("F8", F8),
+    ("F9", F9),
+    ("F10", F10),
+    ("F11", F11),
+    ("F12", F12),
+    ("PRINTSCREEN", PRINTSCREEN),
+    ("SCROLL_LOCK", SCROLL_LOCK),
+    ("PAUSE", PAUSE),
+    ("INSERT", INSERT),
+    ("HOME", HOME),
+    ("PAGE_UP", PAGE_UP),
+    ("DELETE", DELETE),
+    ("END", END),
+    ("PAGE_DOWN", PAGE_DOWN),
+    ("RIGHT", RIGHT),
+    ("LEFT", LEFT),
+    ("DOWN", DOWN),
+    ("UP", UP),
+    ("NUM_LOCK", NUM_LOCK),
+    ("KEYPAD_SLASH", KEYPAD_SLASH),
+    ("KEYPAD_ASTERIX", KEYPAD_ASTERIX),
+    ("KEYPAD_MINUS", KEYPAD_MINUS),
+    ("KEYPAD_PLUS", KEYPAD_PLUS),
+    ("KEYPAD_ENTER", KEYPAD_ENTER),
+    ("KEYPAD_1", KEYPAD_1),
+    ("KEYPAD_2", KEYPAD_2),
+    ("KEYPAD_3", KEYPAD_3),
+    ("KEYPAD_4", KEYPAD_4),
+    ("KEYPAD_5", KEYPAD_5),
+    ("KEYPAD_6", KEYPAD_6),
+    ("KEYPAD_7", KEYPAD_7),
+    ("KEYPAD_8", KEYPAD_8),
+    ("KEYPAD_9", KEYPAD_9),
+    ("KEYPAD_0", KEYPAD_0),
+    ("KEYPAD_PERIOD", KEYPAD_PERIOD),
+    ("NON_US_BS", NON_US_BS),
+    ("MENU", MENU),
+    ("F13", F13),
+    ("F14", F14),
+    ("F15", F15),
+    ("F16", F16),
+    ("F17", F17),
+    ("F18", F18),
+    ("F19", F19),
+    ("F20", F20),
+    ("F21", F21),
+    ("F22", F22),
+    ("F23", F23),
+    ("F24", F24),
+];
+
+/// Names of the modifier flags above, e.g. for parsing or printing layouts.
+pub const FLAG_NAMES: &[(&str, KeyWithFlags)] = &[
+    ("CTRL_FLAG", CTRL_FLAG), ("SHIFT_FLAG", SHIFT_FLAG),
+    ("ALT_FLAG", ALT_FLAG), ("GUI_FLAG", GUI_FLAG),
+    ("RIGHT_CTRL_FLAG", RIGHT_CTRL_FLAG), ("RIGHT_SHIFT_FLAG", RIGHT_SHIFT_FLAG),
+    ("RIGHT_ALT_FLAG", RIGHT_ALT_FLAG), ("RIGHT_GUI_FLAG", RIGHT_GUI_FLAG),
+];
+
+/// Usage IDs on the HID Consumer page, for
+/// [`Action::Media`](crate::action::Action::Media).
+pub mod media {
+    pub const BRIGHTNESS_UP: u16 = 0x6f;
+    pub const BRIGHTNESS_DOWN: u16 = 0x70;
+    pub const NEXT_TRACK: u16 = 0xb5;
+    pub const PREVIOUS_TRACK: u16 = 0xb6;
+    pub const STOP: u16 = 0xb7;
+    pub const EJECT: u16 = 0xb8;
+    pub const PLAY_PAUSE: u16 = 0xcd;
+    pub const MUTE: u16 = 0xe2;
+    pub const VOLUME_UP: u16 = 0xe9;
+    pub const VOLUME_DOWN: u16 = 0xea;
+
+    /// Names of the usages above, e.g. for parsing layouts.
+    pub const NAMES: &[(&str, u16)] = &[
+        ("BRIGHTNESS_UP", BRIGHTNESS_UP),
+        ("BRIGHTNESS_DOWN", BRIGHTNESS_DOWN),
+        ("NEXT_TRACK", NEXT_TRACK),
+        ("PREVIOUS_TRACK", PREVIOUS_TRACK),
+        ("STOP", STOP),
+        ("EJECT", EJECT),
+        ("PLAY_PAUSE", PLAY_PAUSE),
+        ("MUTE", MUTE),
+        ("VOLUME_UP", VOLUME_UP),
+        ("VOLUME_DOWN", VOLUME_DOWN),
+    ];
+}
+
+/// Usage IDs of System Control on the HID Generic Desktop page, for
+/// [`Action::System`](crate::action::Action::System).
+pub mod system {
+    pub const POWER_DOWN: u8 = 0x81;
+    pub const SLEEP: u8 = 0x82;
+    pub const WAKE_UP: u8 = 0x83;
+
+    /// Names of the usages above, e.g. for parsing layouts.
+    pub const NAMES: &[(&str, u8)] = &[
+        ("POWER_DOWN", POWER_DOWN),
+        ("SLEEP", SLEEP),
+        ("WAKE_UP", WAKE_UP),
+    ];
+}
@@ -0,0 +1,157 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Layers pushed on top of the base layer.
+
+/// How long a layer stays on the [`LayerStack`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LayerMode {
+    /// Until the next chord.
+    OneShot,
+    /// While the chord that pushed it is held. If the chord is tapped
+    /// instead, the layer is pushed as [`Self::OneShot`].
+    Momentary,
+    /// Until pushed again, in the same mode.
+    Toggle,
+    /// Until the state is cleared.
+    Locked,
+}
+
+const MAX_DEPTH: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct LayerStack {
+    entries: [(u8, LayerMode); MAX_DEPTH],
+    len: u8,
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        Self { entries: [(0, LayerMode::OneShot); MAX_DEPTH], len: 0 }
+    }
+}
+
+impl LayerStack {
+    /// Pushes the layer on top. If it's already on the stack, it's moved to
+    /// the top - or, if toggled again, removed. If the stack is full, the
+    /// bottom-most layer is dropped.
+    pub fn push(&mut self, layer: u8, mode: LayerMode) {
+        let prev = self.remove(layer);
+        if mode == LayerMode::Toggle && prev == Some(LayerMode::Toggle) {
+            return;
+        }
+        if usize::from(self.len) == MAX_DEPTH {
+            self.entries.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.entries[usize::from(self.len)] = (layer, mode);
+        self.len += 1;
+    }
+
+    /// Removes the layer, returning its mode if it was on the stack.
+    pub fn remove(&mut self, layer: u8) -> Option<LayerMode> {
+        let len = usize::from(self.len);
+        let i = self.entries[..len].iter().position(|&(l, _)| l == layer)?;
+        let (_, mode) = self.entries[i];
+        self.entries.copy_within(i + 1..len, i);
+        self.len -= 1;
+        Some(mode)
+    }
+
+    pub fn remove_one_shots(&mut self) {
+        let mut kept = 0;
+        for i in 0..usize::from(self.len) {
+            if self.entries[i].1 != LayerMode::OneShot {
+                self.entries[kept] = self.entries[i];
+                kept += 1;
+            }
+        }
+        self.len = kept as u8;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates the layers from the top one down.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, LayerMode)> + '_ {
+        self.entries[..usize::from(self.len)].iter().rev().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LayerMode::*;
+
+    fn layers(stack: &LayerStack) -> Vec<(u8, LayerMode)> {
+        stack.iter().collect()
+    }
+
+    #[test]
+    fn push_and_remove() {
+        let mut stack = LayerStack::default();
+        assert!(stack.is_empty());
+        stack.push(1, Locked);
+        stack.push(2, Toggle);
+        stack.push(3, OneShot);
+        assert_eq!(layers(&stack), [(3, OneShot), (2, Toggle), (1, Locked)]);
+        assert_eq!(stack.remove(2), Some(Toggle));
+        assert_eq!(stack.remove(2), None);
+        assert_eq!(layers(&stack), [(3, OneShot), (1, Locked)]);
+        stack.clear();
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn push_again_moves_to_top_or_toggles_off() {
+        let mut stack = LayerStack::default();
+        stack.push(1, Toggle);
+        stack.push(2, Locked);
+        stack.push(1, Toggle);
+        assert_eq!(layers(&stack), [(2, Locked)]);
+        stack.push(1, Momentary);
+        stack.push(2, Locked);
+        assert_eq!(layers(&stack), [(2, Locked), (1, Momentary)]);
+        stack.push(2, Toggle);
+        assert_eq!(layers(&stack), [(2, Toggle), (1, Momentary)]);
+    }
+
+    #[test]
+    fn one_shots_removed() {
+        let mut stack = LayerStack::default();
+        stack.push(1, OneShot);
+        stack.push(2, Toggle);
+        stack.push(3, OneShot);
+        stack.push(4, Momentary);
+        stack.remove_one_shots();
+        assert_eq!(layers(&stack), [(4, Momentary), (2, Toggle)]);
+    }
+
+    #[test]
+    fn full_stack_drops_bottom() {
+        let mut stack = LayerStack::default();
+        for layer in 0..10 {
+            stack.push(layer, Locked);
+        }
+        let expected: Vec<_> = (2..10).rev().map(|l| (l, Locked)).collect();
+        assert_eq!(layers(&stack), expected);
+    }
+}
@@ -14,28 +14,76 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+// For the code generated by `clawtype_macros::layout!` in this crate.
+extern crate self as clawtype_chords;
 
 use core::mem;
-use core::ops::{BitAndAssign, BitOr, BitOrAssign, Not};
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 
+pub mod action;
+pub mod blob;
+pub mod briefs;
+#[cfg(any(test, feature = "std"))]
+pub mod dsl;
+pub mod host_layout;
+pub mod keyboard;
 pub mod keycodes;
+pub mod layer_stack;
+pub mod modifiers;
+pub mod mouse;
+pub mod notation;
 pub mod sample_layers;
+pub mod undo;
+pub mod unicode;
 
-/// Currently, the most significant bit is the pinky finger's tip switch,
-/// then pinky finger's base switch. Subsequent bits represent tip & base
-/// of ring finger, middle finger, and index finger.
+/// With the default `u8` chords, the most significant bit is the pinky
+/// finger's tip switch, then pinky finger's base switch. Subsequent bits
+/// represent tip & base of ring finger, middle finger, and index finger.
 ///
 /// E.g.: `0b10_00_00_01` is: pinky tip + index base pressed.
-#[derive(Default)]
-pub struct SwitchSet(pub u8);
+///
+/// Devices with more switches, e.g. on a thumb, use wider [`Chord`]s, with
+/// the extra switches in the least significant bits.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SwitchSet<C = u8>(pub C);
+
+/// Bits of a [`SwitchSet`], as used for looking up chords.
+pub trait Chord:
+    Copy + Default + Eq + core::fmt::Debug
+    + BitAnd<Output = Self> + BitOr<Output = Self> + BitXor<Output = Self> + Not<Output = Self>
+    + BitAndAssign + BitOrAssign + BitXorAssign + TryInto<u8>
+{
+    fn count_ones(self) -> u32;
+    /// Only the most significant bit set, or zero.
+    fn top_bit(self) -> Self;
+}
+
+macro_rules! impl_chord {
+    ($($t:ty),*) => {$(
+        impl Chord for $t {
+            fn count_ones(self) -> u32 {
+                <$t>::count_ones(self)
+            }
+
+            fn top_bit(self) -> Self {
+                if self == 0 { 0 } else { 1 << self.ilog2() }
+            }
+        }
+    )*};
+}
+
+impl_chord!(u8, u16, u32);
 
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub enum UsbOutcome<KeyWithFlags> {
+pub enum UsbOutcome<A = action::Action> {
     Nothing,
-    KeyHit(KeyWithFlags),
-    KeyPress(KeyWithFlags),
-    KeyRelease(KeyWithFlags),
+    KeyHit(A),
+    KeyPress(A),
+    KeyRelease(A),
+    /// The chord could not be resolved, because of a broken layout.
+    Error(LookupError),
 }
 
 impl<K> UsbOutcome<K> {
@@ -48,97 +96,443 @@ impl<K> UsbOutcome<K> {
             Self::KeyHit(k) => KeyHit(f(k)),
             Self::KeyPress(k) => KeyPress(f(k)),
             Self::KeyRelease(k) => KeyRelease(f(k)),
+            Self::Error(e) => Error(e),
         }
     }
 }
 
-pub struct LayerInfo {
-    pub unchorded_mask: SwitchSet,
+/// How many layers can be visited when resolving a single chord, through
+/// [`Lookup::fallbacks`] and [`LayerOutcome::FromOtherPlusMask`].
+pub const MAX_LOOKUP_DEPTH: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LookupError {
+    /// The layer was reached again while resolving the chord on it.
+    Cycle { layer: u8 },
+    /// More than [`MAX_LOOKUP_DEPTH`] layers were visited.
+    TooDeep,
 }
 
-#[derive(Copy, Clone)]
-pub enum LayerOutcome<KeyWithFlags> {
+/// Most chords in a sequence typed after [`LayerOutcome::Leader`].
+pub const MAX_SEQUENCE_LEN: usize = 4;
+
+/// How the chords typed after [`LayerOutcome::Leader`] match the sequences
+/// of a layout, see [`Lookup::sequence`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SequenceMatch<A = action::Action> {
+    /// No sequence starts with the chords.
+    None,
+    /// Longer sequences start with the chords.
+    Prefix,
+    /// The chords are a whole sequence, and no longer one starts with them.
+    Complete(LayerOutcome<A>),
+    /// The chords are a whole sequence, but longer ones start with them too.
+    /// The outcome is applied if no longer sequence is then typed.
+    Ambiguous(LayerOutcome<A>),
+}
+
+impl<K> SequenceMatch<K> {
+    pub(crate) fn new(found: Option<LayerOutcome<K>>, longer: bool) -> Self {
+        match (found, longer) {
+            (None, false) => Self::None,
+            (None, true) => Self::Prefix,
+            (Some(outcome), false) => Self::Complete(outcome),
+            (Some(outcome), true) => Self::Ambiguous(outcome),
+        }
+    }
+}
+
+/// When a chord is considered complete, see [`Lookup::chord_mode`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ChordMode {
+    /// A chord is committed when all switches are released.
+    #[default]
+    AllReleased,
+    /// A chord is committed when the number of pressed switches first
+    /// decreases. Switches still held then seed the next chord, which is
+    /// committed only if any new switch gets pressed. This lets one roll
+    /// from one chord into the next.
+    Rollover,
+}
+
+/// Auto-repeat of a held chord, see [`Lookup::repeat`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Repeat {
+    /// Milliseconds from pressing the chord until the first repeat.
+    pub delay: u32,
+    /// Milliseconds between subsequent repeats.
+    pub interval: u32,
+}
+
+/// How a key affects [`LayerOutcome::CapsWord`], see [`Lookup::caps_word`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CapsWordKey {
+    /// The key is emitted with Shift, and Caps Word continues.
+    Shifted,
+    /// The key is emitted as is, and Caps Word continues.
+    Continue,
+    /// The key is emitted as is, and Caps Word ends.
+    End,
+}
+
+impl CapsWordKey {
+    /// Letters and `MINUS` (so that it types `_`) are shifted; digits,
+    /// `BACKSPACE` and `DELETE` continue Caps Word; any other key ends it.
+    pub fn default_for(key: keycodes::KeyWithFlags) -> Self {
+        use keycodes::*;
+        match key & KEY_MASK {
+            A..=Z | MINUS => Self::Shifted,
+            KEY_1..=KEY_0 | BACKSPACE | DELETE => Self::Continue,
+            _ => Self::End,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LayerInfo<C = u8> {
+    pub unchorded_mask: SwitchSet<C>,
+    /// Chords not found on this layer are looked up on the layers below it
+    /// on the [`layer_stack::LayerStack`], then on the base layer, then on
+    /// layer 0. This is tried last, after [`Lookup::fallbacks`] and
+    /// [`Self::fallback`] found nothing.
+    pub transparent: bool,
+    /// What to do with chords not found on this layer, nor on any of its
+    /// [`Lookup::fallbacks`]. If this looks up the chord on another layer
+    /// and it's not there either, [`Self::transparent`] still applies.
+    pub fallback: LayerFallback,
+}
+
+/// Default action of a layer, see [`LayerInfo::fallback`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum LayerFallback {
+    /// The chord is not found on the layer.
+    #[default]
+    None,
+    /// The chord is looked up on the other layer.
+    Transparent { layer: u8 },
+    /// The chord is looked up on the other layer, like with
+    /// [`LayerOutcome::FromOtherPlusMask`].
+    EmitWithMask { layer: u8, mask: keycodes::KeyWithFlags },
+    /// The chord does nothing, and is not looked up on any further layers.
+    Swallow,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LayerOutcome<A = action::Action> {
     ClearState,
-    Emit(UsbOutcome<KeyWithFlags>),
+    Emit(UsbOutcome<A>),
     LayerSwitchAndEmit {
         layer: u8,
-        emit: UsbOutcome<KeyWithFlags>,
+        emit: UsbOutcome<A>,
     },
     TemporaryLayerSwitch {
         layer: u8,
     },
     /// Intended for adding USB flag key, like Alt, Shift, GUI, RAlt, etc.
+    /// Locks the modifiers, or releases them if already locked.
     TogglePlusMask {
-        mask: KeyWithFlags,
+        mask: keycodes::KeyWithFlags,
     },
     /// Intended for adding USB flag key, like Alt, Shift, GUI, RAlt, etc.
+    /// Adds the modifiers to the next key only. If tapped again before
+    /// that, locks them instead; tapped when locked, releases them. See
+    /// [`modifiers::Modifiers::tap`].
     TemporaryPlusMask {
-        mask: KeyWithFlags,
+        mask: keycodes::KeyWithFlags,
     },
     /// Intended for adding USB flag key, like Alt, Shift, GUI, RAlt, etc.
     FromOtherPlusMask {
         layer: u8,
-        mask: KeyWithFlags,
+        mask: keycodes::KeyWithFlags,
+    },
+    /// Types the whole text, as a sequence of [`UsbOutcome::KeyHit`]s, in
+    /// the first returned by [`Engine::handle`], and the rest by
+    /// [`Engine::next_pending`]. Characters that can't be typed on
+    /// the host layout (see [`Engine::set_host_layout`]) are skipped.
+    /// Any plus masks are not applied.
+    EmitText(&'static str),
+    /// Types a character, using a key appropriate for the host layout (see
+    /// [`Engine::set_host_layout`]). If there's no such key, falls back to
+    /// [`Self::EmitUnicode`]. Any plus masks are not applied.
+    EmitChar(char),
+    /// Types an arbitrary character using an input method of the host OS,
+    /// as configured by [`Engine::set_unicode_method`]. Like with
+    /// [`Self::EmitText`], further outcomes are returned by
+    /// [`Engine::next_pending`].
+    EmitUnicode(char),
+    SetUnicodeMethod(unicode::Method),
+    SetHostLayout(host_layout::HostLayout),
+    /// Starts Caps Word: Shift is added to the following keys, until a key
+    /// ending it, as decided by [`Lookup::caps_word`]. Ends it if started.
+    CapsWord,
+    /// Starts a sequence: the following chords are not looked up on any
+    /// layer, but collected and matched with [`Lookup::sequence`], until a
+    /// sequence is complete, or no sequence matches, or
+    /// [`Lookup::sequence_timeout`] passes since the last chord. The longest
+    /// sequence matched, if any, is then applied.
+    Leader,
+    /// Toggles brief mode: chords found in the [`Lookup::briefs`] dictionary
+    /// type its translations, instead of being looked up on the layers.
+    Briefs,
+    /// Erases the most recent output, typed by a single chord, with
+    /// `BACKSPACE` hits. An output of [`Self::Briefs`] is erased as a whole
+    /// translation, and the spacing and capitalization from before it are
+    /// restored. Repeated, erases the outputs before it, up to a few.
+    Undo,
+    /// Pushes the layer on the [`layer_stack::LayerStack`].
+    PushLayer {
+        layer: u8,
+        mode: layer_stack::LayerMode,
+    },
+    /// Does `tap` when the chord is released quickly, or `hold` once it's
+    /// held for [`Lookup::hold_threshold`]. The held chord's switches are
+    /// then excluded from further chords, until they're all released.
+    TapHold {
+        tap: TapHoldAction<A>,
+        hold: TapHoldAction<A>,
     },
 }
 
-pub struct Engine<L: Lookup> {
-    most: SwitchSet,
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TapHoldAction<A = action::Action> {
+    /// When tapped, a [`UsbOutcome::KeyHit`]. When held, a
+    /// [`UsbOutcome::KeyPress`], and [`UsbOutcome::KeyRelease`] when released.
+    Key(A),
+    /// When tapped, like [`LayerOutcome::TemporaryLayerSwitch`]. When held,
+    /// like [`layer_stack::LayerMode::Momentary`].
+    Layer(u8),
+}
+
+impl<K> TapHoldAction<K> {
+    fn tapped(self) -> LayerOutcome<K> {
+        match self {
+            Self::Key(key) => LayerOutcome::Emit(UsbOutcome::KeyHit(key)),
+            Self::Layer(layer) => LayerOutcome::TemporaryLayerSwitch { layer },
+        }
+    }
+}
+
+/// Chords typed after [`LayerOutcome::Leader`].
+#[derive(Copy, Clone)]
+struct LeaderSequence<A, C> {
+    chords: [C; MAX_SEQUENCE_LEN],
+    len: u8,
+    /// When the last chord was typed, or the sequence started.
+    last: u32,
+    /// A [`SequenceMatch::Ambiguous`] outcome, to be applied if no longer
+    /// sequence matches.
+    matched: Option<LayerOutcome<A>>,
+}
+
+impl<K, C> LeaderSequence<K, C> {
+    fn chords(&self) -> &[C] {
+        &self.chords[..usize::from(self.len)]
+    }
+}
+
+/// A [`LayerOutcome::TapHold`] chord being held.
+#[derive(Copy, Clone)]
+struct Hold<A, C> {
+    switches: SwitchSet<C>,
+    action: TapHoldAction<A>,
+}
+
+pub struct Engine<L: Lookup<C>, C: Chord = u8> {
+    /// Last switches passed to [`Engine::handle_at`], for [`Engine::tick`].
+    switches: SwitchSet<C>,
+    /// Milliseconds, as last passed to [`Engine::handle_at`] or [`Engine::tick`].
+    now: u32,
+    /// When the first switch of the current chord was pressed.
+    chord_start: u32,
+    /// How many times the current chord was auto-repeated.
+    repeats: u32,
+    /// Switches pressed in previous call, in [`ChordMode::Rollover`].
+    pressed: SwitchSet<C>,
+    /// Whether any switch was pressed since the last commit, in
+    /// [`ChordMode::Rollover`].
+    fresh: bool,
+    hold: Option<Hold<L::Action, C>>,
+    most: SwitchSet<C>,
     layer: u8,
-    temporary_layer: Option<u8>,
-    plus_mask: L::KeyWithFlags,
-    temporary_plus_mask: L::KeyWithFlags,
-    unchorded_state: SwitchSet,
-    unchorded_shunt: SwitchSet, // to be shunted after layer switch
+    layers: layer_stack::LayerStack,
+    modifiers: modifiers::Modifiers<keycodes::KeyWithFlags>,
+    caps_word: bool,
+    leader: Option<LeaderSequence<L::Action, C>>,
+    briefs: bool,
+    translator: briefs::Translator,
+    undo: undo::History,
+    /// Characters typed by the chord being committed, for [`LayerOutcome::Undo`].
+    typed: u16,
+    unchorded_state: SwitchSet<C>,
+    unchorded_shunt: SwitchSet<C>, // to be shunted after layer switch
     unchorded_shunt_layer: u8,
+    pending_text: &'static str,
+    pending_keys: unicode::Sequence,
+    pending_edits: briefs::Output<'static>,
+    unicode_method: unicode::Method,
+    host_layout: host_layout::HostLayout,
 }
 
-impl<L> Default for Engine<L>
+impl<L, C> Default for Engine<L, C>
 where
-    L: Lookup,
-    L::KeyWithFlags: Default,
+    L: Lookup<C>,
+    C: Chord,
 {
     fn default() -> Self {
         Self {
+            switches: SwitchSet::default(),
+            now: 0,
+            chord_start: 0,
+            repeats: 0,
+            pressed: SwitchSet::default(),
+            fresh: false,
+            hold: None,
             most: SwitchSet::default(),
             layer: 0,
-            temporary_layer: None,
-            plus_mask: L::KeyWithFlags::default(),
-            temporary_plus_mask: L::KeyWithFlags::default(),
+            layers: layer_stack::LayerStack::default(),
+            modifiers: modifiers::Modifiers::default(),
+            caps_word: false,
+            leader: None,
+            briefs: false,
+            translator: briefs::Translator::default(),
+            undo: undo::History::default(),
+            typed: 0,
             unchorded_state: SwitchSet::default(),
             unchorded_shunt: SwitchSet::default(),
             unchorded_shunt_layer: 0,
+            pending_text: "",
+            pending_keys: unicode::Sequence::default(),
+            pending_edits: briefs::Output::default(),
+            unicode_method: L::unicode_method(),
+            host_layout: host_layout::HostLayout::default(),
         }
     }
 }
 
-pub trait Lookup {
-    type KeyWithFlags;
-    fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Self::KeyWithFlags>>;
-    fn info(_layer: u8) -> LayerInfo {
-        LayerInfo { unchorded_mask: Default::default() }
+/// A layout, for [`Chord`]s of type `C`.
+pub trait Lookup<C: Chord = u8> {
+    /// What the chords do, usually an [`action::Action`], or a plain
+    /// [`keycodes::KeyWithFlags`] for keyboard-only layouts.
+    type Action;
+    fn lookup(layer: u8, chord: C) -> Option<LayerOutcome<Self::Action>>;
+    fn info(_layer: u8) -> LayerInfo<C> {
+        LayerInfo::default()
+    }
+
+    fn unchorded_key(_layer: u8, _switch: SwitchSet<C>) -> Option<Self::Action> { None }
+
+    /// After how many milliseconds a [`LayerOutcome::TapHold`] chord is
+    /// considered held.
+    fn hold_threshold() -> u32 { 200 }
+
+    /// Auto-repeat for a held chord, if it resolves to
+    /// [`LayerOutcome::Emit`] of a [`UsbOutcome::KeyHit`]. Can be set
+    /// per layer, by ignoring the `chord`.
+    fn repeat(_layer: u8, _chord: C) -> Option<Repeat> { None }
+
+    fn chord_mode() -> ChordMode { ChordMode::default() }
+
+    /// Layers where chords not found on this layer are looked up, in order.
+    /// A fallback layer's own fallbacks and [`LayerInfo::fallback`] are
+    /// tried before the next one; this layer's [`LayerInfo::fallback`] and
+    /// [`LayerInfo::transparent`] only after all of them.
+    fn fallbacks(_layer: u8) -> &'static [u8] { &[] }
+
+    /// How the key, emitted with [`UsbOutcome::KeyHit`] during
+    /// [`LayerOutcome::CapsWord`], affects it.
+    fn caps_word(key: keycodes::KeyWithFlags) -> CapsWordKey {
+        CapsWordKey::default_for(key)
+    }
+
+    /// How the chords typed after [`LayerOutcome::Leader`] match the
+    /// sequences of the layout, e.g. as found by [`lookup_sequence`].
+    fn sequence(_chords: &[C]) -> SequenceMatch<Self::Action> { SequenceMatch::None }
+
+    /// After how many milliseconds since the last chord an unfinished
+    /// [`LayerOutcome::Leader`] sequence ends.
+    fn sequence_timeout() -> u32 { 1000 }
+
+    /// How [`LayerOutcome::EmitUnicode`] types characters, until changed
+    /// with [`LayerOutcome::SetUnicodeMethod`].
+    fn unicode_method() -> unicode::Method { unicode::Method::default() }
+
+    /// The dictionary used in [`LayerOutcome::Briefs`] mode. Only chords
+    /// fitting in a `u8` are looked up in it.
+    fn briefs() -> briefs::Dictionary<'static> {
+        briefs::Dictionary::EMPTY
+    }
+}
+
+/// A chord resolved on a layer, possibly via other layers.
+struct Resolved<K> {
+    layer: u8,
+    outcome: LayerOutcome<K>,
+    /// Accumulated from any [`LayerOutcome::FromOtherPlusMask`] on the way.
+    mask: keycodes::KeyWithFlags,
+}
+
+/// Layers being visited while resolving a chord, for detecting cycles.
+#[derive(Default)]
+struct LookupPath {
+    layers: [u8; MAX_LOOKUP_DEPTH],
+    len: usize,
+}
+
+impl LookupPath {
+    fn enter(&mut self, layer: u8) -> Result<(), LookupError> {
+        if self.layers[..self.len].contains(&layer) {
+            return Err(LookupError::Cycle { layer });
+        }
+        if self.len == MAX_LOOKUP_DEPTH {
+            return Err(LookupError::TooDeep);
+        }
+        self.layers[self.len] = layer;
+        self.len += 1;
+        Ok(())
     }
 
-    fn unchorded_key(_layer: u8, _switch: SwitchSet) -> Option<Self::KeyWithFlags> { None }
+    fn leave(&mut self) {
+        self.len -= 1;
+    }
 }
 
-pub fn lookup_in_slice<K>(chord: u8, layout: &[(u8, LayerOutcome<K>)]) -> Option<&LayerOutcome<K>> {
+pub fn lookup_in_slice<K, C: Chord>(chord: C, layout: &[(C, LayerOutcome<K>)]) -> Option<&LayerOutcome<K>> {
     layout.iter().find(|x| x.0 == chord).map(|x| &x.1)
 }
 
+/// Matches the chords against a table of sequences, for
+/// [`Lookup::sequence`].
+pub fn lookup_sequence<K: Copy, C: Chord>(chords: &[C], sequences: &[(&[C], LayerOutcome<K>)]) -> SequenceMatch<K> {
+    let found = sequences.iter().find(|(seq, _)| *seq == chords).map(|&(_, outcome)| outcome);
+    let longer = sequences.iter().any(|(seq, _)| seq.len() > chords.len() && seq.starts_with(chords));
+    SequenceMatch::new(found, longer)
+}
+
 
-impl<L> Engine<L>
+impl<L, C> Engine<L, C>
 where
-    L: Lookup,
-    L::KeyWithFlags: Copy + Default + BitAndAssign + BitOr<Output = L::KeyWithFlags> + BitOrAssign + Not<Output = L::KeyWithFlags>,
+    L: Lookup<C>,
+    C: Chord,
+    L::Action: Copy + PartialEq + BitOr<keycodes::KeyWithFlags, Output = L::Action>,
+    L::Action: From<keycodes::KeyWithFlags> + TryInto<keycodes::KeyWithFlags>,
 {
-    pub fn handle(&mut self, switches: SwitchSet) -> UsbOutcome<L::KeyWithFlags> {
+    /// Like [`Self::handle_at`], but without time passing since the previous
+    /// call. Nothing time-related happens when only this method is used.
+    pub fn handle(&mut self, switches: SwitchSet<C>) -> UsbOutcome<L::Action> {
+        self.handle_at(switches, self.now)
+    }
+
+    /// Processes the current state of the switches, at `now` - a monotonic
+    /// millisecond tick. The tick is allowed to wrap around.
+    pub fn handle_at(&mut self, switches: SwitchSet<C>, now: u32) -> UsbOutcome<L::Action> {
         use UsbOutcome::*;
+        self.now = now;
+        self.switches = switches;
         // any unchorded keys not from this layer remain pressed?
         // sched them one by one, ignoring any other input switches for now.
-        if self.unchorded_shunt.0 != 0 {
+        if self.unchorded_shunt.0 != C::default() {
             // sched the most significant bit
-            let msb = top_bit(self.unchorded_shunt.0);
+            let msb = self.unchorded_shunt.0.top_bit();
             self.unchorded_shunt.0 &= !msb;
             let layer = self.unchorded_shunt_layer;
             let Some(key) = L::unchorded_key(layer, SwitchSet(msb)) else {
@@ -150,7 +544,7 @@ where
 
         // check unchorded switches for change
         // (not on temporary layers - this feat is incompat. with them)
-        let unchorded_mask = self.temporary_layer.is_none()
+        let unchorded_mask = self.layers.is_empty()
             .then(|| L::info(self.layer).unchorded_mask).unwrap_or_default();
         'unchorded: {
             let unchorded = switches.0 & unchorded_mask.0;
@@ -158,12 +552,12 @@ where
                 break 'unchorded; // no change, proceed
             }
             // find out top-most bit different between prev and curr state
-            let msb = top_bit(unchorded ^ self.unchorded_state.0);
+            let msb = (unchorded ^ self.unchorded_state.0).top_bit();
             let Some(key) = L::unchorded_key(self.layer, SwitchSet(msb)) else {
                 break 'unchorded; // whoops, should not happen
             };
             let key = self.plus_masked(key);
-            let outcome = if self.unchorded_state.0 & msb == 0 {
+            let outcome = if self.unchorded_state.0 & msb == C::default() {
                 KeyPress(key)
             } else {
                 KeyRelease(key)
@@ -171,46 +565,301 @@ where
             self.unchorded_state.0 ^= msb;
             return outcome;
         }
-        let switches = SwitchSet(switches.0 & !unchorded_mask.0);
+
+        // held chord released? (but let any other chord finish first)
+        if let Some(hold) = self.hold {
+            if switches.0 & hold.switches.0 == C::default() && self.most.0 == C::default() {
+                self.hold = None;
+                match hold.action {
+                    TapHoldAction::Key(key) => return KeyRelease(key),
+                    TapHoldAction::Layer(layer) => _ = self.layers.remove(layer),
+                }
+            }
+        }
+        let held = self.hold.map(|h| h.switches).unwrap_or_default();
+        let switches = SwitchSet(switches.0 & !unchorded_mask.0 & !held.0);
+
+        // leader sequence timed out? (but not while a chord is typed)
+        if let Some(leader) = self.leader {
+            if self.most.0 == C::default() && now.wrapping_sub(leader.last) >= L::sequence_timeout() {
+                return self.end_sequence();
+            }
+        }
+
+        if L::chord_mode() == ChordMode::Rollover {
+            if let Some(outcome) = self.roll(switches, now) {
+                return outcome;
+            }
+        }
 
         // some switches are pressed?
-        if switches.0 != 0 {
+        if switches.0 != C::default() {
+            if self.most.0 == C::default() {
+                self.chord_start = now;
+                self.repeats = 0;
+            }
             self.most.0 |= switches.0;
-            return UsbOutcome::Nothing;
+            if self.hold.is_none() && self.chord_held_for() >= Some(L::hold_threshold()) {
+                if let Some(outcome) = self.start_hold() {
+                    return outcome;
+                }
+            }
+            return self.auto_repeat().unwrap_or(Nothing);
         }
 
         // all switches released
         let most = self.most.0;
         self.most = SwitchSet::default();
-        if most == 0 {
+        if most == C::default() {
+            return UsbOutcome::Nothing;
+        }
+        self.commit(most)
+    }
+
+    /// Commits the chord, if any switches were pressed since the previous
+    /// commit and some are released now. Otherwise, lets [`Self::handle_at`]
+    /// proceed as usual.
+    fn roll(&mut self, switches: SwitchSet<C>, now: u32) -> Option<UsbOutcome<L::Action>> {
+        let prev = mem::replace(&mut self.pressed, switches);
+        if switches.0 & !prev.0 != C::default() {
+            self.fresh = true;
+        }
+        if switches.0.count_ones() >= prev.0.count_ones() {
+            return None;
+        }
+        // the switches still held seed the next chord
+        let most = mem::replace(&mut self.most, switches);
+        if !mem::take(&mut self.fresh) || most.0 == C::default() {
+            return None;
+        }
+        self.chord_start = now;
+        Some(self.commit(most.0))
+    }
+
+    fn commit(&mut self, chord: C) -> UsbOutcome<L::Action> {
+        let translator = self.translator;
+        self.typed = 0;
+        let outcome = self.commit_typed(chord);
+        if self.typed > 0 {
+            let erase = briefs::Output::erase(mem::take(&mut self.typed), None);
+            self.undo.push(undo::Entry { erase, translator });
+        }
+        outcome
+    }
+
+    /// Commits the chord, counting the characters typed in [`Self::typed`].
+    fn commit_typed(&mut self, chord: C) -> UsbOutcome<L::Action> {
+        if self.leader.is_some() {
+            return self.lead(chord);
+        }
+        if self.briefs {
+            let brief = match chord.try_into() {
+                Ok(chord) => self.translator.translate(&L::briefs(), chord),
+                Err(_) => {
+                    self.translator.reset();
+                    None
+                }
+            };
+            if let Some(output) = brief {
+                self.layers.remove_one_shots();
+                self.modifiers.take_one_shot();
+                self.repeats = 0;
+                self.undo.forget(output.replaced());
+                let mut translator = self.translator;
+                if let Some(erase) = translator.undo() {
+                    self.undo.push(undo::Entry { erase, translator });
+                }
+                self.pending_edits = output;
+                return self.next_pending().unwrap_or(UsbOutcome::Nothing);
+            }
+        }
+        let found = self.lookup_stack(chord);
+        self.layers.remove_one_shots();
+        if mem::take(&mut self.repeats) > 0 {
+            // the key was already emitted while held
+            self.modifiers.take_one_shot();
+            return UsbOutcome::Nothing;
+        }
+        match found {
+            Ok(Some(found)) => self.apply_resolved(found, chord),
+            Ok(None) => UsbOutcome::Nothing,
+            Err(err) => UsbOutcome::Error(err),
+        }
+    }
+
+    /// Adds the chord to the leader sequence, and applies the sequence if it's
+    /// done.
+    fn lead(&mut self, chord: C) -> UsbOutcome<L::Action> {
+        let Some(leader) = &mut self.leader else {
+            return UsbOutcome::Nothing;
+        };
+        leader.chords[usize::from(leader.len)] = chord;
+        leader.len += 1;
+        leader.last = self.now;
+        let full = usize::from(leader.len) == MAX_SEQUENCE_LEN;
+        match L::sequence(leader.chords()) {
+            SequenceMatch::Prefix if !full => UsbOutcome::Nothing,
+            SequenceMatch::Ambiguous(outcome) if !full => {
+                leader.matched = Some(outcome);
+                UsbOutcome::Nothing
+            }
+            SequenceMatch::Complete(outcome) | SequenceMatch::Ambiguous(outcome) => {
+                self.leader = None;
+                self.apply(outcome, chord)
+            }
+            SequenceMatch::None | SequenceMatch::Prefix => self.end_sequence(),
+        }
+    }
+
+    /// Ends the leader sequence, applying the longest sequence matched so
+    /// far, if any.
+    fn end_sequence(&mut self) -> UsbOutcome<L::Action> {
+        let Some(leader) = self.leader.take() else {
             return UsbOutcome::Nothing;
+        };
+        let chord = leader.chords().last().copied().unwrap_or_default();
+        match leader.matched {
+            Some(outcome) => self.apply(outcome, chord),
+            None => UsbOutcome::Nothing,
+        }
+    }
+
+    /// Looks up the chord on the layers from the top of the stack down, as
+    /// long as they're transparent.
+    fn lookup_stack(&self, chord: C) -> Result<Option<Resolved<L::Action>>, LookupError> {
+        let base = [self.layer, 0];
+        let base = &base[..if self.layer == 0 { 1 } else { 2 }];
+        let layers = self.layers.iter().map(|(layer, _)| layer).chain(base.iter().copied());
+        for layer in layers {
+            let found = Self::resolve(layer, chord, &mut LookupPath::default())?;
+            if found.is_some() || !L::info(layer).transparent {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Looks up the chord on the layer, then on its [`Lookup::fallbacks`],
+    /// then applies its [`LayerInfo::fallback`]. Follows any
+    /// [`LayerOutcome::FromOtherPlusMask`] found. [`LayerInfo::transparent`]
+    /// is up to the caller.
+    fn resolve(layer: u8, chord: C, path: &mut LookupPath) -> Result<Option<Resolved<L::Action>>, LookupError> {
+        path.enter(layer)?;
+        let mut found = L::lookup(layer, chord).map(|outcome| Resolved { layer, outcome, mask: 0 });
+        for &fallback in L::fallbacks(layer) {
+            if found.is_some() {
+                break;
+            }
+            found = Self::resolve(fallback, chord, path)?;
+        }
+        if found.is_none() {
+            let outcome = match L::info(layer).fallback {
+                LayerFallback::None => None,
+                LayerFallback::Transparent { layer } => Some(LayerOutcome::FromOtherPlusMask { layer, mask: 0 }),
+                LayerFallback::EmitWithMask { layer, mask } => Some(LayerOutcome::FromOtherPlusMask { layer, mask }),
+                LayerFallback::Swallow => Some(LayerOutcome::Emit(UsbOutcome::Nothing)),
+            };
+            found = outcome.map(|outcome| Resolved { layer, outcome, mask: 0 });
+        }
+        if let Some(Resolved { layer, outcome: LayerOutcome::FromOtherPlusMask { layer: other, mask }, .. }) = found {
+            // still considered to be on this layer, e.g. for auto-repeat
+            found = Self::resolve(other, chord, path)?
+                .map(|r| Resolved { layer, outcome: r.outcome, mask: r.mask | mask });
+        }
+        path.leave();
+        Ok(found)
+    }
+
+    fn start_hold(&mut self) -> Option<UsbOutcome<L::Action>> {
+        use layer_stack::LayerMode::Momentary;
+        if self.leader.is_some() {
+            return None;
         }
-        let layer = self.temporary_layer.take().unwrap_or(self.layer);
-        self.resolve(layer, most)
+        let hold = match self.lookup_stack(self.most.0).ok()??.outcome {
+            LayerOutcome::TapHold { hold, .. } => hold,
+            LayerOutcome::PushLayer { layer, mode: Momentary } => TapHoldAction::Layer(layer),
+            _ => return None,
+        };
+        self.layers.remove_one_shots();
+        self.hold = Some(Hold { switches: mem::take(&mut self.most), action: hold });
+        Some(match hold {
+            TapHoldAction::Key(key) => UsbOutcome::KeyPress(key),
+            TapHoldAction::Layer(layer) => {
+                self.shunt_unchorded();
+                self.layers.push(layer, Momentary);
+                UsbOutcome::Nothing
+            }
+        })
     }
 
-    fn resolve(&mut self, layer: u8, chord: u8) -> UsbOutcome<L::KeyWithFlags> {
-        let lookup = match L::lookup(layer, chord) {
-            Some(v) => v,
-            // As a fallback, try if we can find default action on an empty
-            // chord 0 (this chord can't be ever selected as a combination
-            // so we hackily reuse it as a "default" action for a layer)
-            None => match L::lookup(layer, 0) {
-                Some(v) => v,
-                None => return UsbOutcome::Nothing,
-            },
+    fn auto_repeat(&mut self) -> Option<UsbOutcome<L::Action>> {
+        let held_for = self.chord_held_for()?;
+        let chord = self.most.0;
+        let brief = || chord.try_into().ok().and_then(|chord| L::briefs().root().child(chord));
+        if self.leader.is_some() || (self.briefs && brief().is_some()) {
+            return None;
+        }
+        let Resolved { layer, outcome: lookup, mask } = self.lookup_stack(chord).ok()??;
+        let repeat = L::repeat(layer, chord)?;
+        let due = repeat.delay.saturating_add(repeat.interval.saturating_mul(self.repeats));
+        if held_for < due {
+            return None;
+        }
+        let LayerOutcome::Emit(UsbOutcome::KeyHit(key)) = lookup else {
+            return None;
         };
+        let key = self.caps_worded(key | mask | self.modifiers.active());
+        if !Self::types_character(key) {
+            self.undo.clear();
+        } else if self.repeats == 0 {
+            let erase = briefs::Output::erase(1, None);
+            self.undo.push(undo::Entry { erase, translator: self.translator });
+        } else {
+            self.undo.extend(1);
+        }
+        self.repeats += 1;
+        // plus masks are applied to every repeat, and cleared on release
+        Some(UsbOutcome::KeyHit(key))
+    }
+
+    /// Lets the time pass, with the switches unchanged since the previous
+    /// call to [`Self::handle_at`]. Intended for idle periods.
+    pub fn tick(&mut self, now: u32) -> UsbOutcome<L::Action> {
+        self.handle_at(self.switches, now)
+    }
+
+    /// For how many milliseconds the current chord is being held, if any.
+    pub fn chord_held_for(&self) -> Option<u32> {
+        (self.most.0 != C::default()).then(|| self.now.wrapping_sub(self.chord_start))
+    }
+
+    fn apply_resolved(&mut self, found: Resolved<L::Action>, chord: C) -> UsbOutcome<L::Action> {
+        self.modifiers.add_one_shot(found.mask);
+        self.apply(found.outcome, chord)
+    }
+
+    fn apply(&mut self, lookup: LayerOutcome<L::Action>, chord: C) -> UsbOutcome<L::Action> {
         use LayerOutcome::*;
         use core::mem::take;
         match lookup {
             ClearState => {
                 self.shunt_unchorded();
                 take(&mut self.layer);
-                take(&mut self.temporary_layer);
-                take(&mut self.plus_mask);
-                take(&mut self.temporary_plus_mask);
+                self.layers.clear();
+                self.modifiers.clear();
+                self.caps_word = false;
+                self.leader = None;
+                self.briefs = false;
+                self.translator.reset();
+                self.undo.clear();
                 UsbOutcome::Nothing
             }
+            Emit(UsbOutcome::KeyHit(k)) => {
+                let k = self.plus_masked(k);
+                let k = self.caps_worded(k);
+                self.count_typed(k);
+                UsbOutcome::KeyHit(k)
+            }
             Emit(v) => v.map(|k| self.plus_masked(k)),
             LayerSwitchAndEmit { layer, emit } => {
                 self.shunt_unchorded();
@@ -219,28 +868,204 @@ where
             }
             TemporaryLayerSwitch { layer } => {
                 self.shunt_unchorded();
-                self.temporary_layer = Some(layer);
+                self.layers.push(layer, layer_stack::LayerMode::OneShot);
+                UsbOutcome::Nothing
+            }
+            PushLayer { layer, mode } => {
+                use layer_stack::LayerMode::*;
+                self.shunt_unchorded();
+                // when held, it'd be handled in start_hold
+                self.layers.push(layer, if mode == Momentary { OneShot } else { mode });
                 UsbOutcome::Nothing
             }
             TogglePlusMask { mask } => {
-                self.temporary_plus_mask &= !mask;
-                self.plus_mask |= mask;
+                self.modifiers.toggle(mask);
                 UsbOutcome::Nothing
             }
             TemporaryPlusMask { mask } => {
-                self.temporary_plus_mask |= mask;
+                self.modifiers.tap(mask);
                 UsbOutcome::Nothing
             }
             FromOtherPlusMask { layer, mask } => {
-                self.temporary_plus_mask |= mask;
-                // FIXME: protect against infinite recursion
-                self.resolve(layer, chord)
+                // normally already followed when resolving the chord
+                self.modifiers.add_one_shot(mask);
+                match Self::resolve(layer, chord, &mut LookupPath::default()) {
+                    Ok(Some(found)) => self.apply_resolved(found, chord),
+                    Ok(None) => UsbOutcome::Nothing,
+                    Err(err) => UsbOutcome::Error(err),
+                }
+            }
+            EmitText(text) => {
+                self.modifiers.take_one_shot();
+                let typable = text.chars().filter(|&c| self.host_layout.stroke(c).is_some()).count();
+                self.typed = self.typed.saturating_add(typable.try_into().unwrap_or(u16::MAX));
+                self.pending_text = text;
+                self.next_pending().unwrap_or(UsbOutcome::Nothing)
+            }
+            EmitUnicode(c) => {
+                self.modifiers.take_one_shot();
+                self.typed = self.typed.saturating_add(1);
+                self.pending_keys = unicode::Sequence::new(c, self.unicode_method);
+                self.next_pending().unwrap_or(UsbOutcome::Nothing)
+            }
+            EmitChar(c) => {
+                self.modifiers.take_one_shot();
+                self.typed = self.typed.saturating_add(1);
+                self.pending_keys = unicode::Sequence::with_layout(c, self.host_layout, self.unicode_method);
+                self.next_pending().unwrap_or(UsbOutcome::Nothing)
+            }
+            SetUnicodeMethod(method) => {
+                self.unicode_method = method;
+                UsbOutcome::Nothing
+            }
+            SetHostLayout(layout) => {
+                self.host_layout = layout;
+                UsbOutcome::Nothing
+            }
+            TapHold { tap, .. } => self.apply(tap.tapped(), chord),
+            CapsWord => {
+                self.caps_word = !self.caps_word;
+                UsbOutcome::Nothing
+            }
+            Leader => {
+                self.leader = Some(LeaderSequence {
+                    chords: [C::default(); MAX_SEQUENCE_LEN],
+                    len: 0,
+                    last: self.now,
+                    matched: None,
+                });
+                UsbOutcome::Nothing
+            }
+            Briefs => {
+                self.briefs = !self.briefs;
+                self.translator.reset();
+                UsbOutcome::Nothing
+            }
+            Undo => {
+                self.modifiers.take_one_shot();
+                let Some(entry) = self.undo.pop() else {
+                    return UsbOutcome::Nothing;
+                };
+                self.translator = entry.translator;
+                self.pending_edits = entry.erase;
+                self.next_pending().unwrap_or(UsbOutcome::Nothing)
+            }
+        }
+    }
+
+    /// The base layer, as set by [`LayerOutcome::LayerSwitchAndEmit`].
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+
+    /// Layers pushed on top of the base layer.
+    pub fn layer_stack(&self) -> &layer_stack::LayerStack {
+        &self.layers
+    }
+
+    /// Whether [`LayerOutcome::CapsWord`] is on.
+    pub fn caps_word(&self) -> bool {
+        self.caps_word
+    }
+
+    /// Chords typed so far after [`LayerOutcome::Leader`], if a sequence is
+    /// being typed.
+    pub fn leader_sequence(&self) -> Option<&[C]> {
+        self.leader.as_ref().map(|l| l.chords())
+    }
+
+    /// Whether [`LayerOutcome::Briefs`] mode is on.
+    pub fn briefs(&self) -> bool {
+        self.briefs
+    }
+
+    /// Recent outputs, to be erased by [`LayerOutcome::Undo`].
+    pub fn undo_history(&self) -> &undo::History {
+        &self.undo
+    }
+
+    /// Modifiers to be added to the emitted keys, e.g. for showing them.
+    pub fn modifiers(&self) -> &modifiers::Modifiers<keycodes::KeyWithFlags> {
+        &self.modifiers
+    }
+
+    pub fn unicode_method(&self) -> unicode::Method {
+        self.unicode_method
+    }
+
+    pub fn set_unicode_method(&mut self, method: unicode::Method) {
+        self.unicode_method = method;
+    }
+
+    pub fn host_layout(&self) -> host_layout::HostLayout {
+        self.host_layout
+    }
+
+    pub fn set_host_layout(&mut self, layout: host_layout::HostLayout) {
+        self.host_layout = layout;
+    }
+
+    /// Returns further outcomes queued by the most recent call to
+    /// [`Self::handle`], if any. Should be called until `None` is returned.
+    pub fn next_pending(&mut self) -> Option<UsbOutcome<L::Action>> {
+        if let Some(key) = self.pending_keys.next() {
+            return Some(key.map(Into::into));
+        }
+        let mut chars = self.pending_text.chars();
+        while let Some(c) = chars.next() {
+            self.pending_text = chars.as_str();
+            if let Some(stroke) = self.host_layout.stroke(c) {
+                self.pending_keys = unicode::Sequence::stroke(stroke);
+                return self.pending_keys.next().map(|v| v.map(Into::into));
+            }
+        }
+        for edit in &mut self.pending_edits {
+            let c = match edit {
+                briefs::Edit::Backspace => return Some(UsbOutcome::KeyHit(keycodes::BACKSPACE.into())),
+                briefs::Edit::Char(c) => c,
+            };
+            if let Some(stroke) = self.host_layout.stroke(c) {
+                self.pending_keys = unicode::Sequence::stroke(stroke);
+                return self.pending_keys.next().map(|v| v.map(Into::into));
             }
         }
+        None
     }
 
-    fn plus_masked(&mut self, key: L::KeyWithFlags) -> L::KeyWithFlags {
-        key | mem::take(&mut self.temporary_plus_mask) | self.plus_mask
+    fn plus_masked(&mut self, key: L::Action) -> L::Action {
+        key | self.modifiers.take_one_shot() | self.modifiers.locked()
+    }
+
+    fn caps_worded(&mut self, key: L::Action) -> L::Action {
+        if !self.caps_word {
+            return key;
+        }
+        let caps_word = match key.try_into() {
+            Ok(key) => L::caps_word(key),
+            Err(_) => CapsWordKey::End,
+        };
+        match caps_word {
+            CapsWordKey::Shifted => key | keycodes::SHIFT_FLAG,
+            CapsWordKey::Continue => key,
+            CapsWordKey::End => {
+                self.caps_word = false;
+                key
+            }
+        }
+    }
+
+    /// Counts a typed key for [`LayerOutcome::Undo`]. Any other key, like
+    /// `BACKSPACE` or arrows, makes the recent outputs impossible to undo.
+    fn count_typed(&mut self, key: L::Action) {
+        if Self::types_character(key) {
+            self.typed = self.typed.saturating_add(1);
+        } else {
+            self.undo.clear();
+        }
+    }
+
+    fn types_character(key: L::Action) -> bool {
+        key.try_into().is_ok_and(keycodes::types_character)
     }
 
     fn shunt_unchorded(&mut self) {
@@ -249,22 +1074,22 @@ where
     }
 }
 
-fn top_bit(v: u8) -> u8 {
-    if v == 0 { 0 } else { 1u8 << v.ilog2() }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use core::iter;
     use SwitchSet as S;
     use UsbOutcome::{
         KeyHit as Hit, KeyPress as Press, KeyRelease as Release,
-        Nothing,
+        Nothing, Error,
     };
+    use action::{Action::{self, Key}, MOUSE_LEFT, MOUSE_POINTER_TOGGLE, MOUSE_RIGHT};
     use keycodes::*;
     use clawtype_macros::chord;
     use sample_layers::SampleLayers as L;
+    use layer_stack::LayerMode;
+    use modifiers::ModifierState;
 
     #[test]
     fn zero() {
@@ -273,57 +1098,918 @@ mod tests {
     }
 
     #[test]
-    fn key_up_incremental_then_decremental_then_esc_instant() {
+    fn time_stands_still_without_ticks() {
         let mut eng = Engine::<L>::default();
-        assert_eq!(eng.handle(S(0b00_10_00_00)), Nothing);
-        assert_eq!(eng.handle(S(0b00_10_00_10)), Nothing);
-        assert_eq!(eng.handle(S(0b00_10_00_11)), Nothing);
-        assert_eq!(eng.handle(S(0b00_10_00_01)), Nothing);
-        assert_eq!(eng.handle(S(0b00_00_00_01)), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(UP));
-
-        assert_eq!(eng.handle(S(chord!("vvvv"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(ESC));
+        assert_eq!(eng.chord_held_for(), None);
+        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
+        assert_eq!(eng.chord_held_for(), Some(0));
+        assert_eq!(eng.handle(S(chord!("_^_%"))), Nothing);
+        assert_eq!(eng.chord_held_for(), Some(0));
+        assert_eq!(eng.handle(S(0)), Hit(Key(UP)));
+        assert_eq!(eng.chord_held_for(), None);
     }
 
     #[test]
-    fn key_from_shift_layer() {
+    fn chord_hold_time() {
         let mut eng = Engine::<L>::default();
-        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
-        assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // "shift"
-        assert_eq!(eng.handle(S(0)), Nothing);
-        // "shifted" key
-        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(DELETE));
-        // back to "unshifted" key
-        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(BACKSPACE));
+        assert_eq!(eng.handle_at(S(0), 1000), Nothing);
+        assert_eq!(eng.tick(1100), Nothing);
+        assert_eq!(eng.chord_held_for(), None);
+        assert_eq!(eng.handle_at(S(chord!("_^__")), 1200), Nothing);
+        assert_eq!(eng.tick(1300), Nothing);
+        assert_eq!(eng.chord_held_for(), Some(100));
+        // more switches don't restart the chord
+        assert_eq!(eng.handle_at(S(chord!("_^_%")), 1350), Nothing);
+        assert_eq!(eng.tick(1400), Nothing);
+        assert_eq!(eng.chord_held_for(), Some(200));
+        assert_eq!(eng.handle_at(S(0), 1450), Hit(Key(UP)));
+        assert_eq!(eng.chord_held_for(), None);
+        assert_eq!(eng.tick(1500), Nothing);
+        // next chord measured from its own start
+        assert_eq!(eng.handle_at(S(chord!("___^")), 2000), Nothing);
+        assert_eq!(eng.handle_at(S(chord!("___^")), 2002), Nothing);
+        assert_eq!(eng.chord_held_for(), Some(2));
+        assert_eq!(eng.handle_at(S(0), 2004), Hit(Key(E)));
     }
 
     #[test]
-    fn upper_case_letter_from_shift_layer() {
+    fn clock_wraps_around() {
         let mut eng = Engine::<L>::default();
-        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
-        assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // "shift"
-        assert_eq!(eng.handle(S(0)), Nothing);
-        // "shifted" key
-        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(E | SHIFT_FLAG));
-        // back to "unshifted" key
-        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(E));
-
-        // another try
-
-        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
-        assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // "shift"
-        assert_eq!(eng.handle(S(0)), Nothing);
-        // "shifted" key
-        assert_eq!(eng.handle(S(chord!("__vv"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(C | SHIFT_FLAG));
-        // back to "unshifted" key
-        assert_eq!(eng.handle(S(chord!("__vv"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(C));
+        assert_eq!(eng.handle_at(S(chord!("___^")), u32::MAX - 9), Nothing);
+        assert_eq!(eng.tick(5), Nothing);
+        assert_eq!(eng.chord_held_for(), Some(15));
+        assert_eq!(eng.handle_at(S(0), 7), Hit(Key(E)));
+    }
+
+    #[test]
+    fn ticks_keep_old_behaviour() {
+        let inputs = [
+            chord!("_^_^"), chord!("____"),
+            chord!("%_%_"), chord!("____"), chord!("___^"), chord!("____"),
+            chord!("v^_v"), chord!("____"), chord!("___^"), chord!("____"),
+            chord!("^^__"), chord!("____"), chord!("___^"), chord!("____"),
+        ];
+        let mut plain = Engine::<L>::default();
+        let mut timed = Engine::<L>::default();
+        let mut now = 0;
+        for input in inputs {
+            let expected = plain.handle(S(input));
+            now += 2;
+            assert_eq!(timed.handle_at(S(input), now), expected);
+            for _ in 0..100 {
+                now += 2;
+                assert_eq!(timed.tick(now), plain.handle(S(input)));
+            }
+        }
+    }
+
+    const SPACE_CTRL: u8 = chord!("_^v_");
+    const SHIFT_HOLD: u8 = chord!("_v^_");
+
+    /// [`SampleLayers`](L) with tap-hold chords on chords it doesn't use.
+    struct TapHolds;
+
+    impl Lookup for TapHolds {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            match (layer, chord) {
+                (0, SPACE_CTRL) => Some(LayerOutcome::TapHold {
+                    tap: TapHoldAction::Key(Key(SPACE)),
+                    hold: TapHoldAction::Key(Key(CTRL_FLAG)),
+                }),
+                (0, SHIFT_HOLD) => Some(LayerOutcome::TapHold {
+                    tap: TapHoldAction::Layer(1),
+                    hold: TapHoldAction::Layer(1),
+                }),
+                _ => L::lookup(layer, chord),
+            }
+        }
+
+        fn info(layer: u8) -> LayerInfo {
+            L::info(layer)
+        }
+    }
+
+    #[test]
+    fn tap_and_hold() {
+        let space_ctrl = SPACE_CTRL;
+        let e = chord!("___^");
+        let mut eng = Engine::<TapHolds>::default();
+        // tap
+        assert_eq!(eng.handle_at(S(space_ctrl), 1000), Nothing);
+        assert_eq!(eng.tick(1199), Nothing);
+        assert_eq!(eng.handle_at(S(0), 1199), Hit(Key(SPACE)));
+        assert_eq!(eng.tick(1300), Nothing);
+
+        // hold, with a chord typed meanwhile
+        assert_eq!(eng.handle_at(S(space_ctrl), 2000), Nothing);
+        assert_eq!(eng.tick(2199), Nothing);
+        assert_eq!(eng.tick(2200), Press(Key(CTRL_FLAG)));
+        assert_eq!(eng.tick(2300), Nothing);
+        assert_eq!(eng.handle_at(S(space_ctrl | e), 2310), Nothing);
+        // holding the other chord long doesn't matter
+        assert_eq!(eng.tick(2600), Nothing);
+        assert_eq!(eng.handle_at(S(space_ctrl), 2610), Hit(Key(E)));
+        assert_eq!(eng.handle_at(S(space_ctrl | e), 2620), Nothing);
+        assert_eq!(eng.handle_at(S(space_ctrl), 2630), Hit(Key(E)));
+        assert_eq!(eng.handle_at(S(0), 2700), Release(Key(CTRL_FLAG)));
+        assert_eq!(eng.tick(2800), Nothing);
+
+        // hold and release, without anything meanwhile
+        assert_eq!(eng.handle_at(S(space_ctrl), 3000), Nothing);
+        assert_eq!(eng.tick(3500), Press(Key(CTRL_FLAG)));
+        assert_eq!(eng.handle_at(S(0), 3600), Release(Key(CTRL_FLAG)));
+        assert_eq!(eng.tick(3700), Nothing);
+    }
+
+    #[test]
+    fn hold_released_together_with_other_chord() {
+        let space_ctrl = SPACE_CTRL;
+        let e = chord!("___^");
+        let mut eng = Engine::<TapHolds>::default();
+        assert_eq!(eng.handle_at(S(space_ctrl), 0), Nothing);
+        assert_eq!(eng.tick(300), Press(Key(CTRL_FLAG)));
+        assert_eq!(eng.handle_at(S(space_ctrl | e), 310), Nothing);
+        assert_eq!(eng.handle_at(S(0), 320), Hit(Key(E)));
+        assert_eq!(eng.tick(322), Release(Key(CTRL_FLAG)));
+        assert_eq!(eng.tick(324), Nothing);
+    }
+
+    #[test]
+    fn hold_layer() {
+        let shift = SHIFT_HOLD;
+        let e = chord!("___^");
+        let mut eng = Engine::<TapHolds>::default();
+        assert_eq!(eng.handle_at(S(shift), 0), Nothing);
+        assert_eq!(eng.tick(250), Nothing);
+        for t in [300, 400] {
+            assert_eq!(eng.handle_at(S(shift | e), t), Nothing);
+            assert_eq!(eng.handle_at(S(shift), t + 10), Hit(Key(E | SHIFT_FLAG)));
+        }
+        assert_eq!(eng.handle_at(S(0), 500), Nothing);
+        assert_eq!(eng.handle_at(S(e), 510), Nothing);
+        assert_eq!(eng.handle_at(S(0), 520), Hit(Key(E)));
+
+        // tap is a one-shot layer switch
+        assert_eq!(eng.handle_at(S(shift), 600), Nothing);
+        assert_eq!(eng.handle_at(S(0), 650), Nothing);
+        for (t, expected) in [(700, Hit(Key(E | SHIFT_FLAG))), (800, Hit(Key(E)))] {
+            assert_eq!(eng.handle_at(S(e), t), Nothing);
+            assert_eq!(eng.handle_at(S(0), t + 10), expected);
+        }
+    }
+
+    fn tap<T, C>(eng: &mut Engine<T, C>, chord: C) -> UsbOutcome<Action>
+    where T: Lookup<C, Action = Action>, C: Chord,
+    {
+        assert_eq!(eng.handle(S(chord)), Nothing);
+        eng.handle(S::default())
+    }
+
+    #[test]
+    fn toggled_layer_is_transparent() {
+        let (num, up, one) = (chord!("v_^_"), chord!("_^_%"), chord!("___^"));
+        let mut eng = Engine::<L>::default();
+        assert_eq!(tap(&mut eng, num), Nothing);
+        assert_eq!(tap(&mut eng, one), Hit(Key(KEY_1)));
+        assert_eq!(tap(&mut eng, up), Hit(Key(UP))); // from layer 0
+        assert_eq!(tap(&mut eng, one), Hit(Key(KEY_1)));
+        assert_eq!(eng.layer_stack().iter().collect::<Vec<_>>(), [(3, LayerMode::Toggle)]);
+        // one-shot on top of the toggled layer
+        assert_eq!(tap(&mut eng, chord!("_vv_")), Nothing);
+        assert_eq!(tap(&mut eng, up), Hit(Key(HOME)));
+        assert_eq!(tap(&mut eng, one), Hit(Key(KEY_1)));
+        // toggled off
+        assert_eq!(tap(&mut eng, num), Nothing);
+        assert_eq!(tap(&mut eng, one), Hit(Key(E)));
+        assert!(eng.layer_stack().is_empty());
+    }
+
+    #[test]
+    fn locked_layer_until_cleared() {
+        let (num, two) = (chord!("vv^_"), chord!("__^_"));
+        let mut eng = Engine::<L>::default();
+        assert_eq!(tap(&mut eng, num), Nothing);
+        assert_eq!(tap(&mut eng, num), Nothing);
+        assert_eq!(tap(&mut eng, two), Hit(Key(KEY_2)));
+        assert_eq!(tap(&mut eng, two), Hit(Key(KEY_2)));
+        assert_eq!(tap(&mut eng, chord!("%%%%")), Nothing);
+        assert!(eng.layer_stack().is_empty());
+        assert_eq!(tap(&mut eng, two), Hit(Key(SPACE)));
+    }
+
+    #[test]
+    fn momentary_layer() {
+        let num = chord!("vv_^");
+        let three = chord!("_^__");
+        let mut eng = Engine::<L>::default();
+        // held
+        assert_eq!(eng.handle_at(S(num), 0), Nothing);
+        assert_eq!(eng.tick(250), Nothing);
+        assert_eq!(eng.layer_stack().iter().collect::<Vec<_>>(), [(3, LayerMode::Momentary)]);
+        for t in [300, 400] {
+            assert_eq!(eng.handle_at(S(num | three), t), Nothing);
+            assert_eq!(eng.handle_at(S(num), t + 10), Hit(Key(KEY_3)));
+        }
+        assert_eq!(eng.handle_at(S(0), 500), Nothing);
+        assert!(eng.layer_stack().is_empty());
+        // tapped
+        assert_eq!(eng.handle_at(S(num), 600), Nothing);
+        assert_eq!(eng.handle_at(S(0), 650), Nothing);
+        assert_eq!(eng.layer_stack().iter().collect::<Vec<_>>(), [(3, LayerMode::OneShot)]);
+        for (t, expected) in [(700, Hit(Key(KEY_3))), (800, Hit(Key(BACKSPACE)))] {
+            assert_eq!(eng.handle_at(S(three), t), Nothing);
+            assert_eq!(eng.handle_at(S(0), t + 10), expected);
+        }
+    }
+
+    #[test]
+    fn no_holds_without_ticks() {
+        let mut eng = Engine::<L>::default();
+        for _ in 0..1000 {
+            assert_eq!(eng.handle(S(chord!("__^_"))), Nothing);
+        }
+        assert_eq!(eng.handle(S(0)), Hit(Key(SPACE)));
+    }
+
+    #[test]
+    fn auto_repeat() {
+        let up = chord!("_^_%");
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle_at(S(up), 1000), Nothing);
+        assert_eq!(eng.tick(1299), Nothing);
+        assert_eq!(eng.tick(1300), Hit(Key(UP)));
+        assert_eq!(eng.tick(1310), Nothing);
+        assert_eq!(eng.tick(1349), Nothing);
+        assert_eq!(eng.tick(1350), Hit(Key(UP)));
+        assert_eq!(eng.tick(1351), Nothing);
+        assert_eq!(eng.tick(1400), Hit(Key(UP)));
+        // no extra hit on release
+        assert_eq!(eng.handle_at(S(0), 1420), Nothing);
+        assert_eq!(eng.tick(1500), Nothing);
+        // next chords are not affected
+        assert_eq!(eng.handle_at(S(up), 1600), Nothing);
+        assert_eq!(eng.handle_at(S(0), 1700), Hit(Key(UP)));
+        assert_eq!(eng.handle_at(S(chord!("___^")), 1800), Nothing);
+        assert_eq!(eng.handle_at(S(0), 1810), Hit(Key(E)));
+    }
+
+    #[test]
+    fn auto_repeat_release_right_when_due() {
+        let up = chord!("_^_%");
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle_at(S(up), 0), Nothing);
+        assert_eq!(eng.handle_at(S(0), 300), Hit(Key(UP)));
+        assert_eq!(eng.tick(400), Nothing);
+        assert_eq!(eng.handle_at(S(up), 1000), Nothing);
+        assert_eq!(eng.tick(1300), Hit(Key(UP)));
+        assert_eq!(eng.handle_at(S(0), 1350), Nothing);
+        assert_eq!(eng.tick(1400), Nothing);
+    }
+
+    #[test]
+    fn auto_repeat_only_where_configured() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle_at(S(chord!("___^")), 0), Nothing);
+        for t in (2..2000).step_by(2) {
+            assert_eq!(eng.tick(t), Nothing);
+        }
+        assert_eq!(eng.handle_at(S(0), 2000), Hit(Key(E)));
+        // not without time passing
+        for _ in 0..1000 {
+            assert_eq!(eng.handle(S(chord!("_^_%"))), Nothing);
+        }
+        assert_eq!(eng.handle(S(0)), Hit(Key(UP)));
+    }
+
+    #[test]
+    fn auto_repeat_with_plus_masks() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle_at(S(chord!("_^^_")), 0), Nothing); // Ctrl
+        assert_eq!(eng.handle_at(S(0), 10), Nothing);
+        // Shift for one chord
+        assert_eq!(eng.handle_at(S(chord!("_vv_")), 20), Nothing);
+        assert_eq!(eng.handle_at(S(0), 30), Nothing);
+        assert_eq!(eng.handle_at(S(chord!("v__%")), 100), Nothing);
+        assert_eq!(eng.tick(400), Nothing); // the shift layer has no repeat
+        assert_eq!(eng.handle_at(S(0), 410), Hit(Key(RIGHT | SHIFT_FLAG | CTRL_FLAG)));
+
+        assert_eq!(eng.handle_at(S(chord!("_^^_")), 500), Nothing); // Ctrl
+        assert_eq!(eng.handle_at(S(0), 510), Nothing);
+        assert_eq!(eng.handle_at(S(chord!("v__%")), 600), Nothing);
+        assert_eq!(eng.tick(900), Hit(Key(RIGHT | CTRL_FLAG)));
+        assert_eq!(eng.tick(950), Hit(Key(RIGHT | CTRL_FLAG)));
+        assert_eq!(eng.handle_at(S(0), 960), Nothing);
+        // Ctrl was consumed
+        assert_eq!(eng.handle_at(S(chord!("v__%")), 1000), Nothing);
+        assert_eq!(eng.handle_at(S(0), 1010), Hit(Key(RIGHT)));
+    }
+
+    /// A broken layout, with layers falling back to each other.
+    struct Cyclic;
+
+    impl Lookup for Cyclic {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            use LayerOutcome::*;
+            let switch = |layer| LayerSwitchAndEmit { layer, emit: Nothing };
+            Some(match (layer, chord) {
+                (0, 0b10_00_00_00) => switch(1),
+                (0, 0b00_10_00_00) => switch(3),
+                (0, 0b00_00_10_00) => switch(5),
+                (0, 0b00_00_00_10) => Emit(Hit(Key(E))),
+                (1, 0b01_00_00_00) => Emit(Hit(Key(KEY_1))),
+                (2, 0b00_01_00_00) => Emit(Hit(Key(KEY_2))),
+                (3, 0b01_00_00_00) => Emit(Hit(Key(KEY_3))),
+                (4, 0b00_01_00_00) => Emit(Hit(Key(T))),
+                (6, 0b00_01_00_00) => Emit(Hit(Key(KEY_6))),
+                (20, 0b01_00_00_00) => Emit(Hit(Key(KEY_0))),
+                _ => return None,
+            })
+        }
+
+        fn info(layer: u8) -> LayerInfo {
+            let fallback = match layer {
+                3 => LayerFallback::EmitWithMask { layer: 4, mask: SHIFT_FLAG },
+                4 => LayerFallback::Transparent { layer: 3 },
+                _ => LayerFallback::None,
+            };
+            LayerInfo { fallback, ..Default::default() }
+        }
+
+        fn fallbacks(layer: u8) -> &'static [u8] {
+            // each of layers 5 to 19 falls back to the next one
+            static CHAIN: [u8; 21] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20];
+            match layer {
+                1 => &[2, 0],
+                2 => &[1],
+                5..20 => &CHAIN[usize::from(layer) + 1..][..1],
+                _ => &[],
+            }
+        }
+    }
+
+    fn cyclic_on(layer_chord: u8) -> Engine<Cyclic> {
+        let mut eng = Engine::<Cyclic>::default();
+        assert_eq!(eng.handle(S(layer_chord)), Nothing);
+        assert_eq!(eng.handle(S(0)), Nothing);
+        eng
+    }
+
+    #[test]
+    fn fallback_cycle() {
+        let mut eng = cyclic_on(chord!("^___"));
+        assert_eq!(eng.handle(S(chord!("v___"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_1)));
+        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_2)));
+        // not on 1, nor on 2, which falls back to 1 again before 0 is tried
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Error(LookupError::Cycle { layer: 1 }));
+    }
+
+    #[test]
+    fn default_action_cycle() {
+        let mut eng = cyclic_on(chord!("_^__"));
+        assert_eq!(eng.handle(S(chord!("v___"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_3)));
+        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(T | SHIFT_FLAG)));
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Error(LookupError::Cycle { layer: 3 }));
+        // the engine is still usable
+        assert_eq!(eng.handle(S(chord!("v___"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_3)));
+    }
+
+    #[test]
+    fn fallback_too_deep() {
+        let mut eng = cyclic_on(chord!("__^_"));
+        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_6)));
+        assert_eq!(eng.handle(S(chord!("v___"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Error(LookupError::TooDeep));
+    }
+
+    /// Layer 1 has its own chord, falls back to layer 2, then emits from
+    /// layer 3 with Shift, and is transparent.
+    struct FallbackOrder;
+
+    impl Lookup for FallbackOrder {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            use LayerOutcome::*;
+            Some(match (layer, chord) {
+                (0, 0b10_10_10_10) => PushLayer { layer: 1, mode: layer_stack::LayerMode::Toggle },
+                (0, 0b00_00_10_00 | 0b00_10_00_00) => Emit(Hit(Key(Q))),
+                (0, 0b10_00_00_00) => Emit(Hit(Key(D))),
+                (1, 0b00_00_00_10) => Emit(Hit(Key(A))),
+                (2, 0b00_00_10_00) => Emit(Hit(Key(B))),
+                (3, 0b00_00_10_00) => Emit(Hit(Key(KEY_3))),
+                (3, 0b00_10_00_00) => Emit(Hit(Key(C))),
+                _ => return None,
+            })
+        }
+
+        fn info(layer: u8) -> LayerInfo {
+            match layer {
+                1 => LayerInfo {
+                    transparent: true,
+                    fallback: LayerFallback::EmitWithMask { layer: 3, mask: SHIFT_FLAG },
+                    ..Default::default()
+                },
+                _ => LayerInfo::default(),
+            }
+        }
+
+        fn fallbacks(layer: u8) -> &'static [u8] {
+            match layer {
+                1 => &[2],
+                _ => &[],
+            }
+        }
+    }
+
+    #[test]
+    fn fallbacks_before_fallback_before_transparent() {
+        let mut eng = Engine::<FallbackOrder>::default();
+        for (chord, expected) in [
+            (chord!("^^^^"), Nothing),
+            // own chord
+            (chord!("___^"), Hit(Key(A))),
+            // on the fallback layer 2, and on layer 3
+            (chord!("__^_"), Hit(Key(B))),
+            // only on layer 3, and on layer 0 below
+            (chord!("_^__"), Hit(Key(C | SHIFT_FLAG))),
+            // not on layer 3 either
+            (chord!("^___"), Hit(Key(D))),
+        ] {
+            assert_eq!(eng.handle(S(chord)), Nothing);
+            assert_eq!(eng.handle(S(0)), expected);
+        }
+    }
+
+    struct Swallowing;
+
+    impl Lookup for Swallowing {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            L::lookup(layer, chord)
+        }
+
+        fn info(layer: u8) -> LayerInfo {
+            LayerInfo { fallback: LayerFallback::Swallow, ..L::info(layer) }
+        }
+    }
+
+    #[test]
+    fn swallow_stops_transparency() {
+        let mut eng = Engine::<Swallowing>::default();
+        let (num, up) = (chord!("vv_^"), chord!("_^_%"));
+        // one-shot NUM is transparent, but doesn't let UP through
+        for (chord, expected) in [(num, Nothing), (chord!("___^"), Hit(Key(KEY_1))), (num, Nothing), (up, Nothing)] {
+            assert_eq!(eng.handle(S(chord)), Nothing);
+            assert_eq!(eng.handle(S(0)), expected);
+        }
+        assert_eq!(eng.handle(S(chord!("_^_%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(UP)));
+    }
+
+    struct Rolling;
+
+    impl Lookup for Rolling {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            L::lookup(layer, chord)
+        }
+
+        fn chord_mode() -> ChordMode { ChordMode::Rollover }
+    }
+
+    #[test]
+    fn rollover() {
+        let mut eng = Engine::<Rolling>::default();
+        // D, rolled into TAB
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("__^^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("___^"))), Hit(Key(D)));
+        assert_eq!(eng.handle(S(chord!("_^_^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("_^__"))), Hit(Key(TAB)));
+        // releasing the rest of the seed doesn't emit anything
+        assert_eq!(eng.handle(S(0)), Nothing);
+        // plain chords still work
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E)));
+        assert_eq!(eng.handle(S(chord!("__^^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(D)));
+    }
+
+    #[test]
+    fn rollover_seed_released_before_next_chord() {
+        let mut eng = Engine::<Rolling>::default();
+        assert_eq!(eng.handle(S(chord!("__^^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("___^"))), Hit(Key(D)));
+        assert_eq!(eng.handle(S(0)), Nothing);
+        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(BACKSPACE)));
+        // a switch swapped for another, without the count decreasing
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("__^_"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(D)));
+    }
+
+    #[test]
+    fn rollover_not_by_default() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("__^^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("_^_^"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(PERIOD)));
+    }
+
+    #[test]
+    fn key_up_incremental_then_decremental_then_esc_instant() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(0b00_10_00_00)), Nothing);
+        assert_eq!(eng.handle(S(0b00_10_00_10)), Nothing);
+        assert_eq!(eng.handle(S(0b00_10_00_11)), Nothing);
+        assert_eq!(eng.handle(S(0b00_10_00_01)), Nothing);
+        assert_eq!(eng.handle(S(0b00_00_00_01)), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(UP)));
+
+        assert_eq!(eng.handle(S(chord!("vvvv"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(ESC)));
+    }
+
+    #[test]
+    fn key_from_shift_layer() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // "shift"
+        assert_eq!(eng.handle(S(0)), Nothing);
+        // "shifted" key
+        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(DELETE)));
+        // back to "unshifted" key
+        assert_eq!(eng.handle(S(chord!("_^__"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(BACKSPACE)));
+    }
+
+    #[test]
+    fn upper_case_letter_from_shift_layer() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // "shift"
+        assert_eq!(eng.handle(S(0)), Nothing);
+        // "shifted" key
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E | SHIFT_FLAG)));
+        // back to "unshifted" key
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E)));
+
+        // another try
+
+        assert_eq!(eng.handle(S(chord!("_v__"))), Nothing);
+        assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // "shift"
+        assert_eq!(eng.handle(S(0)), Nothing);
+        // "shifted" key
+        assert_eq!(eng.handle(S(chord!("__vv"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(C | SHIFT_FLAG)));
+        // back to "unshifted" key
+        assert_eq!(eng.handle(S(chord!("__vv"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(C)));
+    }
+
+    #[test]
+    fn caps_word() {
+        let (caps, e, minus, one, space) =
+            (chord!("%%%^"), chord!("___^"), chord!("vvv_"), chord!("___^"), chord!("__^_"));
+        let mut eng = Engine::<L>::default();
+        assert_eq!(tap(&mut eng, caps), Nothing);
+        assert!(eng.caps_word());
+        assert_eq!(tap(&mut eng, e), Hit(Key(E | SHIFT_FLAG)));
+        assert_eq!(tap(&mut eng, minus), Hit(Key(MINUS | SHIFT_FLAG)));
+        assert_eq!(tap(&mut eng, chord!("vv_^")), Nothing); // one-shot NUM layer
+        assert_eq!(tap(&mut eng, one), Hit(Key(KEY_1)));
+        assert_eq!(tap(&mut eng, e), Hit(Key(E | SHIFT_FLAG)));
+        // space ends the word, but is emitted
+        assert_eq!(tap(&mut eng, space), Hit(Key(SPACE)));
+        assert!(!eng.caps_word());
+        assert_eq!(tap(&mut eng, e), Hit(Key(E)));
+
+        // tapped again, ends the word
+        assert_eq!(tap(&mut eng, caps), Nothing);
+        assert_eq!(tap(&mut eng, e), Hit(Key(E | SHIFT_FLAG)));
+        assert_eq!(tap(&mut eng, caps), Nothing);
+        assert_eq!(tap(&mut eng, e), Hit(Key(E)));
+    }
+
+    struct SnakeCaps;
+
+    impl Lookup for SnakeCaps {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            L::lookup(layer, chord)
+        }
+
+        fn caps_word(key: KeyWithFlags) -> CapsWordKey {
+            match key {
+                SPACE => CapsWordKey::Continue,
+                _ => CapsWordKey::default_for(key),
+            }
+        }
+    }
+
+    #[test]
+    fn caps_word_keys_from_lookup() {
+        let mut eng = Engine::<SnakeCaps>::default();
+        for (chord, expected) in [
+            (chord!("%%%^"), Nothing),
+            (chord!("___^"), Hit(Key(E | SHIFT_FLAG))),
+            (chord!("__^_"), Hit(Key(SPACE))),
+            (chord!("___^"), Hit(Key(E | SHIFT_FLAG))),
+            (chord!("_%_%"), Hit(Key(ENTER))),
+            (chord!("___^"), Hit(Key(E))),
+        ] {
+            assert_eq!(eng.handle(S(chord)), Nothing);
+            assert_eq!(eng.handle(S(0)), expected);
+        }
+    }
+
+    const LEADER: u8 = chord!("%%%v");
+    const GIT: u8 = chord!("_^_v"); // G
+
+    /// Taps the chord, and collects all outcomes.
+    fn tap_all<T, C>(eng: &mut Engine<T, C>, chord: C) -> Vec<UsbOutcome<Action>>
+    where T: Lookup<C, Action = Action>, C: Chord,
+    {
+        let mut all = vec![tap(eng, chord)];
+        all.extend(iter::from_fn(|| eng.next_pending()));
+        all
+    }
+
+    #[test]
+    fn leader_sequence_with_prefix() {
+        let (t, o, d) = (chord!("___v"), chord!("__%_"), chord!("__^^"));
+        let mut eng = Engine::<L>::default();
+        assert_eq!(tap(&mut eng, LEADER), Nothing);
+        assert_eq!(eng.leader_sequence(), Some(&[][..]));
+        assert_eq!(tap(&mut eng, t), Nothing);
+        assert_eq!(tap(&mut eng, o), Nothing);
+        assert_eq!(eng.leader_sequence(), Some(&[t, o][..]));
+        assert_eq!(tap_all(&mut eng, d), [Hit(Key(T | SHIFT_FLAG)), Hit(Key(O | SHIFT_FLAG)), Hit(Key(D | SHIFT_FLAG)),
+            Hit(Key(O | SHIFT_FLAG)), Hit(Key(SEMICOLON | SHIFT_FLAG)), Hit(Key(SPACE))]);
+        assert_eq!(eng.leader_sequence(), None);
+        assert_eq!(tap(&mut eng, t), Hit(Key(T)));
+    }
+
+    #[test]
+    fn leader_sequence_not_matching() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(tap(&mut eng, LEADER), Nothing);
+        assert_eq!(tap(&mut eng, chord!("___v")), Nothing); // T
+        assert_eq!(tap(&mut eng, chord!("___v")), Nothing);
+        assert_eq!(eng.leader_sequence(), None);
+        // chords are not looked up on layers while in the sequence
+        assert_eq!(tap(&mut eng, LEADER), Nothing);
+        assert_eq!(tap(&mut eng, chord!("_vv_")), Nothing); // SHIFT
+        assert_eq!(tap(&mut eng, chord!("___v")), Hit(Key(T)));
+    }
+
+    #[test]
+    fn leader_sequence_ambiguous() {
+        let d = chord!("__^^");
+        let mut eng = Engine::<L>::default();
+        // the longer sequence typed
+        assert_eq!(tap(&mut eng, LEADER), Nothing);
+        assert_eq!(tap(&mut eng, GIT), Nothing);
+        assert_eq!(tap_all(&mut eng, d).len(), "git diff\n".len());
+        // the shorter one, when the next chord doesn't match a longer one
+        assert_eq!(tap(&mut eng, LEADER), Nothing);
+        assert_eq!(tap(&mut eng, GIT), Nothing);
+        assert_eq!(tap_all(&mut eng, chord!("___^")), [Hit(Key(G)), Hit(Key(I)), Hit(Key(T)), Hit(Key(SPACE))]);
+        assert_eq!(eng.leader_sequence(), None);
+        assert_eq!(tap(&mut eng, d), Hit(Key(D)));
+    }
+
+    #[test]
+    fn leader_sequence_timeout() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle_at(S(LEADER), 0), Nothing);
+        assert_eq!(eng.handle_at(S(0), 10), Nothing);
+        assert_eq!(eng.handle_at(S(chord!("___v")), 500), Nothing); // T
+        assert_eq!(eng.handle_at(S(0), 510), Nothing);
+        // not timed out while a chord is held
+        assert_eq!(eng.handle_at(S(chord!("__%_")), 1000), Nothing); // O
+        assert_eq!(eng.tick(1600), Nothing);
+        assert_eq!(eng.handle_at(S(0), 1610), Nothing);
+        assert_eq!(eng.leader_sequence().map(<[u8]>::len), Some(2));
+        assert_eq!(eng.tick(2609), Nothing);
+        assert_eq!(eng.leader_sequence().map(<[u8]>::len), Some(2));
+        assert_eq!(eng.tick(2610), Nothing);
+        assert_eq!(eng.leader_sequence(), None);
+
+        // an ambiguous sequence is applied on timeout
+        assert_eq!(eng.handle_at(S(LEADER), 3000), Nothing);
+        assert_eq!(eng.handle_at(S(0), 3010), Nothing);
+        assert_eq!(eng.handle_at(S(GIT), 3020), Nothing);
+        assert_eq!(eng.handle_at(S(0), 3030), Nothing);
+        assert_eq!(eng.tick(4030), Hit(Key(G)));
+        assert_eq!(iter::from_fn(|| eng.next_pending()).count(), "it ".len());
+    }
+
+    const BRIEFS: u8 = chord!("%%_%");
+
+    struct Steno;
+
+    impl Lookup for Steno {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            match chord {
+                BRIEFS => Some(LayerOutcome::Briefs),
+                _ => L::lookup(layer, chord),
+            }
+        }
+
+        fn briefs() -> briefs::Dictionary<'static> {
+            static BYTES: std::sync::OnceLock<Vec<u8>> = std::sync::OnceLock::new();
+            let bytes = BYTES.get_or_init(|| {
+                let entries = briefs::source::parse("___v the\n___v/__v_ then\n__^^ {.}\n").unwrap();
+                briefs::source::compile(&entries).unwrap()
+            });
+            briefs::Dictionary::new(bytes).unwrap()
+        }
+    }
+
+    #[test]
+    fn briefs() {
+        let (the, n, dot) = (chord!("___v"), chord!("__v_"), chord!("__^^"));
+        let mut eng = Engine::<Steno>::default();
+        assert_eq!(tap(&mut eng, BRIEFS), Nothing);
+        assert!(eng.briefs());
+        assert_eq!(tap_all(&mut eng, the), [Hit(Key(T)), Hit(Key(H)), Hit(Key(E))]);
+        assert_eq!(tap_all(&mut eng, n), [
+            Hit(Key(BACKSPACE)), Hit(Key(BACKSPACE)), Hit(Key(BACKSPACE)), Hit(Key(T)), Hit(Key(H)), Hit(Key(E)), Hit(Key(N))]);
+        assert_eq!(tap_all(&mut eng, dot), [Hit(Key(PERIOD))]);
+        assert_eq!(tap_all(&mut eng, the), [Hit(Key(SPACE)), Hit(Key(T | SHIFT_FLAG)), Hit(Key(H)), Hit(Key(E))]);
+        // chords not in the dictionary are looked up on the layers
+        assert_eq!(tap_all(&mut eng, chord!("___^")), [Hit(Key(E))]);
+        assert_eq!(tap_all(&mut eng, the), [Hit(Key(T)), Hit(Key(H)), Hit(Key(E))]);
+
+        assert_eq!(tap(&mut eng, BRIEFS), Nothing);
+        assert!(!eng.briefs());
+        assert_eq!(tap(&mut eng, the), Hit(Key(T)));
+    }
+
+    #[test]
+    fn wide_chord_patterns() {
+        assert_eq!(chord!("v^_%"), 0b01_10_00_11_u8);
+        assert_eq!(chord!("v^_%^"), 0b01_10_00_11_10_u16);
+        assert_eq!(chord!("v^_%^v"), 0b01_10_00_11_10_01_u16);
+    }
+
+    /// 4 fingers, and a thumb with 3 switches, taking 2 positions.
+    struct Thumb;
+
+    impl Lookup<u16> for Thumb {
+        type Action = Action;
+
+        fn lookup(_layer: u8, chord: u16) -> Option<LayerOutcome<Action>> {
+            lookup_in_slice(chord, &[
+                (chord!("___^__"), LayerOutcome::Emit(Hit(Key(E)))),
+                (chord!("____^_"), LayerOutcome::Emit(Hit(Key(SPACE)))),
+                (chord!("___^%_"), LayerOutcome::Emit(Hit(Key(E | SHIFT_FLAG)))),
+                (chord!("_____^"), LayerOutcome::Leader),
+            ]).copied()
+        }
+
+        fn info(_layer: u8) -> LayerInfo<u16> {
+            LayerInfo { unchorded_mask: S(chord!("_____v")), ..Default::default() }
+        }
+
+        fn unchorded_key(_layer: u8, switch: SwitchSet<u16>) -> Option<Action> {
+            (switch.0 == chord!("_____v")).then_some(MOUSE_LEFT)
+        }
+
+        fn sequence(chords: &[u16]) -> SequenceMatch<Action> {
+            lookup_sequence(chords, &[
+                (&[chord!("___^__"), chord!("____^_")], LayerOutcome::EmitText("e ")),
+            ])
+        }
+    }
+
+    #[test]
+    fn wide_chords() {
+        let mut eng = Engine::<Thumb, u16>::default();
+        assert_eq!(tap(&mut eng, chord!("___^__")), Hit(Key(E)));
+        assert_eq!(tap(&mut eng, chord!("___^%_")), Hit(Key(E | SHIFT_FLAG)));
+        assert_eq!(eng.handle(S(chord!("_____v"))), Press(MOUSE_LEFT));
+        assert_eq!(eng.handle(S(0)), Release(MOUSE_LEFT));
+        assert_eq!(tap(&mut eng, chord!("_____^")), Nothing);
+        assert_eq!(tap(&mut eng, chord!("___^__")), Nothing);
+        assert_eq!(eng.leader_sequence(), Some(&[chord!("___^__")][..]));
+        assert_eq!(tap_all(&mut eng, chord!("____^_")), [Hit(Key(E)), Hit(Key(SPACE))]);
+    }
+
+    struct Wider;
+
+    impl Lookup<u32> for Wider {
+        type Action = Action;
+
+        fn lookup(_layer: u8, chord: u32) -> Option<LayerOutcome<Action>> {
+            lookup_in_slice(chord, &[
+                (1 << 20, LayerOutcome::Emit(Hit(Key(A)))),
+                ((1 << 20) | 1, LayerOutcome::Emit(Hit(Key(B)))),
+            ]).copied()
+        }
+    }
+
+    #[test]
+    fn wider_chords() {
+        let mut eng = Engine::<Wider, u32>::default();
+        assert_eq!(eng.handle(S(1 << 20)), Nothing);
+        assert_eq!(eng.handle(S((1 << 20) | 1)), Nothing);
+        assert_eq!(eng.handle(S(1)), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(B)));
+        assert_eq!(tap(&mut eng, 1 << 20), Hit(Key(A)));
+    }
+
+    const UNDO: u8 = chord!("%%^%");
+
+    #[test]
+    fn undo() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(tap(&mut eng, chord!("___^")), Hit(Key(E)));
+        assert_eq!(tap_all(&mut eng, chord!("%_%%")).len(), "Hi, World!\n".len());
+        tap_all(&mut eng, chord!("v_%%")); // unicode ż
+        assert_eq!(eng.undo_history().len(), 3);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE))]);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE)); 11]);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE))]);
+        assert_eq!(tap_all(&mut eng, UNDO), [Nothing]);
+
+        // other keys can't be undone, and make earlier outputs impossible to undo
+        assert_eq!(tap(&mut eng, chord!("___^")), Hit(Key(E)));
+        assert_eq!(tap(&mut eng, chord!("_^__")), Hit(Key(BACKSPACE)));
+        assert!(eng.undo_history().is_empty());
+        assert_eq!(tap_all(&mut eng, UNDO), [Nothing]);
+    }
+
+    struct Repeating;
+
+    impl Lookup for Repeating {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            L::lookup(layer, chord)
+        }
+
+        fn repeat(_layer: u8, _chord: u8) -> Option<Repeat> {
+            Some(Repeat { delay: 300, interval: 50 })
+        }
+    }
+
+    #[test]
+    fn undo_auto_repeated() {
+        let mut eng = Engine::<Repeating>::default();
+        assert_eq!(tap(&mut eng, chord!("___v")), Hit(Key(T)));
+        assert_eq!(eng.handle_at(S(chord!("___^")), 0), Nothing);
+        assert_eq!(eng.tick(300), Hit(Key(E)));
+        assert_eq!(eng.tick(350), Hit(Key(E)));
+        assert_eq!(eng.tick(400), Hit(Key(E)));
+        assert_eq!(eng.handle_at(S(0), 410), Nothing);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE)); 3]);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE))]);
+    }
+
+    #[test]
+    fn undo_briefs() {
+        let (the, n, dot) = (chord!("___v"), chord!("__v_"), chord!("__^^"));
+        let mut eng = Engine::<Steno>::default();
+        assert_eq!(tap(&mut eng, BRIEFS), Nothing);
+        tap_all(&mut eng, the);
+        tap_all(&mut eng, n);
+        tap_all(&mut eng, dot);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE))]);
+        // "then" replaced "the", and both are undone
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE)); 4]);
+        assert_eq!(tap_all(&mut eng, UNDO), [Nothing]);
+
+        // spacing is restored after undoing a key typed between the briefs
+        assert_eq!(tap_all(&mut eng, the), [Hit(Key(T)), Hit(Key(H)), Hit(Key(E))]);
+        assert_eq!(tap_all(&mut eng, chord!("___^")), [Hit(Key(E))]);
+        assert_eq!(tap_all(&mut eng, UNDO), [Hit(Key(BACKSPACE))]);
+        assert_eq!(tap_all(&mut eng, the), [Hit(Key(SPACE)), Hit(Key(T)), Hit(Key(H)), Hit(Key(E))]);
     }
 
     #[test]
@@ -338,7 +2024,7 @@ mod tests {
         assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // SHIFT layer
         assert_eq!(eng.handle(S(0)), Nothing);
         assert_eq!(eng.handle(S(chord!("_^__"))), Nothing); // DEL
-        assert_eq!(eng.handle(S(0)), Hit(DELETE | CTRL_FLAG | ALT_FLAG));
+        assert_eq!(eng.handle(S(0)), Hit(Key(DELETE | CTRL_FLAG | ALT_FLAG)));
 
         // Win-shift-s = Snippet tool on Windows
         assert_eq!(eng.handle(S(chord!("_%%_"))), Nothing); // Gui
@@ -346,7 +2032,34 @@ mod tests {
         assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing); // SHIFT layer
         assert_eq!(eng.handle(S(0)), Nothing);
         assert_eq!(eng.handle(S(chord!("^___"))), Nothing); // S
-        assert_eq!(eng.handle(S(0)), Hit(keycodes::S | SHIFT_FLAG | GUI_FLAG));
+        assert_eq!(eng.handle(S(0)), Hit(Key(keycodes::S | SHIFT_FLAG | GUI_FLAG)));
+    }
+
+    #[test]
+    fn modifier_double_tap_locks() {
+        let (ctrl, e) = (chord!("_^^_"), chord!("___^"));
+        let mut eng = Engine::<L>::default();
+        for _ in 0..2 {
+            assert_eq!(eng.handle(S(ctrl)), Nothing);
+            assert_eq!(eng.handle(S(0)), Nothing);
+        }
+        assert_eq!(eng.modifiers().state(CTRL_FLAG), ModifierState::Locked);
+        for _ in 0..2 {
+            assert_eq!(eng.handle(S(e)), Nothing);
+            assert_eq!(eng.handle(S(0)), Hit(Key(E | CTRL_FLAG)));
+        }
+        // one-shot on top of the locked one
+        assert_eq!(eng.handle(S(chord!("%%__"))), Nothing); // Alt
+        assert_eq!(eng.handle(S(0)), Nothing);
+        assert_eq!(eng.modifiers().state(ALT_FLAG), ModifierState::OneShot);
+        assert_eq!(eng.handle(S(e)), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E | CTRL_FLAG | ALT_FLAG)));
+        // tap releases
+        assert_eq!(eng.handle(S(ctrl)), Nothing);
+        assert_eq!(eng.handle(S(0)), Nothing);
+        assert_eq!(eng.modifiers().state(CTRL_FLAG), ModifierState::Off);
+        assert_eq!(eng.handle(S(e)), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E)));
     }
 
     #[test]
@@ -359,7 +2072,7 @@ mod tests {
         assert_eq!(eng.handle(S(chord!("%%_^"))), Nothing);
         assert_eq!(eng.handle(S(0)), Nothing);
         assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(keycodes::E | SHIFT_FLAG | RIGHT_ALT_FLAG));
+        assert_eq!(eng.handle(S(0)), Hit(Key(keycodes::E | SHIFT_FLAG | RIGHT_ALT_FLAG)));
 
         // r_alt-shift-e => also Ę
         assert_eq!(eng.handle(S(chord!("%%_^"))), Nothing);
@@ -367,7 +2080,128 @@ mod tests {
         assert_eq!(eng.handle(S(chord!("_vv_"))), Nothing);
         assert_eq!(eng.handle(S(0)), Nothing);
         assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
-        assert_eq!(eng.handle(S(0)), Hit(keycodes::E | SHIFT_FLAG | RIGHT_ALT_FLAG));
+        assert_eq!(eng.handle(S(0)), Hit(Key(keycodes::E | SHIFT_FLAG | RIGHT_ALT_FLAG)));
+    }
+
+    #[test]
+    fn text_expansion() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(chord!("v_v%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(MINUS)));
+        assert_eq!(eng.next_pending(), Some(Hit(Key(PERIOD | SHIFT_FLAG))));
+        assert_eq!(eng.next_pending(), None);
+        // back to regular keys
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E)));
+        assert_eq!(eng.next_pending(), None);
+    }
+
+    #[test]
+    fn text_expansion_with_shifted_characters() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(chord!("%_%%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(H | SHIFT_FLAG)));
+        let rest: Vec<_> = std::iter::from_fn(|| eng.next_pending()).collect();
+        assert_eq!(rest, [
+            Hit(Key(I)), Hit(Key(COMMA)), Hit(Key(SPACE)),
+            Hit(Key(W | SHIFT_FLAG)), Hit(Key(O)), Hit(Key(R)), Hit(Key(L)), Hit(Key(D)),
+            Hit(Key(KEY_1 | SHIFT_FLAG)), Hit(Key(ENTER)),
+        ]);
+    }
+
+    #[test]
+    fn text_expansion_ignores_plus_masks_and_skips_non_ascii() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.handle(S(chord!("_^^_"))), Nothing); // Ctrl
+        assert_eq!(eng.handle(S(0)), Nothing);
+        assert_eq!(eng.handle(S(chord!("^_^%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(Z)));
+        assert_eq!(eng.next_pending(), Some(Hit(Key(A))));
+        assert_eq!(eng.next_pending(), Some(Hit(Key(KEY_0 | SHIFT_FLAG))));
+        assert_eq!(eng.next_pending(), None);
+        // Ctrl was consumed by the text
+        assert_eq!(eng.handle(S(chord!("___^"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(E)));
+    }
+
+    #[test]
+    fn unicode_with_selected_method() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.unicode_method(), unicode::Method::Linux);
+        assert_eq!(eng.handle(S(chord!("v_%%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(U | CTRL_FLAG | SHIFT_FLAG)));
+        let rest: Vec<_> = std::iter::from_fn(|| eng.next_pending()).collect();
+        assert_eq!(rest, [Hit(Key(KEY_1)), Hit(Key(KEY_7)), Hit(Key(C)), Hit(Key(SPACE))]);
+
+        eng.set_unicode_method(unicode::Method::WindowsAltNumpad);
+        assert_eq!(eng.handle(S(chord!("v_%%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Press(Key(LEFT_ALT_FLAG)));
+        let rest: Vec<_> = std::iter::from_fn(|| eng.next_pending()).collect();
+        assert_eq!(rest, [
+            Hit(Key(KEYPAD_PLUS)), Hit(Key(KEYPAD_1)), Hit(Key(KEYPAD_7)), Hit(Key(C)),
+            Release(Key(LEFT_ALT_FLAG)),
+        ]);
+
+        // select method with a chord
+        assert_eq!(eng.handle(S(chord!("vv%%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Nothing);
+        assert_eq!(eng.unicode_method(), unicode::Method::MacHexInput);
+        assert_eq!(eng.handle(S(chord!("v_%%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Press(Key(LEFT_ALT_FLAG)));
+        let rest: Vec<_> = std::iter::from_fn(|| eng.next_pending()).collect();
+        assert_eq!(rest, [
+            Hit(Key(KEY_0)), Hit(Key(KEY_1)), Hit(Key(KEY_7)), Hit(Key(C)),
+            Release(Key(LEFT_ALT_FLAG)),
+        ]);
+    }
+
+    struct MacUnicode;
+
+    impl Lookup for MacUnicode {
+        type Action = Action;
+
+        fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Action>> {
+            L::lookup(layer, chord)
+        }
+
+        fn unicode_method() -> unicode::Method { unicode::Method::MacHexInput }
+    }
+
+    #[test]
+    fn unicode_method_from_lookup() {
+        let mut eng = Engine::<MacUnicode>::default();
+        assert_eq!(eng.unicode_method(), unicode::Method::MacHexInput);
+        assert_eq!(eng.handle(S(chord!("v_%%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Press(Key(LEFT_ALT_FLAG)));
+    }
+
+    #[test]
+    fn characters_on_host_layout() {
+        let mut eng = Engine::<L>::default();
+        assert_eq!(eng.host_layout(), host_layout::HostLayout::Us);
+        assert_eq!(eng.handle(S(chord!("v_^%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_2 | SHIFT_FLAG)));
+        assert_eq!(eng.next_pending(), None);
+
+        // select layout with a chord
+        assert_eq!(eng.handle(S(chord!("vv^%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Nothing);
+        assert_eq!(eng.host_layout(), host_layout::HostLayout::German);
+        assert_eq!(eng.handle(S(chord!("v_^%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(Q | RIGHT_ALT_FLAG)));
+        assert_eq!(eng.next_pending(), None);
+
+        // text follows the layout too, including dead keys
+        eng.set_host_layout(host_layout::HostLayout::French);
+        assert_eq!(eng.handle(S(chord!("v_v%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(KEY_6)));
+        assert_eq!(eng.next_pending(), Some(Hit(Key(NON_US_BS | SHIFT_FLAG))));
+        assert_eq!(eng.next_pending(), None);
+        eng.set_host_layout(host_layout::HostLayout::German);
+        assert_eq!(eng.handle(S(chord!("^_^%"))), Nothing);
+        assert_eq!(eng.handle(S(0)), Hit(Key(Y)));
+        let rest: Vec<_> = std::iter::from_fn(|| eng.next_pending()).collect();
+        assert_eq!(rest, [Hit(Key(A)), Hit(Key(KEY_9 | SHIFT_FLAG))]);
     }
 
     #[test]
@@ -376,31 +2210,31 @@ mod tests {
 
         // enter TEST layer with some unchorded keys
         assert_eq!(eng.handle(S(chord!("v^_v"))), Nothing);
-        assert_eq!(eng.handle(S(chord!("____"))), Hit(HACK_MOUSE_ENABLE_TOGGLE)); // layer switched
+        assert_eq!(eng.handle(S(chord!("____"))), Hit(MOUSE_POINTER_TOGGLE)); // layer switched
         // immediate press of mouse button, then release (a click)
-        assert_eq!(eng.handle(S(chord!("___^"))), Press(HACK_MOUSE_LEFT_BTN));
-        assert_eq!(eng.handle(S(chord!("____"))), Release(HACK_MOUSE_LEFT_BTN));
+        assert_eq!(eng.handle(S(chord!("___^"))), Press(MOUSE_LEFT));
+        assert_eq!(eng.handle(S(chord!("____"))), Release(MOUSE_LEFT));
         // ctrl-press, then release
         assert_eq!(eng.handle(S(chord!("^^__"))), Nothing);
         assert_eq!(eng.handle(S(chord!("____"))), Nothing); // ctrl-...
-        assert_eq!(eng.handle(S(chord!("___^"))), Press(HACK_MOUSE_LEFT_BTN | CTRL_FLAG));
-        assert_eq!(eng.handle(S(chord!("____"))), Release(HACK_MOUSE_LEFT_BTN));
+        assert_eq!(eng.handle(S(chord!("___^"))), Press(MOUSE_LEFT | CTRL_FLAG));
+        assert_eq!(eng.handle(S(chord!("____"))), Release(MOUSE_LEFT));
         // ctrl-press, then ctrl-release
         assert_eq!(eng.handle(S(chord!("^^__"))), Nothing);
         assert_eq!(eng.handle(S(chord!("____"))), Nothing); // ctrl-...
-        assert_eq!(eng.handle(S(chord!("___^"))), Press(HACK_MOUSE_LEFT_BTN | CTRL_FLAG));
+        assert_eq!(eng.handle(S(chord!("___^"))), Press(MOUSE_LEFT | CTRL_FLAG));
         assert_eq!(eng.handle(S(chord!("^^_^"))), Nothing);
         assert_eq!(eng.handle(S(chord!("___^"))), Nothing); // ctrl-...
-        assert_eq!(eng.handle(S(chord!("____"))), Release(HACK_MOUSE_LEFT_BTN | CTRL_FLAG));
+        assert_eq!(eng.handle(S(chord!("____"))), Release(MOUSE_LEFT | CTRL_FLAG));
 
-        assert_eq!(eng.handle(S(chord!("___^"))), Press(HACK_MOUSE_LEFT_BTN));
-        assert_eq!(eng.handle(S(chord!("__^^"))), Press(HACK_MOUSE_RIGHT_BTN));
+        assert_eq!(eng.handle(S(chord!("___^"))), Press(MOUSE_LEFT));
+        assert_eq!(eng.handle(S(chord!("__^^"))), Press(MOUSE_RIGHT));
         // release both in sequence when exiting the layer,
         // ignoring any args until all released
         assert_eq!(eng.handle(S(chord!("v^_v") | chord!("__^^"))), Nothing);
-        assert_eq!(eng.handle(S(chord!("__^^"))), Hit(HACK_MOUSE_ENABLE_TOGGLE));
-        assert_eq!(eng.handle(S(0)), Release(HACK_MOUSE_RIGHT_BTN));
-        assert_eq!(eng.handle(S(0)), Release(HACK_MOUSE_LEFT_BTN));
+        assert_eq!(eng.handle(S(chord!("__^^"))), Hit(MOUSE_POINTER_TOGGLE));
+        assert_eq!(eng.handle(S(0)), Release(MOUSE_RIGHT));
+        assert_eq!(eng.handle(S(0)), Release(MOUSE_LEFT));
         assert_eq!(eng.handle(S(0)), Nothing);
     }
 }
@@ -0,0 +1,167 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Modifiers added to the emitted keys, like Ctrl or Shift.
+
+use core::mem;
+use core::ops::{BitAndAssign, BitOr, BitOrAssign, Not};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ModifierState {
+    #[default]
+    Off,
+    /// Added to the next emitted key only.
+    OneShot,
+    /// Added to all emitted keys, until unlocked.
+    Locked,
+}
+
+/// State of all modifiers, as masks of modifier flags like
+/// [`CTRL_FLAG`](crate::keycodes::CTRL_FLAG).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Modifiers<K> {
+    one_shot: K,
+    locked: K,
+}
+
+impl<K> Modifiers<K>
+where
+    K: Copy + Default + PartialEq + BitAndAssign + BitOr<Output = K> + BitOrAssign + Not<Output = K>,
+{
+    /// State of the `flag` modifier. If `flag` has more than one bit set,
+    /// all of them must be in the state.
+    pub fn state(&self, flag: K) -> ModifierState {
+        if contains(self.locked, flag) {
+            ModifierState::Locked
+        } else if contains(self.one_shot, flag) {
+            ModifierState::OneShot
+        } else {
+            ModifierState::Off
+        }
+    }
+
+    pub fn one_shot(&self) -> K {
+        self.one_shot
+    }
+
+    pub fn locked(&self) -> K {
+        self.locked
+    }
+
+    /// Both the one-shot and locked modifiers.
+    pub fn active(&self) -> K {
+        self.one_shot | self.locked
+    }
+
+    /// A modifier chord was tapped: an off modifier becomes one-shot, a
+    /// one-shot one (i.e. tapped twice in a row) becomes locked, and a locked
+    /// one is released.
+    pub fn tap(&mut self, mask: K) {
+        match self.state(mask) {
+            ModifierState::Off => self.one_shot |= mask,
+            ModifierState::OneShot => {
+                self.one_shot &= !mask;
+                self.locked |= mask;
+            }
+            ModifierState::Locked => {
+                self.one_shot &= !mask;
+                self.locked &= !mask;
+            }
+        }
+    }
+
+    /// Locks the modifiers, or releases them if already locked.
+    pub fn toggle(&mut self, mask: K) {
+        self.one_shot &= !mask;
+        if contains(self.locked, mask) {
+            self.locked &= !mask;
+        } else {
+            self.locked |= mask;
+        }
+    }
+
+    pub fn add_one_shot(&mut self, mask: K) {
+        self.one_shot |= mask;
+    }
+
+    /// Returns the one-shot modifiers, which are then off.
+    pub fn take_one_shot(&mut self) -> K {
+        mem::take(&mut self.one_shot)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn contains<K>(set: K, flags: K) -> bool
+where
+    K: Copy + Default + PartialEq + BitAndAssign,
+{
+    let mut v = set;
+    v &= flags;
+    v == flags && flags != K::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keycodes::*;
+    use ModifierState::*;
+
+    #[test]
+    fn tap_cycles_through_states() {
+        let mut m = Modifiers::default();
+        assert_eq!(m.state(CTRL_FLAG), Off);
+        m.tap(CTRL_FLAG);
+        assert_eq!(m.state(CTRL_FLAG), OneShot);
+        m.tap(CTRL_FLAG);
+        assert_eq!(m.state(CTRL_FLAG), Locked);
+        assert_eq!(m.take_one_shot(), 0);
+        assert_eq!(m.active(), CTRL_FLAG);
+        m.tap(CTRL_FLAG);
+        assert_eq!(m.state(CTRL_FLAG), Off);
+        assert_eq!(m.active(), 0);
+    }
+
+    #[test]
+    fn modifiers_are_independent() {
+        let mut m = Modifiers::default();
+        m.tap(SHIFT_FLAG);
+        m.tap(SHIFT_FLAG);
+        m.tap(RIGHT_ALT_FLAG);
+        assert_eq!(m.state(SHIFT_FLAG), Locked);
+        assert_eq!(m.state(RIGHT_ALT_FLAG), OneShot);
+        assert_eq!(m.state(ALT_FLAG), Off);
+        assert_eq!(m.take_one_shot(), RIGHT_ALT_FLAG);
+        assert_eq!(m.state(RIGHT_ALT_FLAG), Off);
+        assert_eq!(m.locked(), SHIFT_FLAG);
+    }
+
+    #[test]
+    fn toggle() {
+        let mut m = Modifiers::default();
+        m.tap(GUI_FLAG);
+        m.toggle(GUI_FLAG);
+        assert_eq!(m.state(GUI_FLAG), Locked);
+        assert_eq!(m.one_shot(), 0);
+        m.toggle(GUI_FLAG);
+        assert_eq!(m.state(GUI_FLAG), Off);
+        m.toggle(GUI_FLAG | CTRL_FLAG);
+        m.clear();
+        assert_eq!(m.active(), 0);
+    }
+}
@@ -0,0 +1,250 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Mouse buttons and pointer, driven by [`Action::Mouse`] outcomes.
+
+use crate::UsbOutcome;
+use crate::action::{Action, MouseAction, MouseButton, PointerMode};
+
+/// Contents of a HID boot mouse report, like `usbd_hid`'s `MouseReport`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Pressed buttons, as [`MouseButton::mask`]s.
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+    /// Positive is up.
+    pub wheel: i8,
+}
+
+const MAX_REPORTS: usize = 2;
+
+/// Reports to send for an outcome, in order.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Reports {
+    reports: [Report; MAX_REPORTS],
+    len: u8,
+    pos: u8,
+}
+
+impl Reports {
+    fn push(&mut self, report: Report) {
+        self.reports[usize::from(self.len)] = report;
+        self.len += 1;
+    }
+
+    /// Whether there are no more reports to send.
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.len
+    }
+}
+
+impl Iterator for Reports {
+    type Item = Report;
+
+    fn next(&mut self) -> Option<Report> {
+        if self.pos == self.len {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.reports[usize::from(self.pos - 1)])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = usize::from(self.len - self.pos);
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Reports {}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Mouse {
+    /// Buttons pressed until released, e.g. while an unchorded key is held.
+    pressed: u8,
+    /// Buttons pressed by [`MouseAction::DragToggle`].
+    dragged: u8,
+    pointer: bool,
+}
+
+impl Mouse {
+    /// Currently pressed buttons, as [`MouseButton::mask`]s.
+    pub fn buttons(&self) -> u8 {
+        self.pressed | self.dragged
+    }
+
+    /// Whether the pointer is moved, e.g. by a gyroscope.
+    pub fn pointer_enabled(&self) -> bool {
+        self.pointer
+    }
+
+    /// A report moving the pointer, if it's enabled.
+    pub fn movement(&self, x: i8, y: i8) -> Option<Report> {
+        self.pointer.then(|| Report { buttons: self.buttons(), x, y, wheel: 0 })
+    }
+
+    /// Updates the state with a mouse action, and returns the reports to
+    /// send. Other outcomes are ignored.
+    ///
+    /// A [`MouseAction::Button`] is clicked by [`UsbOutcome::KeyHit`], and
+    /// held between [`UsbOutcome::KeyPress`] and [`UsbOutcome::KeyRelease`].
+    /// Other mouse actions are done on hit or press, and ignored on release.
+    pub fn handle(&mut self, outcome: UsbOutcome<Action>) -> Reports {
+        let mut reports = Reports::default();
+        let (action, release) = match outcome {
+            UsbOutcome::KeyHit(Action::Mouse(action, _)) | UsbOutcome::KeyPress(Action::Mouse(action, _)) => {
+                (action, false)
+            }
+            UsbOutcome::KeyRelease(Action::Mouse(action, _)) => (action, true),
+            _ => return reports,
+        };
+        let hit = matches!(outcome, UsbOutcome::KeyHit(_));
+        match action {
+            MouseAction::Button(button) if hit => {
+                self.press(button);
+                reports.push(self.report(0));
+                self.release(button);
+                reports.push(self.report(0));
+            }
+            MouseAction::Button(button) if release => {
+                self.pressed &= !button.mask();
+                reports.push(self.report(0));
+            }
+            MouseAction::Button(button) | MouseAction::Press(button) if !release => {
+                self.press(button);
+                reports.push(self.report(0));
+            }
+            MouseAction::Release(button) if !release => {
+                self.release(button);
+                reports.push(self.report(0));
+            }
+            MouseAction::DragToggle(button) if !release => {
+                self.dragged ^= button.mask();
+                reports.push(self.report(0));
+            }
+            MouseAction::Wheel(amount) if !release => reports.push(self.report(amount)),
+            MouseAction::Pointer(mode) if !release => {
+                self.pointer = match mode {
+                    PointerMode::Toggle => !self.pointer,
+                    PointerMode::Enable => true,
+                    PointerMode::Disable => false,
+                };
+            }
+            _ => (),
+        }
+        reports
+    }
+
+    fn press(&mut self, button: MouseButton) {
+        self.pressed |= button.mask();
+    }
+
+    /// Releases the button, also if dragged.
+    fn release(&mut self, button: MouseButton) {
+        self.pressed &= !button.mask();
+        self.dragged &= !button.mask();
+    }
+
+    fn report(&self, wheel: i8) -> Report {
+        Report { buttons: self.buttons(), x: 0, y: 0, wheel }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::UsbOutcome::{KeyHit as Hit, KeyPress as Press, KeyRelease as Release};
+    use crate::action::*;
+    use crate::keycodes::{CTRL_FLAG, E};
+
+    fn buttons(mouse: &mut Mouse, outcome: UsbOutcome<Action>) -> Vec<u8> {
+        mouse.handle(outcome).map(|r| r.buttons).collect()
+    }
+
+    fn mouse(action: MouseAction) -> Action {
+        Action::Mouse(action, 0)
+    }
+
+    const LEFT: u8 = 0x01;
+    const RIGHT: u8 = 0x02;
+    const MIDDLE: u8 = 0x04;
+
+    #[test]
+    fn clicks_and_holds() {
+        let mut m = Mouse::default();
+        assert_eq!(buttons(&mut m, Hit(MOUSE_LEFT)), [LEFT, 0]);
+        assert_eq!(buttons(&mut m, Hit(MOUSE_MIDDLE | CTRL_FLAG)), [MIDDLE, 0]);
+        assert_eq!(buttons(&mut m, Press(MOUSE_RIGHT)), [RIGHT]);
+        assert_eq!(buttons(&mut m, Hit(MOUSE_LEFT)), [LEFT | RIGHT, RIGHT]);
+        assert_eq!(buttons(&mut m, Release(MOUSE_RIGHT | CTRL_FLAG)), [0]);
+        assert_eq!(buttons(&mut m, Hit(mouse(MouseAction::Press(MouseButton::Middle)))), [MIDDLE]);
+        assert_eq!(m.buttons(), MIDDLE);
+        assert_eq!(buttons(&mut m, Hit(mouse(MouseAction::Release(MouseButton::Middle)))), [0]);
+        assert_eq!(buttons(&mut m, Hit(Action::Key(E))), []);
+        assert_eq!(buttons(&mut m, UsbOutcome::Nothing), []);
+    }
+
+    #[test]
+    fn drag_lock() {
+        let mut m = Mouse::default();
+        assert_eq!(buttons(&mut m, Hit(MOUSE_LEFT_DRAG_TOGGLE)), [LEFT]);
+        // held keys don't release the drag
+        assert_eq!(buttons(&mut m, Press(MOUSE_RIGHT)), [LEFT | RIGHT]);
+        assert_eq!(buttons(&mut m, Release(MOUSE_RIGHT)), [LEFT]);
+        assert_eq!(buttons(&mut m, Press(MOUSE_LEFT)), [LEFT]);
+        assert_eq!(buttons(&mut m, Release(MOUSE_LEFT)), [LEFT]);
+        assert_eq!(buttons(&mut m, Hit(MOUSE_LEFT_DRAG_TOGGLE)), [0]);
+
+        // a click ends the drag
+        assert_eq!(buttons(&mut m, Hit(MOUSE_LEFT_DRAG_TOGGLE)), [LEFT]);
+        assert_eq!(buttons(&mut m, Hit(MOUSE_LEFT)), [LEFT, 0]);
+        assert_eq!(m.buttons(), 0);
+
+        // releasing ends it too, and the toggle can be held like a key
+        let middle_drag = mouse(MouseAction::DragToggle(MouseButton::Middle));
+        assert_eq!(buttons(&mut m, Press(middle_drag)), [MIDDLE]);
+        assert_eq!(buttons(&mut m, Release(middle_drag)), []);
+        assert_eq!(buttons(&mut m, Hit(mouse(MouseAction::Release(MouseButton::Middle)))), [0]);
+    }
+
+    #[test]
+    fn wheel_and_pointer() {
+        let mut m = Mouse::default();
+        assert_eq!(m.movement(3, -4), None);
+        assert_eq!(m.handle(Hit(MOUSE_RIGHT)).len(), 2);
+        assert!(!m.handle(Hit(MOUSE_RIGHT)).is_empty());
+        assert!(m.handle(Release(MOUSE_WHEEL_UP)).is_empty());
+        let reports: Vec<_> = m.handle(Hit(MOUSE_WHEEL_DOWN)).collect();
+        assert_eq!(reports, [Report { wheel: -10, ..Default::default() }]);
+
+        assert_eq!(buttons(&mut m, Hit(MOUSE_POINTER_TOGGLE)), []);
+        assert!(m.pointer_enabled());
+        m.handle(Hit(MOUSE_LEFT_DRAG_TOGGLE));
+        assert_eq!(m.movement(3, -4), Some(Report { buttons: LEFT, x: 3, y: -4, wheel: 0 }));
+        let reports: Vec<_> = m.handle(Press(MOUSE_WHEEL_UP)).collect();
+        assert_eq!(reports, [Report { buttons: LEFT, wheel: 10, ..Default::default() }]);
+        assert_eq!(buttons(&mut m, Release(MOUSE_WHEEL_UP)), []);
+
+        m.handle(Hit(mouse(MouseAction::Pointer(PointerMode::Enable))));
+        assert!(m.pointer_enabled());
+        m.handle(Hit(MOUSE_POINTER_TOGGLE));
+        assert!(!m.pointer_enabled());
+        m.handle(Hit(mouse(MouseAction::Pointer(PointerMode::Enable))));
+        m.handle(Hit(mouse(MouseAction::Pointer(PointerMode::Disable))));
+        assert!(!m.pointer_enabled());
+    }
+}
@@ -0,0 +1,362 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Text notation of chords, as used by the `chord!` macro. This file is
+//! also compiled into `clawtype-macros`, so both parse the same grammar.
+//!
+//! Each position of a chord is a crumb (i.e. 2 bits):
+//! - `^` is `0b10`, e.g. a finger's tip switch,
+//! - `v` is `0b01`, e.g. a finger's base switch,
+//! - `_` or `.` is `0b00`,
+//! - `%` is `0b11`.
+//!
+//! A chord can be written positionally, like `_^_%`, with 4, 5 or 6
+//! crumbs, the first one in the most significant bits. Or with finger
+//! labels, like `I:^ M:v`, where the positions are `P` (pinky), `R`
+//! (ring), `M` (middle), `I` (index), then `T` and `U` (thumb); unlabelled
+//! positions are `_`, and the last label used determines the number of
+//! positions, at least 4.
+//!
+//! A sequence of chords is written separated with whitespace, like
+//! `_v__ ^___`. Chords with finger labels must be separated with a comma
+//! from one another, like `I:^ M:v, P:^`.
+
+use core::fmt;
+
+/// Max number of chords in a [`Sequence`].
+pub const MAX_CHORDS: usize = 8;
+
+const FINGERS: &[u8] = b"PRMITU";
+
+/// Chords parsed from text, all with the same number of positions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sequence {
+    chords: [u16; MAX_CHORDS],
+    len: usize,
+    width: usize,
+}
+
+impl Sequence {
+    pub const fn chords(&self) -> &[u16] {
+        self.chords.split_at(self.len).0
+    }
+
+    /// Number of positions in each chord: 4, 5 or 6.
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    const fn push(&mut self, chord: u16, width: usize, start: usize, end: usize) -> Result<(), Error> {
+        if self.len > 0 && width != self.width {
+            return Err(Error { kind: ErrorKind::MixedWidths, start, end });
+        }
+        if self.len == MAX_CHORDS {
+            return Err(Error { kind: ErrorKind::TooManyChords, start, end });
+        }
+        self.chords[self.len] = chord;
+        self.len += 1;
+        self.width = width;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    NoChords,
+    UnknownCrumb,
+    WrongLength,
+    UnknownFinger,
+    DuplicateFinger,
+    MalformedLabel,
+    MixedWidths,
+    TooManyChords,
+    UnexpectedComma,
+    NotSingleChord,
+    NotFourPositions,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ErrorKind::*;
+        f.write_str(match self {
+            NoChords => "no chords",
+            UnknownCrumb => "unknown crumb, expected one of: ^ v _ . %",
+            WrongLength => "expected 4, 5 or 6 positions in chord",
+            UnknownFinger => "unknown finger, expected one of: P R M I T U",
+            DuplicateFinger => "finger already used in this chord",
+            MalformedLabel => "expected a finger label like I:^",
+            MixedWidths => "chord has a different number of positions than the previous ones",
+            TooManyChords => "too many chords in sequence",
+            UnexpectedComma => "expected a chord before comma",
+            NotSingleChord => "expected a single chord",
+            NotFourPositions => "expected 4 positions in chord",
+        })
+    }
+}
+
+/// A problem found at bytes `start..end` of the parsed text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+const fn crumb(b: u8) -> Option<u16> {
+    match b {
+        b'^' => Some(0b10),
+        b'v' => Some(0b01),
+        b'_' | b'.' => Some(0b00),
+        b'%' => Some(0b11),
+        _ => None,
+    }
+}
+
+/// Length of the UTF-8 character starting with byte `b`.
+const fn char_len(b: u8) -> usize {
+    match b {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+/// Bad crumb at `i`.
+const fn unknown_crumb(bytes: &[u8], i: usize) -> Error {
+    Error { kind: ErrorKind::UnknownCrumb, start: i, end: i + char_len(bytes[i]) }
+}
+
+/// A chord written with finger labels, being parsed.
+struct Labelled {
+    crumbs: [u16; FINGERS.len()],
+    used: usize,
+    width: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Labelled {
+    const EMPTY: Self = Labelled { crumbs: [0; FINGERS.len()], used: 0, width: 0, start: 0, end: 0 };
+
+    const fn bits(&self) -> u16 {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < self.width {
+            bits = (bits << 2) | self.crumbs[i];
+            i += 1;
+        }
+        bits
+    }
+
+    /// Adds the chord to the sequence, if any labels were parsed.
+    const fn flush(&mut self, seq: &mut Sequence) -> Result<(), Error> {
+        if self.used == 0 {
+            return Ok(());
+        }
+        let result = seq.push(self.bits(), self.width, self.start, self.end);
+        *self = Self::EMPTY;
+        result
+    }
+}
+
+/// Parses a chord or a sequence of chords, see the [module docs](self).
+pub const fn parse(s: &str) -> Result<Sequence, Error> {
+    let bytes = s.as_bytes();
+    let mut seq = Sequence { chords: [0; MAX_CHORDS], len: 0, width: 0 };
+    let mut labelled = Labelled::EMPTY;
+    // Whether a chord was finished since the start or the last comma.
+    let mut chord_before = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if b == b',' {
+            if labelled.used == 0 && !chord_before {
+                return Err(Error { kind: ErrorKind::UnexpectedComma, start: i, end: i + 1 });
+            }
+            if let Err(err) = labelled.flush(&mut seq) {
+                return Err(err);
+            }
+            chord_before = false;
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b',' {
+            i += 1;
+        }
+        let end = i;
+
+        if end - start >= 2 && bytes[start + 1] == b':' {
+            if end - start != 3 {
+                return Err(Error { kind: ErrorKind::MalformedLabel, start, end });
+            }
+            let mut finger = 0;
+            while finger < FINGERS.len() && FINGERS[finger] != b {
+                finger += 1;
+            }
+            if finger == FINGERS.len() {
+                return Err(Error { kind: ErrorKind::UnknownFinger, start, end: start + char_len(b) });
+            }
+            if labelled.used & (1 << finger) != 0 {
+                return Err(Error { kind: ErrorKind::DuplicateFinger, start, end });
+            }
+            let Some(c) = crumb(bytes[start + 2]) else {
+                return Err(unknown_crumb(bytes, start + 2));
+            };
+            if labelled.used == 0 {
+                labelled.start = start;
+                labelled.width = 4;
+            }
+            labelled.crumbs[finger] = c;
+            labelled.used |= 1 << finger;
+            if finger + 1 > labelled.width {
+                labelled.width = finger + 1;
+            }
+            labelled.end = end;
+            continue;
+        }
+
+        if let Err(err) = labelled.flush(&mut seq) {
+            return Err(err);
+        }
+        let mut bits = 0;
+        let mut j = start;
+        while j < end {
+            let Some(c) = crumb(bytes[j]) else {
+                return Err(unknown_crumb(bytes, j));
+            };
+            bits = (bits << 2) | c;
+            j += 1;
+        }
+        let width = end - start;
+        if width < 4 || width > 6 {
+            return Err(Error { kind: ErrorKind::WrongLength, start, end });
+        }
+        if let Err(err) = seq.push(bits, width, start, end) {
+            return Err(err);
+        }
+        chord_before = true;
+    }
+    if let Err(err) = labelled.flush(&mut seq) {
+        return Err(err);
+    }
+    if seq.len == 0 {
+        return Err(Error { kind: ErrorKind::NoChords, start: 0, end: bytes.len() });
+    }
+    Ok(seq)
+}
+
+/// Parses a single chord, see the [module docs](self).
+pub const fn parse_chord(s: &str) -> Result<u16, Error> {
+    match parse(s) {
+        Ok(seq) if seq.len == 1 => Ok(seq.chords[0]),
+        Ok(_) => Err(Error { kind: ErrorKind::NotSingleChord, start: 0, end: s.len() }),
+        Err(err) => Err(err),
+    }
+}
+
+/// Parses a single chord of 4 positions, as a `u8` like from the `chord!`
+/// macro.
+pub const fn parse_chord_u8(s: &str) -> Result<u8, Error> {
+    match parse(s) {
+        Ok(seq) if seq.len != 1 => Err(Error { kind: ErrorKind::NotSingleChord, start: 0, end: s.len() }),
+        Ok(seq) if seq.width != 4 => Err(Error { kind: ErrorKind::NotFourPositions, start: 0, end: s.len() }),
+        Ok(seq) => Ok(seq.chords[0] as u8),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chords(s: &str) -> Vec<u16> {
+        parse(s).unwrap().chords().to_vec()
+    }
+
+    fn error(s: &str) -> (ErrorKind, &str) {
+        let err = parse(s).unwrap_err();
+        (err.kind, &s[err.start..err.end])
+    }
+
+    #[test]
+    fn positional() {
+        assert_eq!(parse_chord("_^_%"), Ok(0b00_10_00_11));
+        assert_eq!(parse_chord("v.._"), Ok(0b01_00_00_00));
+        assert_eq!(parse_chord("___^%_"), Ok(0b00_00_00_10_11_00));
+        assert_eq!(parse("__^_v").unwrap().width(), 5);
+    }
+
+    #[test]
+    fn labelled() {
+        assert_eq!(parse_chord("I:^ M:v"), Ok(0b00_00_01_10));
+        assert_eq!(parse_chord("M:v I:^"), Ok(0b00_00_01_10));
+        assert_eq!(parse_chord("P:%"), Ok(0b11_00_00_00));
+        let seq = parse("I:^ T:v").unwrap();
+        assert_eq!((seq.chords(), seq.width()), (&[0b00_00_00_10_01][..], 5));
+        assert_eq!(parse("U:_").unwrap().width(), 6);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(chords("_v__ ^___"), [0b00_01_00_00, 0b10_00_00_00]);
+        assert_eq!(chords(" _v__,^___ "), [0b00_01_00_00, 0b10_00_00_00]);
+        assert_eq!(chords("I:^ M:v, P:^ ^___"), [0b00_00_01_10, 0b10_00_00_00, 0b10_00_00_00]);
+        assert_eq!(chords("_v__ I:^"), [0b00_01_00_00, 0b00_00_00_10]);
+        const SEQ: Sequence = match parse("%%%% vvvv") {
+            Ok(seq) => seq,
+            Err(_) => panic!(),
+        };
+        assert_eq!(SEQ.chords(), [0xff, 0x55]);
+    }
+
+    #[test]
+    fn errors() {
+        use ErrorKind::*;
+        assert_eq!(error(""), (NoChords, ""));
+        assert_eq!(error("_^x%"), (UnknownCrumb, "x"));
+        assert_eq!(error("_^ż%"), (UnknownCrumb, "ż"));
+        assert_eq!(error("_^_"), (WrongLength, "_^_"));
+        assert_eq!(error("__^^ _^_%_^_"), (WrongLength, "_^_%_^_"));
+        assert_eq!(error("I:^ Q:v"), (UnknownFinger, "Q"));
+        assert_eq!(error("I:^ I:v"), (DuplicateFinger, "I:v"));
+        assert_eq!(error("I:^^"), (MalformedLabel, "I:^^"));
+        assert_eq!(error("I:x"), (UnknownCrumb, "x"));
+        assert_eq!(error("____ _____"), (MixedWidths, "_____"));
+        assert_eq!(error("I:^ T:^, I:^"), (MixedWidths, "I:^"));
+        assert_eq!(error("____ ____ ____ ____ ____ ____ ____ ____ ^___"), (TooManyChords, "^___"));
+        assert_eq!(error("____,,____"), (UnexpectedComma, ","));
+        assert_eq!(error(", ____"), (UnexpectedComma, ","));
+        let err = parse_chord("____ ____").unwrap_err();
+        assert_eq!(err.kind, NotSingleChord);
+    }
+
+    #[test]
+    fn chords_u8() {
+        assert_eq!(parse_chord_u8("_^_%"), Ok(0b00_10_00_11));
+        assert_eq!(parse_chord_u8("I:^ M:v"), Ok(0b00_00_01_10));
+        assert_eq!(parse_chord_u8("__^_v").unwrap_err().kind, ErrorKind::NotFourPositions);
+        assert_eq!(parse_chord_u8("T:^").unwrap_err().kind, ErrorKind::NotFourPositions);
+        assert_eq!(parse_chord_u8("____ ____").unwrap_err().kind, ErrorKind::NotSingleChord);
+        assert_eq!(parse_chord_u8("_^x%").unwrap_err().kind, ErrorKind::UnknownCrumb);
+    }
+}
@@ -14,153 +14,133 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use const_map::const_map;
+use clawtype_macros::{chord, layout};
 
-use clawtype_macros::chord;
-
-use crate::LayerOutcome::{self, *};
+use crate::LayerOutcome::*;
 use crate::UsbOutcome::KeyHit as Hit;
-use crate::keycodes::{self, *};
-use crate::{LayerInfo, SwitchSet};
-
-pub struct SampleLayers {}
-
-impl super::Lookup for SampleLayers {
-    type KeyWithFlags = keycodes::KeyWithFlags;
-
-    fn lookup(layer: u8, chord: u8) -> Option<LayerOutcome<Self::KeyWithFlags>> {
-        let layout: &[_] = match layer {
-            1 => &Self::LAYOUT1, // "SHIFT"
-            2 => &Self::LAYOUT2, // "TEST"
-            _ => &Self::LAYOUT0,
-        };
-        crate::lookup_in_slice(chord, layout).copied()
+use crate::action::{Action::{self, Key}, MOUSE_LEFT, MOUSE_POINTER_TOGGLE, MOUSE_RIGHT};
+use crate::keycodes::*;
+use crate::{LayerFallback, Repeat, SequenceMatch, host_layout::HostLayout, unicode};
+use crate::layer_stack::LayerMode;
+
+layout! {
+    pub struct SampleLayers {}
+    type Action = Action;
+
+    layer 0 {
+        "_^_%" => Emit(Hit(Key(UP))),
+        "_v_%" => Emit(Hit(Key(DOWN))),
+        "^__%" => Emit(Hit(Key(LEFT))),
+        "v__%" => Emit(Hit(Key(RIGHT))),
+        "^^_%" => Emit(Hit(Key(PAGE_UP))),
+        "vv_v" => Emit(Hit(Key(PAGE_DOWN))),
+
+        "__^_" => Emit(Hit(Key(SPACE))),
+        "_^__" => Emit(Hit(Key(BACKSPACE))),
+        "___^" => Emit(Hit(Key(E))),
+        "___v" => Emit(Hit(Key(T))),
+        "__v_" => Emit(Hit(Key(A))),
+        "___%" => Emit(Hit(Key(I))),
+        "__%_" => Emit(Hit(Key(O))),
+        "_v__" => Emit(Hit(Key(N))),
+        "^___" => Emit(Hit(Key(S))),
+        "_%__" => Emit(Hit(Key(H))),
+        "v___" => Emit(Hit(Key(R))),
+        "%___" => Emit(Hit(Key(L))),
+        "__^^" => Emit(Hit(Key(D))),
+        "__vv" => Emit(Hit(Key(C))),
+        "__^v" => Emit(Hit(Key(U))),
+        "^^__" => Emit(Hit(Key(M))),
+        "_vv_" => TemporaryLayerSwitch { layer: 1 }, // SHIFT
+        "_^^_" => TemporaryPlusMask { mask: CTRL_FLAG }, // CTRL
+        "%%__" => TemporaryPlusMask { mask: ALT_FLAG }, // ALT
+        "%%_^" => TemporaryPlusMask { mask: RIGHT_ALT_FLAG }, // R-ALT
+        "_%%_" => TemporaryPlusMask { mask: GUI_FLAG }, // GUI
+        "_^_^" => Emit(Hit(Key(TAB))),
+        "__^%" => Emit(Hit(Key(W))),
+        "_^_v" => Emit(Hit(Key(G))),
+        "__%v" => Emit(Hit(Key(F))),
+        "__%%" => Emit(Hit(Key(Y))),
+        "_v_v" => Emit(Hit(Key(P))),
+        "v__v" => Emit(Hit(Key(B))),
+        "^__^" => Emit(Hit(Key(COMMA))),
+        "_^^^" => Emit(Hit(Key(PERIOD))),
+        "_vvv" => Emit(Hit(Key(V))),
+        "_%_%" => Emit(Hit(Key(ENTER))),
+        "vvvv" => Emit(Hit(Key(ESC))),
+        "^__v" => Emit(Hit(Key(K))),
+        "%__%" => Emit(Hit(Key(QUOTE))),
+        "%__v" => Emit(Hit(Key(QUOTE | SHIFT_FLAG))), // "
+        "vvv_" => Emit(Hit(Key(MINUS))),
+        "__v%" => Emit(Hit(Key(X))),
+        "_%%%" => Emit(Hit(Key(J))),
+        "_%_v" => Emit(Hit(Key(SEMICOLON))),
+        "^^^_" => Emit(Hit(Key(KEY_9 | SHIFT_FLAG))), // (
+        "^_^_" => Emit(Hit(Key(KEY_0 | SHIFT_FLAG))), // )
+        "^^^^" => Emit(Hit(Key(Q))),
+        "_^^v" => Emit(Hit(Key(SLASH))),
+        "_^^%" => Emit(Hit(Key(Z))),
+        "^^_v" => Emit(Hit(Key(SEMICOLON | SHIFT_FLAG))), // :
+        "_^%_" => Emit(Hit(Key(KEY_0))),
+        "v_v_" => Emit(Hit(Key(KEY_1))),
+        "%_%_" => Emit(Hit(Key(KEY_2))),
+        "%%%_" => Emit(Hit(Key(KEY_3))),
+        "^^^%" => Emit(Hit(Key(KEY_4))),
+        "_vv%" => Emit(Hit(Key(EQUAL))),
+        "%^__" => Emit(Hit(Key(KEY_4 | SHIFT_FLAG))), // $
+        "^_%_" => Emit(Hit(Key(LEFT_BRACE | SHIFT_FLAG))), // {
+        "v_%_" => Emit(Hit(Key(RIGHT_BRACE | SHIFT_FLAG))), // }
+
+        "v_v%" => EmitText("->"),
+        "%_%%" => EmitText("Hi, World!\n"),
+        "^_^%" => EmitText("zażółć)"),
+        "v_%%" => EmitUnicode('ż'),
+        "vv%%" => SetUnicodeMethod(unicode::Method::MacHexInput),
+        "v_^%" => EmitChar('@'),
+        "vv^%" => SetHostLayout(HostLayout::German),
+
+        "v_^_" => PushLayer { layer: 3, mode: LayerMode::Toggle }, // NUM
+        "vv^_" => PushLayer { layer: 3, mode: LayerMode::Locked }, // NUM
+        "vv_^" => PushLayer { layer: 3, mode: LayerMode::Momentary }, // NUM
+        "%%%%" => ClearState,
+        "%%%^" => CapsWord,
+        "%%%v" => Leader,
+        "%%^%" => Undo,
+
+        "v^_v" => LayerSwitchAndEmit {
+            layer: 2,
+            emit: Hit(MOUSE_POINTER_TOGGLE),
+        },
     }
 
-    fn info(layer: u8) -> LayerInfo {
-        let unchorded_mask = SwitchSet(match layer {
-            2 => chord!("__^^"),
-            _ => 0,
-        });
-        LayerInfo { unchorded_mask }
-    }
-
-    fn unchorded_key(layer: u8, switch: SwitchSet) -> Option<Self::KeyWithFlags> {
-        match (layer, switch.0) {
-            (2, chord!("___^")) => Some(HACK_MOUSE_LEFT_BTN),
-            (2, chord!("__^_")) => Some(HACK_MOUSE_RIGHT_BTN),
-            _ => None,
-        }
-    }
-}
-
-impl SampleLayers {
-    const_map!(
-        LAYOUT0, lookup0(),
-        (u8 => LayerOutcome<KeyWithFlags>) {
-            chord!("_^_%") => Emit(Hit(UP)),
-            chord!("_v_%") => Emit(Hit(DOWN)),
-            chord!("^__%") => Emit(Hit(LEFT)),
-            chord!("v__%") => Emit(Hit(RIGHT)),
-            chord!("^^_%") => Emit(Hit(PAGE_UP)),
-            chord!("vv_v") => Emit(Hit(PAGE_DOWN)),
-
-            chord!("__^_") => Emit(Hit(SPACE)),
-            chord!("_^__") => Emit(Hit(BACKSPACE)),
-            chord!("___^") => Emit(Hit(E)),
-            chord!("___v") => Emit(Hit(T)),
-            chord!("__v_") => Emit(Hit(A)),
-            chord!("___%") => Emit(Hit(I)),
-            chord!("__%_") => Emit(Hit(O)),
-            chord!("_v__") => Emit(Hit(N)),
-            chord!("^___") => Emit(Hit(S)),
-            chord!("_%__") => Emit(Hit(H)),
-            chord!("v___") => Emit(Hit(R)),
-            chord!("%___") => Emit(Hit(L)),
-            chord!("__^^") => Emit(Hit(D)),
-            chord!("__vv") => Emit(Hit(C)),
-            chord!("__^v") => Emit(Hit(U)),
-            chord!("^^__") => Emit(Hit(M)),
-            chord!("_vv_") => TemporaryLayerSwitch { layer: 1 }, // SHIFT
-            chord!("_^^_") => TemporaryPlusMask { mask: CTRL_FLAG }, // CTRL
-            chord!("%%__") => TemporaryPlusMask { mask: ALT_FLAG }, // ALT
-            chord!("%%_^") => TemporaryPlusMask { mask: RIGHT_ALT_FLAG }, // R-ALT
-            chord!("_%%_") => TemporaryPlusMask { mask: GUI_FLAG }, // GUI
-            chord!("_^_^") => Emit(Hit(TAB)),
-            chord!("__^%") => Emit(Hit(W)),
-            chord!("_^_v") => Emit(Hit(G)),
-            chord!("__%v") => Emit(Hit(F)),
-            chord!("__%%") => Emit(Hit(Y)),
-            chord!("_v_v") => Emit(Hit(P)),
-            chord!("v__v") => Emit(Hit(B)),
-            chord!("^__^") => Emit(Hit(COMMA)),
-            chord!("_^^^") => Emit(Hit(PERIOD)),
-            chord!("_vvv") => Emit(Hit(V)),
-            chord!("_%_%") => Emit(Hit(ENTER)),
-            chord!("vvvv") => Emit(Hit(ESC)),
-            chord!("^__v") => Emit(Hit(K)),
-            chord!("%__%") => Emit(Hit(QUOTE)),
-            chord!("%__v") => Emit(Hit(QUOTE | SHIFT_FLAG)), // "
-            chord!("vvv_") => Emit(Hit(MINUS)),
-            chord!("__v%") => Emit(Hit(X)),
-            chord!("_%%%") => Emit(Hit(J)),
-            chord!("_%_v") => Emit(Hit(SEMICOLON)),
-            chord!("^^^_") => Emit(Hit(KEY_9 | SHIFT_FLAG)), // (
-            chord!("^_^_") => Emit(Hit(KEY_0 | SHIFT_FLAG)), // )
-            chord!("^^^^") => Emit(Hit(Q)),
-            chord!("_^^v") => Emit(Hit(SLASH)),
-            chord!("_^^%") => Emit(Hit(Z)),
-            chord!("^^_v") => Emit(Hit(SEMICOLON | SHIFT_FLAG)), // :
-            chord!("_^%_") => Emit(Hit(KEY_0)),
-            chord!("v_v_") => Emit(Hit(KEY_1)),
-            chord!("%_%_") => Emit(Hit(KEY_2)),
-            chord!("%%%_") => Emit(Hit(KEY_3)),
-            chord!("^^^%") => Emit(Hit(KEY_4)),
-            chord!("_vv%") => Emit(Hit(EQUAL)),
-            chord!("%^__") => Emit(Hit(KEY_4 | SHIFT_FLAG)), // $
-            chord!("^^_%") => Emit(Hit(KEY_8 | SHIFT_FLAG)), // *
-            chord!("^_%_") => Emit(Hit(LEFT_BRACE | SHIFT_FLAG)), // {
-            chord!("v_%_") => Emit(Hit(RIGHT_BRACE | SHIFT_FLAG)), // }
-
-            chord!("v^_v") => LayerSwitchAndEmit {
-                layer: 2,
-                emit: Hit(HACK_MOUSE_ENABLE_TOGGLE),
-            },
-        }
-    );
-
     // "SHIFT" layer
-    const_map!(
-        LAYOUT1, lookup1(),
-        (u8 => LayerOutcome<KeyWithFlags>) {
-            0 => FromOtherPlusMask { layer: 0, mask: SHIFT_FLAG },
-
-            chord!("_^%_") => Emit(Hit(KEY_5)), // S-0 5
-            chord!("v_v_") => Emit(Hit(KEY_6)), // S-1 6
-            chord!("%_%_") => Emit(Hit(KEY_7)), // S-2 7
-            chord!("%%%_") => Emit(Hit(KEY_8)), // S-3 8
-            chord!("^^^%") => Emit(Hit(KEY_9)), // S-4 9
-            chord!("^__^") => Emit(Hit(SLASH | SHIFT_FLAG)), // S-, ?
-            chord!("_^^^") => Emit(Hit(KEY_1 | SHIFT_FLAG)), // S-. !
-            chord!("vvv_") => Emit(Hit(MINUS | SHIFT_FLAG)), // S-- _
-            chord!("%__%") => Emit(Hit(TILDE)), // S-' `
-            chord!("^^^_") => Emit(Hit(LEFT_BRACE)), // S-( [
-            chord!("^_^_") => Emit(Hit(RIGHT_BRACE)), // S-) ]
-            chord!("_vv%") => Emit(Hit(EQUAL | SHIFT_FLAG)), // S-= +
-            chord!("^_%_") => Emit(Hit(COMMA | SHIFT_FLAG)), // S-{ <
-            chord!("v_%_") => Emit(Hit(PERIOD | SHIFT_FLAG)), // S-} >
-            chord!("%__v") => Emit(Hit(KEY_7 | SHIFT_FLAG)), // S-" &
-            chord!("_%_v") => Emit(Hit(KEY_2 | SHIFT_FLAG)), // S-; @
-            chord!("_^^v") => Emit(Hit(BACKSLASH)), // S-/ \
-            chord!("^^_v") => Emit(Hit(BACKSLASH | SHIFT_FLAG)), // S-: |
-            chord!("%^__") => Emit(Hit(TILDE | SHIFT_FLAG)), // S-$ ~
-            chord!("^^_%") => Emit(Hit(KEY_6 | SHIFT_FLAG)), // S-* ^
-
-            chord!("_^__") => Emit(Hit(DELETE)), // S-Backspace KEY_DELETE
-            chord!("_^_%") => Emit(Hit(HOME)), // S-Up KEY_HOME
-            chord!("_v_%") => Emit(Hit(END)), // S-Down KEY_END
-        }
-    );
+    layer 1 {
+        fallback: LayerFallback::EmitWithMask { layer: 0, mask: SHIFT_FLAG },
+        "_^%_" => Emit(Hit(Key(KEY_5))), // S-0 5
+        "v_v_" => Emit(Hit(Key(KEY_6))), // S-1 6
+        "%_%_" => Emit(Hit(Key(KEY_7))), // S-2 7
+        "%%%_" => Emit(Hit(Key(KEY_8))), // S-3 8
+        "^^^%" => Emit(Hit(Key(KEY_9))), // S-4 9
+        "^__^" => Emit(Hit(Key(SLASH | SHIFT_FLAG))), // S-, ?
+        "_^^^" => Emit(Hit(Key(KEY_1 | SHIFT_FLAG))), // S-. !
+        "vvv_" => Emit(Hit(Key(MINUS | SHIFT_FLAG))), // S-- _
+        "%__%" => Emit(Hit(Key(TILDE))), // S-' `
+        "^^^_" => Emit(Hit(Key(LEFT_BRACE))), // S-( [
+        "^_^_" => Emit(Hit(Key(RIGHT_BRACE))), // S-) ]
+        "_vv%" => Emit(Hit(Key(EQUAL | SHIFT_FLAG))), // S-= +
+        "^_%_" => Emit(Hit(Key(COMMA | SHIFT_FLAG))), // S-{ <
+        "v_%_" => Emit(Hit(Key(PERIOD | SHIFT_FLAG))), // S-} >
+        "%__v" => Emit(Hit(Key(KEY_7 | SHIFT_FLAG))), // S-" &
+        "_%_v" => Emit(Hit(Key(KEY_2 | SHIFT_FLAG))), // S-; @
+        "_^^v" => Emit(Hit(Key(BACKSLASH))), // S-/ \
+        "^^_v" => Emit(Hit(Key(BACKSLASH | SHIFT_FLAG))), // S-: |
+        "%^__" => Emit(Hit(Key(TILDE | SHIFT_FLAG))), // S-$ ~
+        "^^_%" => Emit(Hit(Key(KEY_6 | SHIFT_FLAG))), // S-* ^
+
+        "_^__" => Emit(Hit(Key(DELETE))), // S-Backspace KEY_DELETE
+        "_^_%" => Emit(Hit(Key(HOME))), // S-Up KEY_HOME
+        "_v_%" => Emit(Hit(Key(END))), // S-Down KEY_END
+    }
 
     //== TODO: ==
     // '%'
@@ -172,16 +152,41 @@ impl SampleLayers {
     // Alt-Tab & Alt-Shift-Tab
 
     // TEST layer
-    const_map!(
-        LAYOUT2, lookup2(),
-        (u8 => LayerOutcome<KeyWithFlags>) {
-            // 0 => FromOtherPlusMask { layer: 0, mask: SHIFT_FLAG },
-            chord!("^^__") => TemporaryPlusMask { mask: CTRL_FLAG }, // CTRL
-
-            chord!("v^_v") => LayerSwitchAndEmit {
-                layer: 0,
-                emit: Hit(HACK_MOUSE_ENABLE_TOGGLE),
-            },
+    layer 2 {
+        unchorded_mask: "__^^",
+        unchorded "___^" => MOUSE_LEFT,
+        unchorded "__^_" => MOUSE_RIGHT,
+
+        "^^__" => TemporaryPlusMask { mask: CTRL_FLAG }, // CTRL
+
+        "v^_v" => LayerSwitchAndEmit {
+            layer: 0,
+            emit: Hit(MOUSE_POINTER_TOGGLE),
+        },
+    }
+
+    // "NUM" layer
+    layer 3 {
+        transparent: true,
+        "___^" => Emit(Hit(Key(KEY_1))),
+        "__^_" => Emit(Hit(Key(KEY_2))),
+        "_^__" => Emit(Hit(Key(KEY_3))),
+        "^___" => Emit(Hit(Key(KEY_4))),
+    }
+
+    fn repeat(layer: u8, chord: u8) -> Option<Repeat> {
+        match Self::lookup(layer, chord)? {
+            Emit(Hit(Key(UP | DOWN | LEFT | RIGHT))) => Some(Repeat { delay: 300, interval: 50 }),
+            _ => None,
         }
-    );
+    }
+
+    fn sequence(chords: &[u8]) -> SequenceMatch<Self::Action> {
+        crate::lookup_sequence(chords, &[
+            (&[chord!("_^_v")], EmitText("git ")), // G
+            (&chord!("_^_v ^___"), EmitText("git status\n")), // G S
+            (&chord!("_^_v __^^"), EmitText("git diff\n")), // G D
+            (&chord!("___v __%_ __^^"), EmitText("TODO: ")), // T O D
+        ])
+    }
 }
@@ -0,0 +1,119 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Recent outputs, to be erased by [`LayerOutcome::Undo`](crate::LayerOutcome::Undo).
+
+use crate::briefs::{Output, Translator};
+
+const MAX_DEPTH: usize = 8;
+
+/// How to undo an output.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Entry {
+    /// Edits erasing the output.
+    pub erase: Output<'static>,
+    /// State of the [`LayerOutcome::Briefs`](crate::LayerOutcome::Briefs)
+    /// translation from before the output.
+    pub translator: Translator,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct History {
+    entries: [Entry; MAX_DEPTH],
+    len: u8,
+}
+
+impl History {
+    /// Adds the most recent output. If the history is full, the oldest
+    /// output is forgotten.
+    pub fn push(&mut self, entry: Entry) {
+        if usize::from(self.len) == MAX_DEPTH {
+            self.entries.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.entries[usize::from(self.len)] = entry;
+        self.len += 1;
+    }
+
+    /// Removes the most recent output.
+    pub fn pop(&mut self) -> Option<Entry> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.entries[usize::from(self.len)])
+    }
+
+    /// Adds characters to the most recent output, e.g. when its key is
+    /// auto-repeated.
+    pub fn extend(&mut self, chars: u16) {
+        if let Some(entry) = self.entries[..usize::from(self.len)].last_mut() {
+            entry.erase.erase_more(chars);
+        }
+    }
+
+    /// Forgets the most recent `n` outputs, e.g. replaced by a later one.
+    pub fn forget(&mut self, n: usize) {
+        self.len -= n.min(usize::from(self.len)) as u8;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        usize::from(self.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::briefs::Edit;
+
+    fn erasing(chars: u16) -> Entry {
+        Entry { erase: Output::erase(chars, None), ..Default::default() }
+    }
+
+    fn popped(history: &mut History) -> Option<usize> {
+        history.pop().map(|entry| entry.erase.count())
+    }
+
+    #[test]
+    fn push_pop_and_overflow() {
+        let mut history = History::default();
+        assert_eq!(popped(&mut history), None);
+        for chars in 1..=10 {
+            history.push(erasing(chars));
+        }
+        assert_eq!(history.len(), MAX_DEPTH);
+        history.extend(5);
+        assert_eq!(popped(&mut history), Some(15));
+        history.forget(2);
+        assert_eq!(popped(&mut history), Some(7));
+        history.forget(100);
+        assert!(history.is_empty());
+        assert_eq!(popped(&mut history), None);
+    }
+
+    #[test]
+    fn erase_with_restore() {
+        let edits: Vec<_> = Output::erase(2, Some('e')).collect();
+        assert_eq!(edits, [Edit::Backspace, Edit::Backspace, Edit::Char('e')]);
+    }
+}
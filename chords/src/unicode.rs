@@ -0,0 +1,230 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Typing arbitrary Unicode characters, using input methods of the host OS.
+
+use crate::UsbOutcome::{self, *};
+use crate::host_layout::{HostLayout, Stroke};
+use crate::keycodes::*;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Method {
+    /// Ctrl+Shift+U, hex digits, Space - for Linux with IBus, or GTK apps.
+    #[default]
+    Linux,
+    /// Alt held, numpad '+', hex digits - requires `EnableHexNumpad` set to
+    /// `"1"` in `HKEY_CURRENT_USER\Control Panel\Input Method` on Windows.
+    WindowsAltNumpad,
+    /// Option held, 4 hex digits per UTF-16 code unit - requires the
+    /// "Unicode Hex Input" input source selected on macOS.
+    MacHexInput,
+}
+
+// Longest is macOS with a surrogate pair: Option press, 8 digits, release.
+const MAX_LEN: usize = 10;
+
+/// A sequence of key outcomes typing a single character.
+#[derive(Copy, Clone, Debug)]
+pub struct Sequence {
+    keys: [UsbOutcome<KeyWithFlags>; MAX_LEN],
+    len: u8,
+    pos: u8,
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self { keys: [Nothing; MAX_LEN], len: 0, pos: 0 }
+    }
+}
+
+impl Sequence {
+    pub fn new(c: char, method: Method) -> Self {
+        let mut seq = Self::default();
+        let v = u32::from(c);
+        match method {
+            Method::Linux => {
+                seq.push(KeyHit(U | CTRL_FLAG | SHIFT_FLAG));
+                seq.push_hex(v, hex_digits(v), false);
+                seq.push(KeyHit(SPACE));
+            }
+            Method::WindowsAltNumpad => {
+                seq.push(KeyPress(LEFT_ALT_FLAG));
+                seq.push(KeyHit(KEYPAD_PLUS));
+                seq.push_hex(v, hex_digits(v), true);
+                seq.push(KeyRelease(LEFT_ALT_FLAG));
+            }
+            Method::MacHexInput => {
+                seq.push(KeyPress(LEFT_ALT_FLAG));
+                let mut buf = [0u16; 2];
+                for &unit in c.encode_utf16(&mut buf).iter() {
+                    seq.push_hex(unit.into(), 4, false);
+                }
+                seq.push(KeyRelease(LEFT_ALT_FLAG));
+            }
+        }
+        seq
+    }
+
+    /// Types `c` with a key on the `layout` if possible, or else using the
+    /// input `method`.
+    pub fn with_layout(c: char, layout: HostLayout, method: Method) -> Self {
+        match layout.stroke(c) {
+            Some(stroke) => Self::stroke(stroke),
+            None => Self::new(c, method),
+        }
+    }
+
+    pub(crate) fn stroke(stroke: Stroke) -> Self {
+        let mut seq = Self::default();
+        seq.push(KeyHit(stroke.key));
+        if stroke.dead {
+            seq.push(KeyHit(SPACE));
+        }
+        seq
+    }
+
+    fn push(&mut self, key: UsbOutcome<KeyWithFlags>) {
+        self.keys[usize::from(self.len)] = key;
+        self.len += 1;
+    }
+
+    /// Pushes `digits` lowest hex digits of `v`, most significant first.
+    fn push_hex(&mut self, v: u32, digits: u32, keypad: bool) {
+        for i in (0..digits).rev() {
+            let digit = (v >> (4 * i)) & 0xf;
+            self.push(KeyHit(hex_key(digit, keypad)));
+        }
+    }
+}
+
+impl Iterator for Sequence {
+    type Item = UsbOutcome<KeyWithFlags>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.len {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.keys[usize::from(self.pos - 1)])
+    }
+}
+
+/// Number of hex digits needed to print `v`, without leading zeros.
+fn hex_digits(v: u32) -> u32 {
+    (u32::BITS - v.leading_zeros()).div_ceil(4).max(1)
+}
+
+fn hex_key(digit: u32, keypad: bool) -> KeyWithFlags {
+    let digit = digit as KeyWithFlags;
+    match (digit, keypad) {
+        (0, false) => KEY_0,
+        (0, true) => KEYPAD_0,
+        (1..=9, false) => KEY_1 + digit - 1,
+        (1..=9, true) => KEYPAD_1 + digit - 1,
+        _ => A + digit - 0xa,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(c: char, method: Method) -> Vec<UsbOutcome<KeyWithFlags>> {
+        Sequence::new(c, method).collect()
+    }
+
+    #[test]
+    fn linux() {
+        // ż = U+017C
+        assert_eq!(keys('ż', Method::Linux), [
+            KeyHit(U | CTRL_FLAG | SHIFT_FLAG),
+            KeyHit(KEY_1), KeyHit(KEY_7), KeyHit(C),
+            KeyHit(SPACE),
+        ]);
+        // Привіт: П = U+041F
+        assert_eq!(keys('П', Method::Linux), [
+            KeyHit(U | CTRL_FLAG | SHIFT_FLAG),
+            KeyHit(KEY_4), KeyHit(KEY_1), KeyHit(F),
+            KeyHit(SPACE),
+        ]);
+        assert_eq!(keys('\0', Method::Linux), [
+            KeyHit(U | CTRL_FLAG | SHIFT_FLAG),
+            KeyHit(KEY_0),
+            KeyHit(SPACE),
+        ]);
+        // 🦀 = U+1F980
+        assert_eq!(keys('🦀', Method::Linux), [
+            KeyHit(U | CTRL_FLAG | SHIFT_FLAG),
+            KeyHit(KEY_1), KeyHit(F), KeyHit(KEY_9), KeyHit(KEY_8), KeyHit(KEY_0),
+            KeyHit(SPACE),
+        ]);
+    }
+
+    #[test]
+    fn windows() {
+        assert_eq!(keys('ż', Method::WindowsAltNumpad), [
+            KeyPress(LEFT_ALT_FLAG),
+            KeyHit(KEYPAD_PLUS),
+            KeyHit(KEYPAD_1), KeyHit(KEYPAD_7), KeyHit(C),
+            KeyRelease(LEFT_ALT_FLAG),
+        ]);
+        assert_eq!(keys('\u{10ffff}', Method::WindowsAltNumpad), [
+            KeyPress(LEFT_ALT_FLAG),
+            KeyHit(KEYPAD_PLUS),
+            KeyHit(KEYPAD_1), KeyHit(KEYPAD_0), KeyHit(F), KeyHit(F), KeyHit(F), KeyHit(F),
+            KeyRelease(LEFT_ALT_FLAG),
+        ]);
+    }
+
+    #[test]
+    fn mac() {
+        assert_eq!(keys('ż', Method::MacHexInput), [
+            KeyPress(LEFT_ALT_FLAG),
+            KeyHit(KEY_0), KeyHit(KEY_1), KeyHit(KEY_7), KeyHit(C),
+            KeyRelease(LEFT_ALT_FLAG),
+        ]);
+        // surrogate pair: D83E DD80
+        assert_eq!(keys('🦀', Method::MacHexInput), [
+            KeyPress(LEFT_ALT_FLAG),
+            KeyHit(D), KeyHit(KEY_8), KeyHit(KEY_3), KeyHit(E),
+            KeyHit(D), KeyHit(D), KeyHit(KEY_8), KeyHit(KEY_0),
+            KeyRelease(LEFT_ALT_FLAG),
+        ]);
+    }
+
+    #[test]
+    fn with_layout() {
+        let keys = |c, layout| -> Vec<_> { Sequence::with_layout(c, layout, Method::Linux).collect() };
+        assert_eq!(keys('@', HostLayout::German), [KeyHit(Q | RIGHT_ALT_FLAG)]);
+        assert_eq!(keys('^', HostLayout::German), [KeyHit(TILDE), KeyHit(SPACE)]);
+        assert_eq!(keys('ż', HostLayout::PolishProgrammer), [KeyHit(Z | RIGHT_ALT_FLAG)]);
+        assert_eq!(keys('ż', HostLayout::German), [
+            KeyHit(U | CTRL_FLAG | SHIFT_FLAG),
+            KeyHit(KEY_1), KeyHit(KEY_7), KeyHit(C),
+            KeyHit(SPACE),
+        ]);
+    }
+
+    #[test]
+    fn all_hex_digits() {
+        let seq = keys('\u{fedcb}', Method::Linux);
+        assert_eq!(seq[1..6], [KeyHit(F), KeyHit(E), KeyHit(D), KeyHit(C), KeyHit(B)]);
+        let seq = keys('\u{98765}', Method::Linux);
+        assert_eq!(seq[1..6], [KeyHit(KEY_9), KeyHit(KEY_8), KeyHit(KEY_7), KeyHit(KEY_6), KeyHit(KEY_5)]);
+        let seq = keys('\u{4321}', Method::WindowsAltNumpad);
+        assert_eq!(seq[2..6], [KeyHit(KEYPAD_4), KeyHit(KEYPAD_3), KeyHit(KEYPAD_2), KeyHit(KEYPAD_1)]);
+    }
+}
@@ -0,0 +1,688 @@
+// clawtype-chords is (a part of) firmware for chorded keyboards
+// Copyright (C) 2025  Mateusz Czapliński akavel.pl
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Implementation of the [`layout!`](crate::layout) macro.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use proc_macro2::{Delimiter, Group, Ident, Literal, Spacing, Span, TokenStream, TokenTree as TT};
+
+use crate::Error;
+
+/// Path of the crate with the `Lookup` trait, as seen from the generated code.
+const KRATE: &str = "clawtype_chords";
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub(crate) fn layout(input: TokenStream) -> TokenStream {
+    let result = parse(input).and_then(|layout| {
+        layout.check()?;
+        Ok(layout.generate())
+    });
+    result.unwrap_or_else(|err| err.to_compile_error())
+}
+
+/// A chord like `"_^_%"`, as in [`chord!`](crate::chord).
+#[derive(Clone, Debug)]
+struct Pattern {
+    bits: u16,
+    width: usize,
+    text: String,
+    span: Span,
+}
+
+impl Pattern {
+    fn parse(lit: &Literal) -> Result<Self> {
+        let seq = crate::parse_literal(lit)?;
+        let &[bits] = seq.chords() else {
+            return Err(Error::new(lit.span(), "expected a single chord"));
+        };
+        let text = lit.to_string().trim_matches('"').to_string();
+        Ok(Self { bits, width: seq.width(), text, span: lit.span() })
+    }
+}
+
+/// A layer number used in the layout, which must be declared.
+#[derive(Clone, Copy, Debug)]
+struct LayerRef {
+    number: u64,
+    span: Span,
+}
+
+#[derive(Debug)]
+struct Layer {
+    number: u8,
+    span: Span,
+    unchorded_mask: Option<Pattern>,
+    transparent: Option<TokenStream>,
+    fallback: Option<TokenStream>,
+    fallbacks: Option<Vec<LayerRef>>,
+    unchorded: Vec<(Pattern, TokenStream)>,
+    chords: Vec<(Pattern, TokenStream)>,
+}
+
+#[derive(Debug, Default)]
+struct Layout {
+    /// The `struct` declaration, with any attributes.
+    decl: TokenStream,
+    name: Option<Ident>,
+    action_type: Option<TokenStream>,
+    layers: Vec<Layer>,
+    /// Other methods of the `Lookup` impl, copied verbatim.
+    items: TokenStream,
+}
+
+struct Cursor {
+    tokens: std::iter::Peekable<proc_macro2::token_stream::IntoIter>,
+    /// Where to report a missing token.
+    end: Span,
+}
+
+impl Cursor {
+    fn new(stream: TokenStream, end: Span) -> Self {
+        Self { tokens: stream.into_iter().peekable(), end }
+    }
+
+    fn peek(&mut self) -> Option<&TT> {
+        self.tokens.peek()
+    }
+
+    fn next(&mut self) -> Option<TT> {
+        self.tokens.next()
+    }
+
+    fn expected(&mut self, what: &str) -> Error {
+        match self.peek() {
+            Some(tree) => Error::new(tree.span(), format!("expected {what}, found `{tree}`")),
+            None => Error::new(self.end, format!("expected {what}")),
+        }
+    }
+
+    fn is_punct(&mut self, c: char) -> bool {
+        matches!(self.peek(), Some(TT::Punct(p)) if p.as_char() == c)
+    }
+
+    fn punct(&mut self, c: char) -> Result<()> {
+        if !self.is_punct(c) {
+            return Err(self.expected(&format!("`{c}`")));
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn arrow(&mut self) -> Result<()> {
+        match self.peek() {
+            Some(TT::Punct(p)) if p.as_char() == '=' && p.spacing() == Spacing::Joint => {
+                self.next();
+                self.punct('>')
+            }
+            _ => Err(self.expected("`=>`")),
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<Ident> {
+        match self.peek() {
+            Some(TT::Ident(_)) => match self.next() {
+                Some(TT::Ident(ident)) => Ok(ident),
+                _ => unreachable!(),
+            },
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    fn literal(&mut self, what: &str) -> Result<Literal> {
+        match self.peek() {
+            Some(TT::Literal(_)) => match self.next() {
+                Some(TT::Literal(lit)) => Ok(lit),
+                _ => unreachable!(),
+            },
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    fn group(&mut self, delimiter: Delimiter, what: &str) -> Result<Group> {
+        match self.peek() {
+            Some(TT::Group(g)) if g.delimiter() == delimiter => match self.next() {
+                Some(TT::Group(g)) => Ok(g),
+                _ => unreachable!(),
+            },
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    /// Takes the tokens up to the `end` punctuation, which is consumed,
+    /// or up to the end of input.
+    fn until(&mut self, end: char, what: &str) -> Result<TokenStream> {
+        let mut stream = TokenStream::new();
+        while let Some(tree) = self.next() {
+            if matches!(&tree, TT::Punct(p) if p.as_char() == end) {
+                break;
+            }
+            stream.extend([tree]);
+        }
+        if stream.is_empty() {
+            return Err(Error::new(self.end, format!("expected {what}")));
+        }
+        Ok(stream)
+    }
+
+    /// Consumes the `,` ending a layer's entry, if any.
+    fn end_entry(&mut self) -> Result<()> {
+        if self.peek().is_some() {
+            self.punct(',')?;
+        }
+        Ok(())
+    }
+}
+
+fn set<T>(slot: &mut Option<T>, value: T, name: &Ident) -> Result<()> {
+    if slot.is_some() {
+        return Err(Error::new(name.span(), format!("`{name}` is already set")));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Parses an integer literal like `3` or `3u8`.
+fn int_value(lit: &Literal) -> Option<u64> {
+    let s = lit.to_string();
+    let digits = s.split(['u', 'i']).next()?.replace('_', "");
+    digits.parse().ok()
+}
+
+fn parse(input: TokenStream) -> Result<Layout> {
+    let mut c = Cursor::new(input, Span::call_site());
+    let mut layout = Layout::default();
+    while c.peek().is_some() {
+        // Attributes and visibility, before `struct` or `fn`.
+        let mut prefix = TokenStream::new();
+        loop {
+            if c.is_punct('#') {
+                prefix.extend(c.next());
+                prefix.extend([TT::Group(c.group(Delimiter::Bracket, "`[`")?)]);
+            } else if matches!(c.peek(), Some(TT::Ident(i)) if i == "pub") {
+                prefix.extend(c.next());
+                if let Some(TT::Group(g)) = c.peek() {
+                    if g.delimiter() == Delimiter::Parenthesis {
+                        prefix.extend(c.next());
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+        let keyword = c.ident("`struct`, `type`, `layer` or `fn`")?;
+        if !prefix.is_empty() && keyword != "struct" && keyword != "fn" {
+            return Err(Error::new(keyword.span(), format!("expected `struct` or `fn`, found `{keyword}`")));
+        }
+        match keyword.to_string().as_str() {
+            "struct" => {
+                let name = c.ident("a struct name")?;
+                if layout.name.is_some() {
+                    return Err(Error::new(name.span(), "only one `struct` can be declared"));
+                }
+                layout.decl.extend(prefix);
+                layout.decl.extend([TT::Ident(keyword), TT::Ident(name.clone())]);
+                match c.next() {
+                    Some(TT::Punct(p)) if p.as_char() == ';' => layout.decl.extend([TT::Punct(p)]),
+                    Some(TT::Group(g)) if g.delimiter() == Delimiter::Brace && g.stream().is_empty() => {
+                        layout.decl.extend([TT::Group(g)])
+                    }
+                    Some(tree) => return Err(Error::new(tree.span(), "expected `;` or `{}`")),
+                    None => return Err(Error::new(c.end, "expected `;` or `{}`")),
+                }
+                layout.name = Some(name);
+            }
+            "type" => {
+                let name = c.ident("`Action`")?;
+                if name != "Action" {
+                    return Err(Error::new(name.span(), format!("expected `Action`, found `{name}`")));
+                }
+                c.punct('=')?;
+                let ty = c.until(';', "a type")?;
+                set(&mut layout.action_type, ty, &name)?;
+            }
+            "layer" => layout.layers.push(parse_layer(&mut c)?),
+            "fn" => {
+                layout.items.extend(prefix);
+                layout.items.extend([TT::Ident(keyword)]);
+                // The signature, then the body.
+                loop {
+                    let Some(tree) = c.next() else {
+                        return Err(Error::new(c.end, "expected a function body"));
+                    };
+                    let body = matches!(&tree, TT::Group(g) if g.delimiter() == Delimiter::Brace);
+                    layout.items.extend([tree]);
+                    if body {
+                        break;
+                    }
+                }
+            }
+            _ => return Err(Error::new(keyword.span(),
+                format!("expected `struct`, `type`, `layer` or `fn`, found `{keyword}`"))),
+        }
+    }
+    if layout.name.is_none() {
+        return Err(Error::new(c.end, "missing a `struct` declaration"));
+    }
+    if layout.action_type.is_none() {
+        return Err(Error::new(c.end, "missing `type Action = ...;`"));
+    }
+    Ok(layout)
+}
+
+fn parse_layer(c: &mut Cursor) -> Result<Layer> {
+    let lit = c.literal("a layer number")?;
+    let Some(number) = int_value(&lit) else {
+        return Err(Error::new(lit.span(), format!("expected a layer number, found `{lit}`")));
+    };
+    let Ok(number) = u8::try_from(number) else {
+        return Err(Error::new(lit.span(), format!("layer {number} is out of range, expected at most 255")));
+    };
+    let body = c.group(Delimiter::Brace, "`{`")?;
+    let mut layer = Layer {
+        number,
+        span: lit.span(),
+        unchorded_mask: None,
+        transparent: None,
+        fallback: None,
+        fallbacks: None,
+        unchorded: Vec::new(),
+        chords: Vec::new(),
+    };
+    let mut c = Cursor::new(body.stream(), body.span_close());
+    while let Some(tree) = c.next() {
+        match tree {
+            TT::Literal(lit) => {
+                let chord = Pattern::parse(&lit)?;
+                c.arrow()?;
+                let outcome = c.until(',', "a layer outcome")?;
+                layer.chords.push((chord, outcome));
+            }
+            TT::Ident(name) if name == "unchorded" => {
+                let switch = Pattern::parse(&c.literal("a chord pattern")?)?;
+                c.arrow()?;
+                let key = c.until(',', "a key")?;
+                layer.unchorded.push((switch, key));
+            }
+            TT::Ident(name) if name == "unchorded_mask" => {
+                c.punct(':')?;
+                let mask = Pattern::parse(&c.literal("a chord pattern")?)?;
+                set(&mut layer.unchorded_mask, mask, &name)?;
+                c.end_entry()?;
+            }
+            TT::Ident(name) if name == "transparent" => {
+                c.punct(':')?;
+                let value = c.until(',', "`true` or `false`")?;
+                set(&mut layer.transparent, value, &name)?;
+            }
+            TT::Ident(name) if name == "fallback" => {
+                c.punct(':')?;
+                let value = c.until(',', "a layer fallback")?;
+                set(&mut layer.fallback, value, &name)?;
+            }
+            TT::Ident(name) if name == "fallbacks" => {
+                c.punct(':')?;
+                let list = c.group(Delimiter::Bracket, "`[`")?;
+                let mut refs = Vec::new();
+                let mut items = Cursor::new(list.stream(), list.span_close());
+                while items.peek().is_some() {
+                    let lit = items.literal("a layer number")?;
+                    let Some(number) = int_value(&lit) else {
+                        return Err(Error::new(lit.span(), format!("expected a layer number, found `{lit}`")));
+                    };
+                    refs.push(LayerRef { number, span: lit.span() });
+                    items.end_entry()?;
+                }
+                set(&mut layer.fallbacks, refs, &name)?;
+                c.end_entry()?;
+            }
+            tree => return Err(Error::new(tree.span(), format!(
+                "expected a chord pattern like \"_^_%\", `unchorded`, `unchorded_mask`, \
+                 `transparent`, `fallback` or `fallbacks`, found `{tree}`"))),
+        }
+    }
+    Ok(layer)
+}
+
+/// Finds layer numbers in `layer: N` fields and `Layer(N)` actions.
+fn layer_refs(stream: &TokenStream, refs: &mut Vec<LayerRef>) {
+    let trees: Vec<TT> = stream.clone().into_iter().collect();
+    for (i, tree) in trees.iter().enumerate() {
+        match tree {
+            TT::Group(g) => {
+                let after_layer = i > 0 && matches!(&trees[i - 1], TT::Ident(id) if id == "Layer");
+                let inner: Vec<TT> = g.stream().into_iter().collect();
+                if let (true, Delimiter::Parenthesis, [TT::Literal(lit)]) = (after_layer, g.delimiter(), &inner[..]) {
+                    if let Some(number) = int_value(lit) {
+                        refs.push(LayerRef { number, span: lit.span() });
+                    }
+                }
+                layer_refs(&g.stream(), refs);
+            }
+            TT::Ident(id) if id == "layer" => {
+                if let (Some(TT::Punct(p)), Some(TT::Literal(lit))) = (trees.get(i + 1), trees.get(i + 2)) {
+                    if p.as_char() == ':' && p.spacing() == Spacing::Alone {
+                        if let Some(number) = int_value(lit) {
+                            refs.push(LayerRef { number, span: lit.span() });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Layout {
+    fn patterns(&self) -> impl Iterator<Item = &Pattern> {
+        self.layers.iter().flat_map(|layer| {
+            let chords = layer.chords.iter().map(|(p, _)| p);
+            let unchorded = layer.unchorded.iter().map(|(p, _)| p);
+            layer.unchorded_mask.iter().chain(unchorded).chain(chords)
+        })
+    }
+
+    /// Number of positions in the chord patterns.
+    fn width(&self) -> usize {
+        self.patterns().next().map_or(4, |p| p.width)
+    }
+
+    fn check(&self) -> Result<()> {
+        let width = self.width();
+        if let Some(p) = self.patterns().find(|p| p.width != width) {
+            return Err(Error::new(p.span, format!(
+                "chord has {} positions, but the first one in the layout has {width}", p.width)));
+        }
+
+        let mut declared = BTreeMap::new();
+        for layer in &self.layers {
+            if declared.insert(layer.number, layer.span).is_some() {
+                return Err(Error::new(layer.span, format!("layer {} is already declared", layer.number)));
+            }
+        }
+
+        let mut refs = Vec::new();
+        for layer in &self.layers {
+            let n = layer.number;
+            let mask = layer.unchorded_mask.as_ref().map_or(0, |p| p.bits);
+
+            let mut chords = BTreeMap::new();
+            for (chord, outcome) in &layer.chords {
+                if chord.bits == 0 {
+                    return Err(Error::new(chord.span, "chord with no switches can never be typed"));
+                }
+                if chord.bits & mask != 0 {
+                    let mask = &layer.unchorded_mask.as_ref().unwrap().text;
+                    return Err(Error::new(chord.span, format!(
+                        "chord \"{}\" is masked out by `unchorded_mask: \"{mask}\"` of layer {n}, \
+                         so it can never be typed", chord.text)));
+                }
+                if chords.insert(chord.bits, ()).is_some() {
+                    return Err(Error::new(chord.span, format!(
+                        "duplicate chord \"{}\" in layer {n}", chord.text)));
+                }
+                layer_refs(outcome, &mut refs);
+            }
+
+            let mut switches = BTreeMap::new();
+            for (switch, _) in &layer.unchorded {
+                if switch.bits.count_ones() != 1 {
+                    return Err(Error::new(switch.span, format!(
+                        "unchorded key must be a single switch, got \"{}\"", switch.text)));
+                }
+                if switch.bits & !mask != 0 {
+                    return Err(Error::new(switch.span, format!(
+                        "switch \"{}\" is not in `unchorded_mask` of layer {n}", switch.text)));
+                }
+                if switches.insert(switch.bits, ()).is_some() {
+                    return Err(Error::new(switch.span, format!(
+                        "duplicate unchorded key \"{}\" in layer {n}", switch.text)));
+                }
+            }
+
+            if let Some(fallback) = &layer.fallback {
+                layer_refs(fallback, &mut refs);
+            }
+            refs.extend(layer.fallbacks.iter().flatten().copied());
+        }
+
+        for r in refs {
+            if u8::try_from(r.number).is_ok_and(|n| declared.contains_key(&n)) {
+                continue;
+            }
+            let list: Vec<_> = declared.keys().map(u8::to_string).collect();
+            return Err(Error::new(r.span, format!(
+                "layer {} is out of range, declared layers are: {}", r.number, list.join(", "))));
+        }
+        Ok(())
+    }
+
+    fn generate(self) -> TokenStream {
+        let ct = if self.width() == 4 { "u8" } else { "u16" };
+        let name = self.name.as_ref().unwrap();
+        let mut args = vec![self.action_type.clone().unwrap(), self.items.clone()];
+        let mut arg = |stream: &TokenStream| {
+            args.push(stream.clone());
+            format!("__LAYOUT_ARG_{}", args.len() - 1)
+        };
+
+        let mut lookup_arms = String::new();
+        let mut info_arms = String::new();
+        let mut unchorded_arms = String::new();
+        let mut fallbacks_arms = String::new();
+        let mut consts = String::new();
+        for layer in &self.layers {
+            let n = layer.number;
+            writeln!(lookup_arms, "{n} => Self::LAYOUT{n},").unwrap();
+
+            if layer.unchorded_mask.is_some() || layer.transparent.is_some() || layer.fallback.is_some() {
+                let mask = layer.unchorded_mask.as_ref().map_or(0, |p| p.bits);
+                let transparent = layer.transparent.as_ref().map_or("false".into(), &mut arg);
+                let fallback = layer.fallback.as_ref()
+                    .map_or(format!("{KRATE}::LayerFallback::None"), &mut arg);
+                writeln!(info_arms, "{n} => {KRATE}::LayerInfo {{ \
+                    unchorded_mask: {KRATE}::SwitchSet({mask}{ct}), \
+                    transparent: {transparent}, fallback: {fallback} }},").unwrap();
+            }
+            for (switch, key) in &layer.unchorded {
+                writeln!(unchorded_arms, "({n}, {}{ct}) => ::core::option::Option::Some({}),",
+                    switch.bits, arg(key)).unwrap();
+            }
+            if let Some(refs) = &layer.fallbacks {
+                let list: Vec<_> = refs.iter().map(|r| r.number.to_string()).collect();
+                writeln!(fallbacks_arms, "{n} => &[{}],", list.join(", ")).unwrap();
+            }
+
+            let mut entries = String::new();
+            for (chord, outcome) in &layer.chords {
+                writeln!(entries, "({}{ct}, {}),", chord.bits, arg(outcome)).unwrap();
+            }
+            writeln!(consts, "const LAYOUT{n}: &'static [({ct}, {KRATE}::LayerOutcome<__LAYOUT_ARG_0>)] = &[{entries}];")
+                .unwrap();
+        }
+
+        let mut methods = format!("
+            fn lookup(layer: u8, chord: {ct}) -> ::core::option::Option<{KRATE}::LayerOutcome<Self::Action>> {{
+                let layout: &[({ct}, {KRATE}::LayerOutcome<Self::Action>)] = match layer {{
+                    {lookup_arms}
+                    _ => &[],
+                }};
+                {KRATE}::lookup_in_slice(chord, layout).copied()
+            }}
+        ");
+        if !info_arms.is_empty() {
+            write!(methods, "
+                fn info(layer: u8) -> {KRATE}::LayerInfo<{ct}> {{
+                    match layer {{
+                        {info_arms}
+                        _ => ::core::default::Default::default(),
+                    }}
+                }}
+            ").unwrap();
+        }
+        if !unchorded_arms.is_empty() {
+            write!(methods, "
+                fn unchorded_key(layer: u8, switch: {KRATE}::SwitchSet<{ct}>) -> ::core::option::Option<Self::Action> {{
+                    match (layer, switch.0) {{
+                        {unchorded_arms}
+                        _ => ::core::option::Option::None,
+                    }}
+                }}
+            ").unwrap();
+        }
+        if !fallbacks_arms.is_empty() {
+            write!(methods, "
+                fn fallbacks(layer: u8) -> &'static [u8] {{
+                    match layer {{
+                        {fallbacks_arms}
+                        _ => &[],
+                    }}
+                }}
+            ").unwrap();
+        }
+
+        let code = format!("
+            impl {KRATE}::Lookup<{ct}> for {name} {{
+                type Action = __LAYOUT_ARG_0;
+                {methods}
+                __LAYOUT_ARG_1
+            }}
+            impl {name} {{
+                {consts}
+            }}
+        ");
+        let mut out = self.decl;
+        out.extend(substitute(code.parse().expect("valid generated code"), &args));
+        out
+    }
+}
+
+/// Replaces the `__LAYOUT_ARG_<i>` placeholders with the macro's input
+/// tokens, keeping their spans for errors in them.
+fn substitute(stream: TokenStream, args: &[TokenStream]) -> TokenStream {
+    stream.into_iter().flat_map(|tree| match tree {
+        TT::Ident(ident) => {
+            let s = ident.to_string();
+            match s.strip_prefix("__LAYOUT_ARG_").and_then(|i| i.parse::<usize>().ok()) {
+                Some(i) => args[i].clone(),
+                None => TT::Ident(ident).into(),
+            }
+        }
+        TT::Group(g) => {
+            let mut group = Group::new(g.delimiter(), substitute(g.stream(), args));
+            group.set_span(g.span());
+            TT::Group(group).into()
+        }
+        tree => tree.into(),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> String {
+        let layout = parse(input.parse().unwrap()).unwrap();
+        layout.check().unwrap_err().msg
+    }
+
+    const HEADER: &str = "pub struct Layers; type Action = u16;";
+
+    #[test]
+    fn generates_lookup() {
+        let out = layout(format!("{HEADER}
+            layer 0 {{
+                \"_^_%\" => Emit(Hit(UP)),
+                \"%%%%\" => PushLayer {{ layer: 1, mode: LayerMode::Toggle }},
+            }}
+            layer 1 {{
+                unchorded_mask: \"__^^\",
+                unchorded \"___^\" => LEFT_BTN,
+                fallbacks: [0],
+                \"^^__\" => Emit(Hit(DOWN)),
+            }}
+            fn hold_threshold() -> u32 {{ 100 }}
+        ").parse().unwrap()).to_string();
+        assert!(!out.contains("compile_error"), "{out}");
+        assert!(!out.contains("__LAYOUT_ARG_"), "{out}");
+        for part in [
+            "impl clawtype_chords :: Lookup < u8 > for Layers",
+            "type Action = u16 ;",
+            "(35u8 , Emit (Hit (UP)))",
+            "SwitchSet (10u8)",
+            "(1 , 2u8) => :: core :: option :: Option :: Some (LEFT_BTN)",
+            "1 => & [0]",
+            "fn hold_threshold () -> u32 { 100 }",
+        ] {
+            assert!(out.contains(part), "{part:?} not in {out}");
+        }
+    }
+
+    #[test]
+    fn wide_chords() {
+        let out = layout(format!("{HEADER} layer 0 {{ \"__^__v\" => Emit(Hit(UP)) }}").parse().unwrap());
+        assert!(out.to_string().contains("Lookup < u16 >"), "{out}");
+    }
+
+    #[test]
+    fn errors() {
+        for (input, want) in [
+            ("layer 0 { \"_^_%\" => Emit(Hit(UP)), \"_^_%\" => Emit(Hit(DOWN)) }",
+                "duplicate chord \"_^_%\" in layer 0"),
+            ("layer 2 { unchorded_mask: \"__^^\", \"%%%%\" => ClearState }",
+                "chord \"%%%%\" is masked out by `unchorded_mask: \"__^^\"` of layer 2, so it can never be typed"),
+            ("layer 0 { \"v_^_\" => PushLayer { layer: 3, mode: LayerMode::Toggle } } layer 1 {}",
+                "layer 3 is out of range, declared layers are: 0, 1"),
+            ("layer 0 { \"_vv_\" => TapHold { tap: Layer(1), hold: Key(CTRL_FLAG) } }",
+                "layer 1 is out of range, declared layers are: 0"),
+            ("layer 0 { fallback: LayerFallback::EmitWithMask { layer: 4, mask: 0 } }",
+                "layer 4 is out of range, declared layers are: 0"),
+            ("layer 0 { fallbacks: [0, 7] }", "layer 7 is out of range, declared layers are: 0"),
+            ("layer 0 {} layer 0 {}", "layer 0 is already declared"),
+            ("layer 0 { unchorded_mask: \"__^^\", unchorded \"__^^\" => BTN }",
+                "unchorded key must be a single switch, got \"__^^\""),
+            ("layer 0 { unchorded_mask: \"__^^\", unchorded \"___v\" => BTN }",
+                "switch \"___v\" is not in `unchorded_mask` of layer 0"),
+            ("layer 0 { \"____\" => ClearState }", "chord with no switches can never be typed"),
+            ("layer 0 { \"^___\" => ClearState, \"^____\" => ClearState }",
+                "chord has 5 positions, but the first one in the layout has 4"),
+        ] {
+            assert_eq!(error(&format!("{HEADER} {input}")), want, "{input}");
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (input, want) in [
+            ("layer 256 {}", "layer 256 is out of range, expected at most 255"),
+            ("layer 0 { \"_^x%\" => ClearState }", "unknown crumb, expected one of: ^ v _ . %, found `x`"),
+            ("layer 0 { \"_^\" => ClearState }", "expected 4, 5 or 6 positions in chord"),
+            ("layer 0 { \"____ ^___\" => ClearState }", "expected a single chord"),
+            ("layer 0 { \"_^_^\" ClearState }", "expected `=>`, found `ClearState`"),
+            ("layer 0 { transparent: true, transparent: false }", "`transparent` is already set"),
+            ("layer 0 { shift }", "expected a chord pattern like \"_^_%\", `unchorded`, `unchorded_mask`, \
+                `transparent`, `fallback` or `fallbacks`, found `shift`"),
+            ("enum Foo;", "expected `struct`, `type`, `layer` or `fn`, found `enum`"),
+        ] {
+            let err = parse(format!("{HEADER} {input}").parse().unwrap()).unwrap_err();
+            assert_eq!(err.msg, want, "{input}");
+        }
+        let err = parse("layer 0 {}".parse().unwrap()).unwrap_err();
+        assert_eq!(err.msg, "missing a `struct` declaration");
+    }
+}
@@ -17,43 +17,167 @@
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
-use proc_macro2::{TokenStream as TokenStream2, TokenTree as TT2};
+use proc_macro2::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream as TokenStream2, TokenTree as TT2};
 
-/// Convert string like: `"v^_%"` to byte, by translating
-/// each of the 4 characters to a crumb (i.e. 2 bits) as follows:
+mod layout;
+#[allow(dead_code)]
+#[path = "../../chords/src/notation.rs"]
+mod notation;
+
+/// Converts a chord written as a string to a number, as in
+/// `clawtype_chords::notation`, translating each position of the chord to a
+/// crumb (i.e. 2 bits) as follows:
 /// - '^' -> 0b10
 /// - 'v' -> 0b01
-/// - '_' -> 0b00
+/// - '_' or '.' -> 0b00
 /// - '%' -> 0b11
+///
+/// Chords of 4 positions, like `"v^_%"` or `"I:^ M:v"` (with finger
+/// labels), become a `u8`. Chords of 5 or 6 positions, e.g. with extra
+/// positions for a thumb, become a `u16`, with the last position in the
+/// least significant bits. A sequence of chords, like `"_v__ ^___"`,
+/// becomes an array.
 #[proc_macro]
 pub fn chord(input: TokenStream) -> TokenStream {
-    let mut input = TokenStream2::from(input).into_iter();
+    let output = chord_impl(input.into()).unwrap_or_else(|err| err.to_compile_error());
+    TokenStream::from(output)
+}
 
-    let Some(tree) = input.next() else {
-        panic!("required exactly 1 token, none found");
+fn chord_impl(input: TokenStream2) -> Result<TokenStream2, Error> {
+    let mut input = input.into_iter();
+    let lit = match input.next() {
+        Some(TT2::Literal(lit)) => lit,
+        Some(tree) => return Err(Error::new(tree.span(), "expected a chord string, like \"_^_%\"")),
+        None => return Err(Error::new(Span::call_site(), "expected a chord string, like \"_^_%\"")),
     };
-    let TT2::Literal(lit) = tree else {
-        panic!("required a string, found: {:?}", tree);
+    if let Some(tree) = input.next() {
+        return Err(Error::new(tree.span(), "unexpected token after the chord string"));
+    }
+    let seq = parse_literal(&lit)?;
+    let literal = |bits: u16| -> TT2 {
+        match seq.width() {
+            4 => Literal::u8_suffixed(bits as u8).into(),
+            _ => Literal::u16_suffixed(bits).into(),
+        }
     };
-    let s = lit.to_string();
-    let s = s.trim_matches('"');
-    let bits = match s.as_bytes() {
-        [a, b, c, d] => b2c(*a, 6) | b2c(*b, 4) | b2c(*c, 2) | b2c(*d, 0),
-        _ => panic!("wanted 4 chars in string, got: {:?}", s),
+    Ok(match seq.chords() {
+        &[bits] => literal(bits).into(),
+        chords => {
+            let mut items = TokenStream2::new();
+            for &bits in chords {
+                items.extend([literal(bits), Punct::new(',', Spacing::Alone).into()]);
+            }
+            TT2::Group(Group::new(Delimiter::Bracket, items)).into()
+        }
+    })
+}
+
+/// Parses a string literal in the chords notation. Errors point at the
+/// offending characters, if the compiler supports it.
+fn parse_literal(lit: &Literal) -> Result<notation::Sequence, Error> {
+    let quoted = lit.to_string();
+    let Some(s) = quoted.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+        return Err(Error::new(lit.span(), "expected a chord string, like \"_^_%\""));
     };
+    notation::parse(s).map_err(|err| {
+        let span = lit.subspan(err.start + 1..err.end + 1).unwrap_or(lit.span());
+        let part = &s[err.start..err.end];
+        match part {
+            "" => Error::new(span, err.kind.to_string()),
+            _ if part == s => Error::new(span, err.kind.to_string()),
+            _ => Error::new(span, format!("{}, found `{part}`", err.kind)),
+        }
+    })
+}
 
-    let tree: TT2 = proc_macro2::Literal::u8_suffixed(bits).into();
-    let output: TokenStream2 = Some(tree).into_iter().collect();
-    TokenStream::from(output)
+/// A problem with a macro's input, reported at the offending tokens.
+#[derive(Debug)]
+struct Error {
+    span: Span,
+    msg: String,
 }
 
-fn b2c(b: u8, shift: usize) -> u8 {
-    let c = match b {
-        b'^' => 0b10u8,
-        b'v' => 0b01u8,
-        b'_' | b'.' => 0b00u8,
-        b'%' => 0b11u8,
-        _ => panic!("unknown crumb pattern: {b:?}"),
-    };
-    c << shift
+impl Error {
+    fn new(span: Span, msg: impl Into<String>) -> Self {
+        Self { span, msg: msg.into() }
+    }
+
+    /// Renders as a `compile_error!` invocation spanning the offending tokens.
+    fn to_compile_error(&self) -> TokenStream2 {
+        let mut msg = Literal::string(&self.msg);
+        msg.set_span(self.span);
+        let mut bang = Punct::new('!', Spacing::Alone);
+        bang.set_span(self.span);
+        let mut args = Group::new(Delimiter::Brace, TT2::Literal(msg).into());
+        args.set_span(self.span);
+        [
+            TT2::Ident(Ident::new("compile_error", self.span)),
+            TT2::Punct(bang),
+            TT2::Group(args),
+        ].into_iter().collect()
+    }
+}
+
+/// Declares a struct implementing `clawtype_chords::Lookup`, with all its
+/// layers, their masks and unchorded keys, e.g.:
+///
+/// ```ignore
+/// layout! {
+///     pub struct MyLayers;
+///     type Action = Action;
+///
+///     layer 0 {
+///         "_^_%" => Emit(Hit(Key(UP))),
+///         "v^_v" => LayerSwitchAndEmit { layer: 1, emit: Hit(MOUSE_POINTER_TOGGLE) },
+///     }
+///     layer 1 {
+///         unchorded_mask: "__^^",
+///         unchorded "___^" => MOUSE_LEFT,
+///         fallback: LayerFallback::EmitWithMask { layer: 0, mask: SHIFT_FLAG },
+///         "^^__" => TemporaryPlusMask { mask: CTRL_FLAG },
+///     }
+///
+///     fn hold_threshold() -> u32 { 300 }
+/// }
+/// ```
+///
+/// Besides chords, a layer can set `unchorded_mask`, `unchorded` keys,
+/// `transparent`, `fallback` and `fallbacks`, as in `LayerInfo` and
+/// `Lookup`. Other methods of `Lookup` are copied into the impl verbatim.
+/// Chords are written as in [`chord!`], with 5 or 6 positions making the
+/// impl a `Lookup<u16>`.
+///
+/// Chords which could never be typed are compile errors: duplicates
+/// within a layer, and ones using switches of the layer's
+/// `unchorded_mask`. So are layer numbers which are not declared.
+#[proc_macro]
+pub fn layout(input: TokenStream) -> TokenStream {
+    TokenStream::from(layout::layout(input.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(input: &str) -> Result<String, String> {
+        chord_impl(input.parse().unwrap()).map(|out| out.to_string()).map_err(|err| err.msg)
+    }
+
+    #[test]
+    fn chords() {
+        assert_eq!(chord("\"_^_%\""), Ok("35u8".into()));
+        assert_eq!(chord("\"I:^ M:v\""), Ok("6u8".into()));
+        assert_eq!(chord("\"___^%_\""), Ok("44u16".into()));
+        assert_eq!(chord("\"_v__ ^___\""), Ok("[16u8 , 128u8 ,]".into()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(chord(""), Err("expected a chord string, like \"_^_%\"".into()));
+        assert_eq!(chord("_^_%"), Err("expected a chord string, like \"_^_%\"".into()));
+        assert_eq!(chord("\"_^_%\" 1"), Err("unexpected token after the chord string".into()));
+        assert_eq!(chord("\"_^x%\""), Err("unknown crumb, expected one of: ^ v _ . %, found `x`".into()));
+        assert_eq!(chord("\"_^_\""), Err("expected 4, 5 or 6 positions in chord".into()));
+        assert_eq!(chord("\"I:^ Q:v\""), Err("unknown finger, expected one of: P R M I T U, found `Q`".into()));
+    }
 }
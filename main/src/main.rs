@@ -24,7 +24,7 @@ use embassy_rp::peripherals::{I2C0, USB};
 use embassy_rp::{usb as rp_usb, i2c as rp_i2c, spi as rp_spi};
 use embassy_sync::mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::*;
-use embassy_time::{Delay, Timer};
+use embassy_time::{Delay, Instant, Timer};
 use embassy_usb::class::hid;
 use embassy_usb::class::cdc_acm;
 use embedded_graphics::prelude::*;
@@ -39,18 +39,10 @@ use u8g2_fonts::{FontRenderer, fonts, types as font_params};
 
 use clawtype_chords::{
     self as chords,
+    keyboard,
     keycodes as new_keys,
-    keycodes::{
-        HACK_MOUSE_MARKER,
-        HACK_MOUSE_ENABLE_TOGGLE,
-        HACK_MOUSE_LEFT_DRAG_TOGGLE,
-        HACK_MOUSE_LEFT_CLICK,
-        HACK_MOUSE_RIGHT_CLICK,
-        HACK_MOUSE_WHEEL_DOWN,
-        HACK_MOUSE_WHEEL_UP,
-        HACK_MOUSE_LEFT_BTN,
-        HACK_MOUSE_RIGHT_BTN,
-    },
+    action::Action,
+    mouse::{Mouse, Report as MouseReport},
     SwitchSet,
     UsbOutcome::*
 };
@@ -77,11 +69,14 @@ async fn main(_spawner: Spawner) {
     use usb_simpler::buffers as usb_buffers;
     let mut usb_buf_dev = usb_buffers::ForDevice::new();
     let mut usb_buf_hid_kbd = usb_buffers::ForHid::new();
+    let mut usb_buf_hid_nkro = usb_buffers::ForHid::new();
     let mut usb_buf_hid_mouse = usb_buffers::ForHid::new();
+    let mut usb_buf_hid_media = usb_buffers::ForHid::new();
+    let mut usb_buf_hid_system = usb_buffers::ForHid::new();
     let mut logger_state = cdc_acm::State::new();
     let mut usb_dev_builder =
         usb_simpler::new("akavel", "clawtype").into_device_builder(driver, &mut usb_buf_dev);
-    let kbd_hid = usb_dev_builder.add_hid_reader_writer::<1, 8>(
+    let kbd_hid = usb_dev_builder.add_hid_reader_writer::<1, { keyboard::REPORT_LEN }>(
         &mut usb_buf_hid_kbd,
         hid::Config {
             report_descriptor: hid_desc::KeyboardReport::desc(),
@@ -90,6 +85,15 @@ async fn main(_spawner: Spawner) {
             max_packet_size: 64,
         },
     );
+    let nkro_writer = usb_dev_builder.add_hid_writer::<{ keyboard::NKRO_REPORT_LEN }>(
+        &mut usb_buf_hid_nkro,
+        hid::Config {
+            report_descriptor: keyboard::NKRO_DESCRIPTOR,
+            request_handler: None,
+            poll_ms: 60,
+            max_packet_size: 64,
+        },
+    );
     let mouse_hid = usb_dev_builder.add_hid_reader_writer::<1, 8>(
         &mut usb_buf_hid_mouse,
         hid::Config {
@@ -99,10 +103,29 @@ async fn main(_spawner: Spawner) {
             max_packet_size: 64,
         },
     );
+    let mut media_writer = usb_dev_builder.add_hid_writer::<2>(
+        &mut usb_buf_hid_media,
+        hid::Config {
+            report_descriptor: hid_desc::MediaKeyboardReport::desc(),
+            request_handler: None,
+            poll_ms: 60,
+            max_packet_size: 64,
+        },
+    );
+    let mut system_writer = usb_dev_builder.add_hid_writer::<1>(
+        &mut usb_buf_hid_system,
+        hid::Config {
+            report_descriptor: hid_desc::SystemControlReport::desc(),
+            request_handler: None,
+            poll_ms: 60,
+            max_packet_size: 64,
+        },
+    );
     let logger_class = usb_dev_builder.add_cdc_acm_class(&mut logger_state, 64);
     let mut usb = usb_dev_builder.build();
     let usb_fut = usb.run();
-    let (kbd_reader, mut kbd_writer) = kbd_hid.split();
+    let (kbd_reader, kbd_writer) = kbd_hid.split();
+    let mut kbd_writers = usb_kbd::Writers::new(kbd_writer, nkro_writer);
     let (mouse_reader, mouse_writer) = mouse_hid.split();
     let log_fut = embassy_usb_logger::with_class!(1024, log::LevelFilter::Info, logger_class);
 
@@ -144,8 +167,7 @@ async fn main(_spawner: Spawner) {
     }
 
     // WARN: to avoid deadlocks, ALWAYS lock multiple ONLY in order like below
-    let mouse_enabled = Mutex::<ThreadModeRawMutex, _>::new(false);
-    let mouse_buttons = Mutex::<ThreadModeRawMutex, _>::new(0u8); // TODO: atomic
+    let mouse = Mutex::<ThreadModeRawMutex, _>::new(Mouse::default());
     let mouse_writer = Mutex::<ThreadModeRawMutex, _>::new(mouse_writer);
 
     ////
@@ -244,17 +266,16 @@ async fn main(_spawner: Spawner) {
             let vx = (gyro.x()/250) as i8;
             let vy = (-gyro.z()/200) as i8;
 
-            let m = { *mouse_enabled.lock().await };
-            if m {
-                // log::info!("m enabled");
-                let b = mouse_buttons.lock().await;
+            let report = { mouse.lock().await.movement(vx, vy) };
+            if let Some(report) = report {
                 let mut mw = mouse_writer.lock().await;
-                let _ = usb_send_mouse_report(&mut *mw, *b, vx, vy, 0).await;
+                usb_send_mouse_report(&mut *mw, report).await;
             }
         }
     };
 
     let in_fut = async {
+        let mut kbd_state = keyboard::NkroReport::default();
         loop {
             _ = Timer::after_millis(2).await;
             let switches =
@@ -267,49 +288,71 @@ async fn main(_spawner: Spawner) {
                 bit(0b00_00_00_01, p6.is_low()) | // index base
                 bit(0b00_00_00_10, p7.is_low());  // index tip
 
-            let outcome = cho.handle(SwitchSet(switches));
-            if outcome != Nothing {
-                log::info!("got: {outcome:?}");
-            }
-            match outcome {
-                Nothing => (),
-                KeyPress(k) => {
-                    let mask = mouse_mask_from_key_with_flags(k);
-                    let mut b = mouse_buttons.lock().await;
-                    *b |= mask;
-                    let mut mw = mouse_writer.lock().await;
-                    usb_send_mouse_report(&mut *mw, *b, 0, 0, 0).await;
-                },
-                KeyRelease(k) => {
-                    let mask = mouse_mask_from_key_with_flags(k);
-                    let mut b = mouse_buttons.lock().await;
-                    *b &= !mask;
-                    let mut mw = mouse_writer.lock().await;
-                    usb_send_mouse_report(&mut *mw, *b, 0, 0, 0).await;
-                },
-                KeyHit(key_with_flags) => {
-                    if key_with_flags & HACK_MOUSE_MARKER == HACK_MOUSE_MARKER {
-                        match key_with_flags {
-                            HACK_MOUSE_ENABLE_TOGGLE => {
-                                let mut m = mouse_enabled.lock().await;
-                                *m = !*m;
-                            }
-                            HACK_MOUSE_WHEEL_DOWN => {
-                                let b = mouse_buttons.lock().await;
-                                let mut mw = mouse_writer.lock().await;
-                                usb_send_mouse_report(&mut *mw, *b, 0, 0, -10).await;
-                            },
-                            HACK_MOUSE_WHEEL_UP => {
-                                let b = mouse_buttons.lock().await;
-                                let mut mw = mouse_writer.lock().await;
-                                usb_send_mouse_report(&mut *mw, *b, 0, 0, 10).await;
-                            },
-                            _ => (),
+            // Wrapping after ~49 days is fine for the engine.
+            let now = Instant::now().as_millis() as u32;
+            let mut outcome = cho.handle_at(SwitchSet(switches), now);
+            loop {
+                if outcome != Nothing {
+                    log::info!("got: {outcome:?}");
+                }
+                match outcome {
+                    Nothing => (),
+                    Error(_) => (), // a broken layout, logged above
+                    KeyPress(Action::Key(k)) => {
+                        // Keys held by the chord engine, e.g. unchorded keys,
+                        // or Alt while typing a Unicode character on Windows
+                        // or macOS.
+                        match kbd_state.press_with_flags(k) {
+                            Ok(()) => kbd_writers.write(&kbd_state).await,
+                            Err(err) => log::warn!("press {k:#06x}: {err}"),
+                        }
+                    },
+                    KeyRelease(Action::Key(k)) => {
+                        match kbd_state.release_with_flags(k) {
+                            Ok(()) => kbd_writers.write(&kbd_state).await,
+                            Err(err) => log::warn!("release {k:#06x}: {err}"),
                         }
-                    } else {
-                        usb_send_key_with_flags(&mut kbd_writer, key_with_flags).await;
+                    },
+                    KeyHit(Action::Key(key_with_flags)) => {
+                        usb_send_key_with_flags(&mut kbd_writers, key_with_flags, &kbd_state).await;
                     }
+                    KeyHit(Action::Mouse(_, flags)) | KeyPress(Action::Mouse(_, flags)) | KeyRelease(Action::Mouse(_, flags)) => {
+                        // The guard is dropped before any USB writes.
+                        let reports = { mouse.lock().await.handle(outcome) };
+                        // Modifiers of the action are held only for its
+                        // reports, e.g. for Ctrl+click.
+                        let mut with_flags = kbd_state;
+                        let _ = with_flags.press_with_flags(flags & new_keys::FLAG_MASK);
+                        let with_modifier = !reports.is_empty() && with_flags.modifier() != kbd_state.modifier();
+                        if with_modifier {
+                            kbd_writers.write(&with_flags).await;
+                        }
+                        let mut mw = mouse_writer.lock().await;
+                        for report in reports {
+                            usb_send_mouse_report(&mut *mw, report).await;
+                        }
+                        if with_modifier {
+                            kbd_writers.write(&kbd_state).await;
+                        }
+                    }
+                    KeyHit(Action::Media(usage)) => {
+                        usb_send_media(&mut media_writer, usage).await;
+                        usb_send_media(&mut media_writer, 0).await;
+                    }
+                    KeyPress(Action::Media(usage)) => usb_send_media(&mut media_writer, usage).await,
+                    KeyRelease(Action::Media(_)) => usb_send_media(&mut media_writer, 0).await,
+                    KeyHit(Action::System(usage)) => {
+                        usb_send_system(&mut system_writer, usage).await;
+                        usb_send_system(&mut system_writer, 0).await;
+                    }
+                    KeyPress(Action::System(usage)) => usb_send_system(&mut system_writer, usage).await,
+                    KeyRelease(Action::System(_)) => usb_send_system(&mut system_writer, 0).await,
                 }
+                // Some chords (e.g. typing text) result in a sequence of outcomes.
+                let Some(next) = cho.next_pending() else {
+                    break;
+                };
+                outcome = next;
             }
         }
     };
@@ -339,46 +382,44 @@ fn bit(mask: u8, apply: bool) -> u8 {
     if apply { mask } else { 0 }
 }
 
-async fn usb_send_key_with_flags<'d, D, const N: usize>(writer: &mut hid::HidWriter<'d, D, N>, k: new_keys::KeyWithFlags)
+/// Types `k`, keeping the `held` keys pressed all the time.
+async fn usb_send_key_with_flags<'d, D>(writers: &mut usb_kbd::Writers<'d, D>, k: new_keys::KeyWithFlags, held: &keyboard::NkroReport)
 where
       D: embassy_usb::driver::Driver<'d>,
 {
-    let bytes = k.to_be_bytes();
-    let modifier = bytes[0];
-    let key = bytes[1];
-    use usbd_hid::descriptor::KeyboardReport;
+    let mut report = *held;
+    // Can't fail without a key.
+    let _ = report.press_with_flags(k & new_keys::FLAG_MASK);
+    let with_modifier = report.modifier() != held.modifier();
 
-    if modifier != 0u8 {
+    if with_modifier {
         // press just the modifier first
-        let mut report = KeyboardReport::default();
-        report.modifier = modifier;
-        let _ = writer.write_serialize(&report).await;
+        writers.write(&report).await;
     }
 
-    // press...
-    let mut report = KeyboardReport::default();
-    report.modifier = modifier;
-    report.keycodes[0] = key;
-    let _ = writer.write_serialize(&report).await;
-
-    // ...and release the key
-    let empty_report = KeyboardReport::default();
-    report.modifier = modifier;
-    let _ = writer.write_serialize(&empty_report).await;
+    match report.press_with_flags(k & new_keys::KEY_MASK) {
+        Ok(()) => {
+            // press...
+            writers.write(&report).await;
+            // ...and release the key
+            let _ = report.release_with_flags(k & new_keys::KEY_MASK);
+            writers.write(&report).await;
+        }
+        Err(err) => log::warn!("type {k:#06x}: {err}"),
+    }
 
-    if modifier != 0u8 {
+    if with_modifier {
         // also release the modifier
-        let mut report = KeyboardReport::default();
-        let _ = writer.write_serialize(&report).await;
+        writers.write(held).await;
     }
 }
 
-async fn usb_send_mouse_report<'d, D, const N: usize>(writer: &mut hid::HidWriter<'d, D, N>, buttons: u8, x: i8, y: i8, wheel: i8)
+async fn usb_send_mouse_report<'d, D, const N: usize>(writer: &mut hid::HidWriter<'d, D, N>, report: MouseReport)
 where
       D: embassy_usb::driver::Driver<'d>,
 {
-    use usbd_hid::descriptor::MouseReport;
-    let report = MouseReport {
+    let MouseReport { buttons, x, y, wheel } = report;
+    let report = usbd_hid::descriptor::MouseReport {
         buttons,
         x,
         y,
@@ -388,16 +429,23 @@ where
     let _ = writer.write_serialize(&report).await;
 }
 
-fn mouse_mask_from_key_with_flags(k: new_keys::KeyWithFlags) -> u8 {
-    match k {
-        HACK_MOUSE_LEFT_BTN => MASK_MOUSE_BTN_LEFT,
-        HACK_MOUSE_RIGHT_BTN => MASK_MOUSE_BTN_RIGHT,
-        _ => 0u8,
-    }
+/// Sends the media key, or `0` for none; only one can be pressed at a time.
+async fn usb_send_media<'d, D, const N: usize>(writer: &mut hid::HidWriter<'d, D, N>, usage_id: u16)
+where
+      D: embassy_usb::driver::Driver<'d>,
+{
+    let report = hid_desc::MediaKeyboardReport { usage_id };
+    let _ = writer.write_serialize(&report).await;
 }
 
-const MASK_MOUSE_BTN_LEFT: u8 = 0x1;
-const MASK_MOUSE_BTN_RIGHT: u8 = 0x2;
+/// Sends the system control, or `0` for none.
+async fn usb_send_system<'d, D, const N: usize>(writer: &mut hid::HidWriter<'d, D, N>, usage_id: u8)
+where
+      D: embassy_usb::driver::Driver<'d>,
+{
+    let report = hid_desc::SystemControlReport { usage_id };
+    let _ = writer.write_serialize(&report).await;
+}
 
 // based on Vault Boy cross-stitch pattern by IFeel_Attacked (https://redd.it/rnt3ou)
 // copied and cropped manually, converted with https://javl.github.io/image2cpp/
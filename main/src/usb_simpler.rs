@@ -103,6 +103,15 @@ where
         hid::HidReaderWriter::new(&mut self.wrapped, &mut buf.state, cfg)
     }
 
+    /// For HID interfaces without output reports, e.g. media keys.
+    pub fn add_hid_writer<const WRITE_N: usize>(
+        &mut self,
+        buf: &'a mut buffers::ForHid<'a>,
+        cfg: hid::Config<'a>,
+    ) -> hid::HidWriter<'a, D, WRITE_N> {
+        hid::HidWriter::new(&mut self.wrapped, &mut buf.state, cfg)
+    }
+
     pub fn add_cdc_acm_class(&mut self, state: &'a mut cdc_acm::State<'a>, max_packet_size: u16) -> cdc_acm::CdcAcmClass<'a, D> {
         cdc_acm::CdcAcmClass::new(&mut self.wrapped, state, max_packet_size)
     }